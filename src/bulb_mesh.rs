@@ -0,0 +1,60 @@
+use ash::vk;
+use memoffset::offset_of;
+
+use crate::vulkan::Vertex;
+
+/// Small glowing lights, unaffected by any scene lights.
+#[derive(Debug)]
+pub struct BulbMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub instances: Vec<BulbInstanceData>,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BulbInstanceData {
+    pub position: [f32; 3],
+    pub radius: f32,
+    pub color: [f32; 3],
+    pub brightness: f32, // 0 is off, 1 is fully lit
+}
+
+impl BulbInstanceData {
+    pub fn get_binding_descriptions() -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription {
+            binding: 1,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::INSTANCE,
+        }]
+    }
+
+    pub fn get_attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription {
+                binding: 1,
+                location: 2,
+                format: vk::Format::R32G32B32_SFLOAT, // aka vec3
+                offset: offset_of!(Self, position) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 1,
+                location: 3,
+                format: vk::Format::R32_SFLOAT, // aka float
+                offset: offset_of!(Self, radius) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 1,
+                location: 4,
+                format: vk::Format::R32G32B32_SFLOAT, // aka vec3
+                offset: offset_of!(Self, color) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 1,
+                location: 5,
+                format: vk::Format::R32_SFLOAT, // aka float
+                offset: offset_of!(Self, brightness) as u32,
+            },
+        ]
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+/// Keeps frames from starting more often than `max_fps` times per second. They're scheduled on a
+/// fixed grid, not relative to the previous one, so that small delays don't add up to a lower
+/// frame rate.
+pub struct FramePacer {
+    max_fps: Option<u32>, // none means as many as the present mode allows
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    pub(crate) fn new(max_fps: Option<u32>) -> Self {
+        FramePacer {
+            max_fps,
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub(crate) fn max_fps(&self) -> Option<u32> {
+        self.max_fps
+    }
+
+    pub(crate) fn set_max_fps(&mut self, max_fps: Option<u32>) {
+        self.max_fps = max_fps;
+        self.next_frame = Instant::now();
+    }
+
+    /// When the next frame is due, none if it's due already.
+    pub(crate) fn wait_until(&self, now: Instant) -> Option<Instant> {
+        match self.max_fps {
+            Some(_) if now < self.next_frame => Some(self.next_frame),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn frame_started(&mut self, now: Instant) {
+        if let Some(max_fps) = self.max_fps {
+            let frame_duration = Duration::from_secs_f64(1. / max_fps as f64);
+            self.next_frame += frame_duration;
+            // more than a whole frame late, rushing to catch up would only make it worse
+            if self.next_frame < now {
+                self.next_frame = now + frame_duration;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::frame_pacer::FramePacer;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn pacer_at(start: Instant, max_fps: Option<u32>) -> FramePacer {
+        FramePacer {
+            max_fps,
+            next_frame: start,
+        }
+    }
+
+    #[test]
+    fn uncapped_frames_are_always_due() {
+        let start = Instant::now();
+        let mut pacer = pacer_at(start, None);
+        pacer.frame_started(start);
+        assert_eq!(pacer.wait_until(start), None);
+    }
+
+    #[test]
+    fn waits_until_next_frame_is_due() {
+        let start = Instant::now();
+        let mut pacer = pacer_at(start, Some(50));
+        pacer.frame_started(start);
+        assert_eq!(pacer.wait_until(start + ms(5)), Some(start + ms(20)));
+        assert_eq!(pacer.wait_until(start + ms(20)), None);
+    }
+
+    #[test]
+    fn slightly_late_frames_dont_shift_the_schedule() {
+        let start = Instant::now();
+        let mut pacer = pacer_at(start, Some(50));
+        pacer.frame_started(start);
+        pacer.frame_started(start + ms(23));
+        assert_eq!(pacer.wait_until(start + ms(30)), Some(start + ms(40)));
+    }
+
+    #[test]
+    fn very_late_frames_start_a_new_schedule() {
+        let start = Instant::now();
+        let mut pacer = pacer_at(start, Some(50));
+        pacer.frame_started(start);
+        pacer.frame_started(start + ms(100));
+        assert_eq!(pacer.wait_until(start + ms(110)), Some(start + ms(120)));
+    }
+}
@@ -0,0 +1,31 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Writes whatever is at most as severe as the max level to stderr.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!(
+                "[{}][{}] {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Before anything gets logged, Vulkan picks the validation messages it reports based on it.
+pub fn init(max_level: LevelFilter) {
+    log::set_logger(&LOGGER).expect("Logger set up twice!");
+    log::set_max_level(max_level);
+}
@@ -1,42 +1,195 @@
 #![windows_subsystem = "windows"]
 
+use std::cell::Cell;
 use std::f32::consts::{FRAC_PI_8, TAU};
-use std::thread;
-use std::time::{Duration, Instant};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::{process, thread};
 
 use crate::fps_calculator::FpsCalculator;
+use crate::frame_pacer::FramePacer;
+use crate::options::{Options, USAGE};
+use crate::recorder::FrameRecorder;
 use crate::scene::Scene;
+use image::RgbaImage;
+use vulkan::error::{VulkanError, VulkanResult};
+use vulkan::gpu::{self, GpuSelection};
 use vulkan::Vulkan;
 use winit::dpi::{PhysicalPosition, PhysicalSize};
 use winit::event::ElementState::Pressed;
 use winit::event::{Event, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
-use winit::event_loop::EventLoop;
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
 use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::Window;
 
 mod vulkan;
 
+mod bulb_mesh;
 mod color_mesh;
 mod coords;
 mod fps_calculator;
+mod frame_pacer;
+mod logger;
+mod options;
+mod pbr_mesh;
+mod recorder;
 mod scene;
+mod skybox;
 mod textured_mesh;
 
-const AUTO_ROTATION_SPEED_RAD_PER_SEC: f32 = TAU / 30.0;
+pub const AUTO_ROTATION_SPEED_RAD_PER_SEC: f32 = TAU / 30.0;
 
-const MAX_FPS: u8 = 60;
+// when turning the frame rate limit on without any given
+const DEFAULT_MAX_FPS: u32 = 60;
 
 const APPLICATION_NAME: &'static str = "Vulkan Christmas Tree";
 
+// picks the GPU when there's no --gpu
+const GPU_ENV_VAR: &str = "CHRISTMAS_TREE_GPU";
+
+const DEFAULT_WINDOW_SIZE: PhysicalSize<u32> = PhysicalSize::new(1600, 900);
+
 fn main() {
+    let mut options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|message| {
+        eprintln!("{}\n\n{}", message, USAGE);
+        process::exit(2);
+    });
+    if options.help {
+        println!("{}", USAGE);
+        return;
+    }
+    logger::init(options.log_level);
+    if options.list_gpus {
+        let gpus = Vulkan::list_gpus(APPLICATION_NAME).unwrap_or_else(|error| exit_with(error));
+        for (index, gpu) in gpus.iter().enumerate() {
+            let suitable = if gpu.suitable { "" } else { ", not suitable" };
+            println!("{}: {}{}", index, gpu, suitable);
+        }
+        return;
+    }
+    if options.gpu.is_none() {
+        options.gpu = std::env::var(GPU_ENV_VAR)
+            .ok()
+            .and_then(|gpu| GpuSelection::parse(&gpu));
+    }
+    let mut recorder = options.record.as_ref().map(|directory| {
+        FrameRecorder::new(
+            directory.clone(),
+            options.frames,
+            options.fps,
+            options.rotate,
+        )
+        .unwrap_or_else(|message| {
+            eprintln!("{}", message);
+            process::exit(1);
+        })
+    });
+    if options.headless {
+        let frame =
+            render_headless(&options, recorder.as_mut()).unwrap_or_else(|error| exit_with(error));
+        let saved = match recorder {
+            Some(recorder) => recorder.finish(),
+            None => save_frame(frame, &options.output),
+        };
+        saved.unwrap_or_else(|message| {
+            eprintln!("{}", message);
+            process::exit(1);
+        });
+        exit_on_validation_errors(options.fail_on_validation_errors);
+        return;
+    }
+
     let event_loop = EventLoop::new().unwrap();
-    let window = init_window(&event_loop);
-    let mut vulkan = Vulkan::new(&window, APPLICATION_NAME);
-    let scene = Scene::setup(&mut vulkan, &window);
-    main_loop(vulkan, window, scene, event_loop);
+    let window = init_window(&event_loop, options.size);
+    let (vulkan, scene) = setup(&window, &options).unwrap_or_else(|error| exit_with(error));
+    main_loop(vulkan, window, scene, recorder, options.max_fps, event_loop);
+    exit_on_validation_errors(options.fail_on_validation_errors);
+}
+
+fn exit_with(error: VulkanError) -> ! {
+    eprintln!("{}", error);
+    process::exit(1);
+}
+
+/// Once everything Vulkan is gone, so that errors when destroying it count too.
+fn exit_on_validation_errors(fail_on_validation_errors: bool) {
+    let errors = vulkan::debug::validation_errors();
+    if fail_on_validation_errors && errors > 0 {
+        eprintln!("Validation layers reported {} errors", errors);
+        process::exit(1);
+    }
+}
+
+/// When the GPU itself is the problem, the other suitable ones get tried, one by one.
+fn with_fallback_gpus(
+    gpu_selection: Option<&GpuSelection>,
+    create: impl Fn(Option<&GpuSelection>) -> VulkanResult<Vulkan>,
+) -> VulkanResult<Vulkan> {
+    let error = match create(gpu_selection) {
+        Err(error) if error.is_gpu_specific() => error,
+        result => return result,
+    };
+    let gpus = Vulkan::list_gpus(APPLICATION_NAME)?;
+    for index in gpu::fallbacks(&gpus, gpu_selection) {
+        log::warn!("{}, trying GPU {} instead", error, index);
+        if let Ok(vulkan) = create(Some(&GpuSelection::Index(index))) {
+            return Ok(vulkan);
+        }
+    }
+    Err(error)
+}
+
+fn setup(window: &Window, options: &Options) -> VulkanResult<(Vulkan, Scene)> {
+    let mut vulkan = with_fallback_gpus(options.gpu.as_ref(), |gpu_selection| {
+        Vulkan::new(window, APPLICATION_NAME, gpu_selection)
+    })?;
+    if let Some(samples) = options.msaa_samples {
+        vulkan.set_msaa_samples(samples)?;
+    }
+    vulkan.set_present_mode(options.present_mode)?;
+    let scene = Scene::setup(&mut vulkan, window.inner_size(), options.blink_pattern)?;
+    Ok((vulkan, scene))
+}
+
+/// Returns the single frame rendered when not recording, none if it couldn't be captured.
+fn render_headless(
+    options: &Options,
+    recorder: Option<&mut FrameRecorder>,
+) -> VulkanResult<Option<RgbaImage>> {
+    let size = options.size.unwrap_or(DEFAULT_WINDOW_SIZE);
+    let mut vulkan = with_fallback_gpus(options.gpu.as_ref(), |gpu_selection| {
+        Vulkan::new_headless(size.width, size.height, APPLICATION_NAME, gpu_selection)
+    })?;
+    if let Some(samples) = options.msaa_samples {
+        vulkan.set_msaa_samples(samples)?;
+    }
+    let mut scene = Scene::setup(&mut vulkan, size, options.blink_pattern)?;
+    let frame = match recorder {
+        Some(recorder) => {
+            while !recorder.is_done() {
+                recorder.record_frame(&mut vulkan, &mut scene)?;
+            }
+            None
+        }
+        None => {
+            vulkan.capture_next_frame()?;
+            vulkan.draw_frame(0.0)?;
+            vulkan.take_captured_frame()
+        }
+    };
+    vulkan.wait_device_idle()?;
+    Ok(frame)
 }
 
-fn init_window(event_loop: &EventLoop<()>) -> Window {
+fn save_frame(frame: Option<RgbaImage>, path: &Path) -> Result<(), String> {
+    frame
+        .ok_or_else(|| "Failed to capture frame".to_string())?
+        .save(path)
+        .map_err(|error| format!("Failed to save {}: {}", path.display(), error))
+}
+
+fn init_window(event_loop: &EventLoop<()>, size: Option<PhysicalSize<u32>>) -> Window {
     let window = event_loop
         .create_window(
             Window::default_attributes()
@@ -48,28 +201,40 @@ fn init_window(event_loop: &EventLoop<()>) -> Window {
         .current_monitor()
         .or(window.primary_monitor())
         .or(window.available_monitors().next());
-    let screen_size = monitor
-        .map(|monitor| monitor.size())
-        .map(|size| PhysicalSize::new(size.width / 2, size.height / 2))
-        .unwrap_or(PhysicalSize::new(1600, 900));
+    let screen_size = size.unwrap_or(
+        monitor
+            .map(|monitor| monitor.size())
+            .map(|size| PhysicalSize::new(size.width / 2, size.height / 2))
+            .unwrap_or(DEFAULT_WINDOW_SIZE),
+    );
     window.request_inner_size(screen_size).unwrap();
 
     window
 }
 
-fn main_loop(mut vulkan: Vulkan, window: Window, mut scene: Scene, event_loop: EventLoop<()>) {
+fn main_loop(
+    mut vulkan: Vulkan,
+    window: Window,
+    mut scene: Scene,
+    mut recorder: Option<FrameRecorder>,
+    max_fps: Option<u32>,
+    event_loop: EventLoop<()>,
+) {
     let mut fps_calculator = FpsCalculator::new();
+    let mut frame_pacer = FramePacer::new(max_fps);
     let mut autorotate = false;
     let mut mouse_rotating = false;
     let mut last_cursor_position: PhysicalPosition<f64> = PhysicalPosition::new(0.0, 0.0);
-    let desired_frame_duration = Duration::from_secs_f32(1.0 / MAX_FPS as f32);
-    event_loop
-        .run(move |event, elwt| match event {
+    // anything failing ends the loop, the error shown once it's over
+    let failed = Rc::new(Cell::new(false));
+    let recording_failed = failed.clone();
+    let mut handle_event = move |event, elwt: &ActiveEventLoop| -> VulkanResult<()> {
+        match event {
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
             } => {
-                vulkan.wait_device_idle();
+                vulkan.wait_device_idle()?;
                 elwt.exit();
             }
             Event::WindowEvent {
@@ -86,7 +251,7 @@ fn main_loop(mut vulkan: Vulkan, window: Window, mut scene: Scene, event_loop: E
                 ..
             } => match code {
                 KeyCode::Escape => {
-                    vulkan.wait_device_idle();
+                    vulkan.wait_device_idle()?;
                     elwt.exit();
                 }
                 KeyCode::ArrowUp => {
@@ -112,6 +277,40 @@ fn main_loop(mut vulkan: Vulkan, window: Window, mut scene: Scene, event_loop: E
                 KeyCode::KeyR => {
                     autorotate = !autorotate;
                 }
+                KeyCode::KeyL => {
+                    scene.next_blink_pattern();
+                }
+                KeyCode::KeyM => match vulkan.next_msaa_samples()? {
+                    1 => println!("Anti-aliasing off"),
+                    samples => println!("Anti-aliasing with {}x MSAA", samples),
+                },
+                KeyCode::KeyV => {
+                    let present_mode = vulkan.next_present_mode()?;
+                    println!("Vsync {}", present_mode.name());
+                }
+                KeyCode::KeyF => {
+                    let max_fps = match frame_pacer.max_fps() {
+                        Some(_) => None,
+                        None => Some(max_fps.unwrap_or(DEFAULT_MAX_FPS)),
+                    };
+                    frame_pacer.set_max_fps(max_fps);
+                    match max_fps {
+                        Some(max_fps) => println!("Frame rate limited to {} FPS", max_fps),
+                        None => println!("Frame rate not limited"),
+                    }
+                }
+                KeyCode::KeyG => {
+                    for heap_stats in vulkan.memory_stats() {
+                        println!("{}", heap_stats);
+                    }
+                }
+                KeyCode::F12 => match vulkan.capture_next_frame() {
+                    // not worth stopping for
+                    Err(error @ VulkanError::Unsupported(_)) => {
+                        log::warn!("{}, no screenshot taken", error)
+                    }
+                    result => result?,
+                },
                 _ => (),
             },
             Event::WindowEvent {
@@ -153,18 +352,38 @@ fn main_loop(mut vulkan: Vulkan, window: Window, mut scene: Scene, event_loop: E
                 event: WindowEvent::Resized(new_size),
                 ..
             } => {
-                vulkan.wait_device_idle();
+                vulkan.wait_device_idle()?;
                 scene.framebuffer_resized(new_size, &mut vulkan);
                 vulkan.framebuffer_resized(new_size.width, new_size.height);
             }
             Event::AboutToWait => {
-                window.request_redraw();
+                // recording doesn't need to keep up with the clock
+                let wait_until = match recorder {
+                    Some(_) => None,
+                    None => frame_pacer.wait_until(Instant::now()),
+                };
+                match wait_until {
+                    Some(instant) => elwt.set_control_flow(ControlFlow::WaitUntil(instant)),
+                    None => {
+                        elwt.set_control_flow(ControlFlow::Wait);
+                        window.request_redraw();
+                    }
+                }
             }
             Event::WindowEvent {
                 event: WindowEvent::RedrawRequested,
                 ..
             } => {
-                let frame_start = Instant::now();
+                if let Some(recorder) = recorder.as_mut() {
+                    // recording uses its own fixed time step, no need to keep up with the clock
+                    recorder.record_frame(&mut vulkan, &mut scene)?;
+                    if recorder.is_done() {
+                        vulkan.wait_device_idle()?;
+                        elwt.exit();
+                    }
+                    return Ok(());
+                }
+                frame_pacer.frame_started(Instant::now());
                 fps_calculator.tick();
                 let last_frame_time_secs = fps_calculator.last_frame_time_secs();
                 if autorotate {
@@ -173,17 +392,51 @@ fn main_loop(mut vulkan: Vulkan, window: Window, mut scene: Scene, event_loop: E
                         &mut vulkan,
                     );
                 }
-                vulkan.draw_frame(last_frame_time_secs);
-                let frame_end = Instant::now();
-                let actual_frame_duration = frame_end - frame_start;
-                if actual_frame_duration < desired_frame_duration {
-                    thread::sleep(desired_frame_duration - actual_frame_duration);
+                scene.animate(last_frame_time_secs, &mut vulkan);
+                #[cfg(feature = "hot-reload")]
+                vulkan.reload_changed_shaders()?;
+                vulkan.draw_frame(last_frame_time_secs)?;
+                if let Some(frame) = vulkan.take_captured_frame() {
+                    save_screenshot(frame);
                 }
             }
             Event::LoopExiting => {
-                vulkan.wait_device_idle();
+                if let Some(recorder) = recorder.take() {
+                    if let Err(message) = recorder.finish() {
+                        eprintln!("{}", message);
+                        recording_failed.set(true);
+                    }
+                }
+                vulkan.wait_device_idle()?;
             }
             _ => (),
+        }
+        Ok(())
+    };
+    let loop_failed = failed.clone();
+    event_loop
+        .run(move |event, elwt| {
+            if let Err(error) = handle_event(event, elwt) {
+                eprintln!("{}", error);
+                loop_failed.set(true);
+                elwt.exit();
+            }
         })
         .unwrap();
+    if failed.get() {
+        process::exit(1);
+    }
+}
+
+fn save_screenshot(frame: RgbaImage) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = format!("screenshot-{}.png", timestamp);
+    // encoding takes a while, don't stall the rendering
+    thread::spawn(move || match frame.save(&path) {
+        Ok(_) => println!("Screenshot saved to {}", path),
+        Err(error) => log::error!("Failed to save screenshot to {}: {}", path, error),
+    });
 }
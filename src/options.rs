@@ -0,0 +1,276 @@
+use std::path::PathBuf;
+
+use log::LevelFilter;
+use winit::dpi::PhysicalSize;
+
+use crate::scene::fairy_lights::BlinkPattern;
+use crate::vulkan::gpu::GpuSelection;
+use crate::vulkan::present_mode::PresentMode;
+
+pub const USAGE: &str = "\
+Usage: vulkan-christmas-tree [OPTIONS]
+
+Options:
+  --headless          render a single frame without any window and save it as PNG
+  --output <FILE>     where to save the headless frame [default: frame.png]
+  --size <WIDTHxHEIGHT>
+                      window or offscreen image size in pixels
+  --record <DIR>      render frames with a fixed time step and save them as DIR/frame_NNNNN.png
+  --frames <N>        how many frames to record [default: 300]
+  --fps <N>           frame rate of the recording [default: 30]
+  --rotate            turn the camera around the tree while recording
+  --lights <PATTERN>  how the fairy lights blink: chase, twinkle or fade [default: chase]
+  --msaa <N>          samples per pixel for anti-aliasing: 1 (off), 2, 4 or 8, or as many as
+                      the GPU supports if less [default: 4]
+  --vsync <MODE>      on, adaptive (tears only late frames), mailbox or off [default: on]
+  --max-fps <N>       don't render more frames per second than that [default: no limit]
+  --gpu <GPU>         which GPU to use: its index from --list-gpus, part of its name, or its
+                      type: discrete, integrated, virtual or cpu [default: $CHRISTMAS_TREE_GPU,
+                      or the first discrete one]
+  --list-gpus         print all GPUs and exit
+  --log-level <LEVEL> what to log: off, error, warn, info, debug or trace [default: warn]
+  --fail-on-validation-errors
+                      exit with an error once done if the validation layers reported any
+                      (needs the validation-layers feature)
+  --help              print this help";
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub headless: bool,
+    pub output: PathBuf,
+    pub size: Option<PhysicalSize<u32>>,
+    pub record: Option<PathBuf>,
+    pub frames: u32,
+    pub fps: u32,
+    pub rotate: bool,
+    pub blink_pattern: BlinkPattern,
+    pub msaa_samples: Option<u32>,
+    pub present_mode: PresentMode,
+    pub max_fps: Option<u32>,
+    pub gpu: Option<GpuSelection>,
+    pub list_gpus: bool,
+    pub log_level: LevelFilter,
+    pub fail_on_validation_errors: bool,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            headless: false,
+            output: PathBuf::from("frame.png"),
+            size: None,
+            record: None,
+            frames: 300,
+            fps: 30,
+            rotate: false,
+            blink_pattern: BlinkPattern::default(),
+            msaa_samples: None,
+            present_mode: PresentMode::default(),
+            max_fps: None,
+            gpu: None,
+            list_gpus: false,
+            log_level: LevelFilter::Warn,
+            fail_on_validation_errors: false,
+            help: false,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => options.headless = true,
+                "--output" => options.output = PathBuf::from(value_of(&arg, args.next())?),
+                "--size" => options.size = Some(parse_size(&value_of(&arg, args.next())?)?),
+                "--record" => options.record = Some(PathBuf::from(value_of(&arg, args.next())?)),
+                "--frames" => options.frames = parse_positive(&arg, &value_of(&arg, args.next())?)?,
+                "--fps" => options.fps = parse_positive(&arg, &value_of(&arg, args.next())?)?,
+                "--rotate" => options.rotate = true,
+                "--lights" => {
+                    let pattern = value_of(&arg, args.next())?;
+                    options.blink_pattern = BlinkPattern::parse(&pattern)
+                        .ok_or(format!("Invalid value for {}: {}", arg, pattern))?
+                }
+                "--msaa" => {
+                    options.msaa_samples = Some(parse_msaa_samples(&value_of(&arg, args.next())?)?)
+                }
+                "--vsync" => {
+                    let mode = value_of(&arg, args.next())?;
+                    options.present_mode = PresentMode::parse(&mode)
+                        .ok_or(format!("Invalid value for {}: {}", arg, mode))?
+                }
+                "--max-fps" => {
+                    options.max_fps = Some(parse_positive(&arg, &value_of(&arg, args.next())?)?)
+                }
+                "--gpu" => {
+                    let gpu = value_of(&arg, args.next())?;
+                    options.gpu = Some(
+                        GpuSelection::parse(&gpu)
+                            .ok_or(format!("Invalid value for {}: {}", arg, gpu))?,
+                    )
+                }
+                "--list-gpus" => options.list_gpus = true,
+                "--log-level" => {
+                    let level = value_of(&arg, args.next())?;
+                    options.log_level = level
+                        .parse()
+                        .map_err(|_| format!("Invalid value for {}: {}", arg, level))?
+                }
+                "--fail-on-validation-errors" => options.fail_on_validation_errors = true,
+                "--help" | "-h" => options.help = true,
+                _ => return Err(format!("Unknown option: {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn value_of(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or(format!("Missing value for {}", option))
+}
+
+fn parse_positive(option: &str, value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(format!("Invalid value for {}: {}", option, value)),
+    }
+}
+
+fn parse_msaa_samples(samples: &str) -> Result<u32, String> {
+    match samples.parse() {
+        Ok(samples @ (1 | 2 | 4 | 8)) => Ok(samples),
+        _ => Err(format!(
+            "Invalid value for --msaa: {}, expected 1, 2, 4 or 8",
+            samples
+        )),
+    }
+}
+
+fn parse_size(size: &str) -> Result<PhysicalSize<u32>, String> {
+    let invalid = || format!("Invalid size: {}, expected WIDTHxHEIGHT", size);
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.parse().map_err(|_| invalid())?;
+    let height: u32 = height.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok(PhysicalSize::new(width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use log::LevelFilter;
+    use rstest::*;
+    use winit::dpi::PhysicalSize;
+
+    use crate::options::Options;
+    use crate::scene::fairy_lights::BlinkPattern;
+    use crate::vulkan::gpu::GpuSelection;
+    use crate::vulkan::present_mode::PresentMode;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn no_args_mean_defaults() {
+        assert_eq!(Options::parse(args(&[])), Ok(Options::default()));
+    }
+
+    #[test]
+    fn headless_with_output_and_size() {
+        let options = Options::parse(args(&[
+            "--headless",
+            "--output",
+            "tree.png",
+            "--size",
+            "640x480",
+        ]))
+        .unwrap();
+        assert!(options.headless);
+        assert_eq!(options.output, PathBuf::from("tree.png"));
+        assert_eq!(options.size, Some(PhysicalSize::new(640, 480)));
+    }
+
+    #[test]
+    fn recording() {
+        let options = Options::parse(args(&[
+            "--record", "frames", "--frames", "60", "--fps", "24", "--rotate",
+        ]))
+        .unwrap();
+        assert_eq!(options.record, Some(PathBuf::from("frames")));
+        assert_eq!(options.frames, 60);
+        assert_eq!(options.fps, 24);
+        assert!(options.rotate);
+    }
+
+    #[test]
+    fn fairy_lights_pattern() {
+        let options = Options::parse(args(&["--lights", "twinkle"])).unwrap();
+        assert_eq!(options.blink_pattern, BlinkPattern::Twinkle);
+    }
+
+    #[test]
+    fn vsync_and_frame_cap() {
+        let options = Options::parse(args(&["--vsync", "adaptive", "--max-fps", "144"])).unwrap();
+        assert_eq!(options.present_mode, PresentMode::FifoRelaxed);
+        assert_eq!(options.max_fps, Some(144));
+    }
+
+    #[rstest(given, expected,
+    case(& ["--gpu", "1"], GpuSelection::Index(1)),
+    case(& ["--gpu", "GeForce"], GpuSelection::Name("geforce".to_string())),
+    )]
+    fn gpu_selection(given: &[&str], expected: GpuSelection) {
+        let options = Options::parse(args(given)).unwrap();
+        assert_eq!(options.gpu, Some(expected));
+    }
+
+    #[test]
+    fn logging_and_validation() {
+        let options = Options::parse(args(&[
+            "--log-level",
+            "debug",
+            "--fail-on-validation-errors",
+        ]))
+        .unwrap();
+        assert_eq!(options.log_level, LevelFilter::Debug);
+        assert!(options.fail_on_validation_errors);
+    }
+
+    #[rstest(given, expected,
+    case(& ["--msaa", "1"], 1),
+    case(& ["--msaa", "8"], 8),
+    )]
+    fn msaa_samples(given: &[&str], expected: u32) {
+        let options = Options::parse(args(given)).unwrap();
+        assert_eq!(options.msaa_samples, Some(expected));
+    }
+
+    #[rstest(given,
+    case(& ["--size"]),
+    case(& ["--size", "640"]),
+    case(& ["--size", "0x480"]),
+    case(& ["--size", "axb"]),
+    case(& ["--output"]),
+    case(& ["--frames", "0"]),
+    case(& ["--fps", "-30"]),
+    case(& ["--lights", "disco"]),
+    case(& ["--msaa", "3"]),
+    case(& ["--msaa", "16"]),
+    case(& ["--vsync", "sometimes"]),
+    case(& ["--max-fps", "0"]),
+    case(& ["--gpu", ""]),
+    case(& ["--log-level", "loud"]),
+    case(& ["--fullscreen"]),
+    )]
+    fn invalid_args_are_rejected(given: &[&str]) {
+        assert!(Options::parse(args(given)).is_err());
+    }
+}
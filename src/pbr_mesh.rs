@@ -0,0 +1,166 @@
+use ash::vk;
+use cgmath::{Matrix4, SquareMatrix};
+use memoffset::offset_of;
+
+use crate::color_mesh::Color;
+use crate::vulkan::Vertex;
+
+// Phong materials with weaker highlights than that are plastic, with stronger ones are metal
+const DIELECTRIC_SPECULAR: f32 = 0.1;
+const METAL_SPECULAR: f32 = 0.5;
+const MIN_ROUGHNESS: f32 = 0.05; // perfect mirrors have no highlights at all
+
+/// Meshes shaded physically, with Cook-Torrance.
+#[derive(Debug)]
+pub struct PbrMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub instances: Vec<PbrInstanceData>,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PbrInstanceData {
+    pub model: Matrix4<f32>,
+    pub material: Material,
+}
+
+impl PbrInstanceData {
+    pub fn get_binding_descriptions() -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription {
+            binding: 1,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::INSTANCE,
+        }]
+    }
+
+    pub fn get_attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        let matrix_quarter = (std::mem::size_of::<Matrix4<f32>>() / 4) as u32;
+        vec![
+            vk::VertexInputAttributeDescription {
+                binding: 1,
+                location: 2,
+                format: vk::Format::R32G32B32_SFLOAT, // aka vec3
+                offset: (offset_of!(Self, material) + offset_of!(Material, base_color)) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 1,
+                location: 3,
+                format: vk::Format::R32_SFLOAT, // aka float
+                offset: (offset_of!(Self, material) + offset_of!(Material, metallic)) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 1,
+                location: 4,
+                format: vk::Format::R32_SFLOAT, // aka float
+                offset: (offset_of!(Self, material) + offset_of!(Material, roughness)) as u32,
+            },
+            // model stays at the same locations as in color meshes, shadow.vert expects it there
+            vk::VertexInputAttributeDescription {
+                binding: 1,
+                location: 6,
+                format: vk::Format::R32G32B32A32_SFLOAT, // aka vec4
+                offset: offset_of!(Self, model) as u32 + 0 * matrix_quarter,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 1,
+                location: 7,
+                format: vk::Format::R32G32B32A32_SFLOAT, // aka vec4
+                offset: offset_of!(Self, model) as u32 + 1 * matrix_quarter,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 1,
+                location: 8,
+                format: vk::Format::R32G32B32A32_SFLOAT, // aka vec4
+                offset: offset_of!(Self, model) as u32 + 2 * matrix_quarter,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 1,
+                location: 9,
+                format: vk::Format::R32G32B32A32_SFLOAT, // aka vec4
+                offset: offset_of!(Self, model) as u32 + 3 * matrix_quarter,
+            },
+        ]
+    }
+}
+
+impl Default for PbrInstanceData {
+    fn default() -> Self {
+        Self {
+            model: Matrix4::identity(),
+            material: Material::default(),
+        }
+    }
+}
+
+/// Metallic-roughness material, as in glTF.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Material {
+    pub base_color: [f32; 3], // albedo for dielectrics, reflectance for metals
+    pub metallic: f32,        // 0 is plastic, 1 is metal
+    pub roughness: f32,       // 0 is a mirror, 1 is chalk
+}
+
+impl From<Color> for Material {
+    /// Best guess only, Phong materials don't map onto physical ones exactly.
+    fn from(color: Color) -> Self {
+        let specular = color.specular.iter().sum::<f32>() / 3.;
+        let metallic = ((specular - DIELECTRIC_SPECULAR) / (METAL_SPECULAR - DIELECTRIC_SPECULAR))
+            .clamp(0., 1.);
+        // Blinn-Phong exponent to GGX alpha, which is roughness squared
+        let alpha = (2. / (color.shininess + 2.)).sqrt();
+        Material {
+            base_color: color.diffuse,
+            metallic,
+            roughness: alpha.sqrt().clamp(MIN_ROUGHNESS, 1.),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use crate::color_mesh::Color;
+    use crate::pbr_mesh::Material;
+
+    fn color(specular: f32, shininess: f32) -> Color {
+        Color {
+            ambient: [0.1, 0.1, 0.1],
+            diffuse: [0.6, 0.05, 0.05],
+            specular: [specular; 3],
+            shininess,
+        }
+    }
+
+    #[rstest(
+        specular,
+        metallic,
+        case(0.05, 0.),
+        case(0.1, 0.),
+        case(0.3, 0.5),
+        case(0.5, 1.),
+        case(0.73, 1.)
+    )]
+    fn strong_highlights_mean_metal(specular: f32, metallic: f32) {
+        let material = Material::from(color(specular, 76.8));
+        assert!(
+            (material.metallic - metallic).abs() < 0.001,
+            "{:?}",
+            material
+        );
+        assert_eq!(material.base_color, [0.6, 0.05, 0.05]);
+    }
+
+    #[test]
+    fn higher_shininess_means_smoother() {
+        let mut previous = Material::from(color(0.5, 0.)).roughness;
+        assert_eq!(previous, 1.);
+        for shininess in [1., 10., 76.8, 225., 1000., 1_000_000.] {
+            let roughness = Material::from(color(0.5, shininess)).roughness;
+            assert!(roughness <= previous && roughness > 0., "{}", roughness);
+            previous = roughness;
+        }
+    }
+}
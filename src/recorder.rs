@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::thread;
+use std::thread::JoinHandle;
+
+use image::RgbaImage;
+
+use crate::scene::Scene;
+use crate::vulkan::error::VulkanResult;
+use crate::vulkan::Vulkan;
+use crate::AUTO_ROTATION_SPEED_RAD_PER_SEC;
+
+// how many captured frames may wait for being written before rendering blocks
+const WRITE_QUEUE_SIZE: usize = 8;
+
+/// Renders a fixed number of frames with a fixed time step, independent of how fast the machine is,
+/// and writes them as numbered PNG images, ready to be put together e.g. by ffmpeg.
+pub struct FrameRecorder {
+    directory: PathBuf,
+    frame_time_secs: f32,
+    frames_total: u32,
+    frames_recorded: u32,
+    rotate: bool,
+
+    sender: SyncSender<(PathBuf, RgbaImage)>,
+    writer: JoinHandle<Result<(), String>>,
+    writer_failed: bool, // `finish` tells why
+}
+
+impl FrameRecorder {
+    pub fn new(
+        directory: PathBuf,
+        frames_total: u32,
+        fps: u32,
+        rotate: bool,
+    ) -> Result<Self, String> {
+        std::fs::create_dir_all(&directory).map_err(|error| {
+            format!(
+                "Failed to create recording directory {}: {}",
+                directory.display(),
+                error
+            )
+        })?;
+        let (sender, receiver) = sync_channel::<(PathBuf, RgbaImage)>(WRITE_QUEUE_SIZE);
+        // encoding PNGs is way slower than rendering, don't do it on the rendering thread
+        let writer = thread::spawn(move || {
+            for (path, frame) in receiver {
+                frame
+                    .save(&path)
+                    .map_err(|error| format!("Failed to save {}: {}", path.display(), error))?;
+            }
+            Ok(())
+        });
+        Ok(FrameRecorder {
+            directory,
+            frame_time_secs: 1.0 / fps as f32,
+            frames_total,
+            frames_recorded: 0,
+            rotate,
+
+            sender,
+            writer,
+            writer_failed: false,
+        })
+    }
+
+    /// Also once frames can't be written any more.
+    pub fn is_done(&self) -> bool {
+        self.writer_failed || self.frames_recorded >= self.frames_total
+    }
+
+    /// Fails if frames can't be captured at all, instead of waiting for them forever.
+    pub fn record_frame(&mut self, vulkan: &mut Vulkan, scene: &mut Scene) -> VulkanResult<()> {
+        vulkan.capture_next_frame()?;
+        vulkan.draw_frame(self.frame_time_secs)?;
+        // the frame might have been skipped, e.g. because the swapchain had to be recreated
+        if let Some(frame) = vulkan.take_captured_frame() {
+            let path = self
+                .directory
+                .join(format!("frame_{:05}.png", self.frames_recorded));
+            if self.sender.send((path, frame)).is_err() {
+                self.writer_failed = true;
+                return Ok(());
+            }
+            self.frames_recorded += 1;
+            // only once the frame is in, so that skipped frames don't make the lights run ahead
+            scene.animate(self.frame_time_secs, vulkan);
+            if self.rotate {
+                scene.rotate_camera_horizontally(
+                    AUTO_ROTATION_SPEED_RAD_PER_SEC * self.frame_time_secs,
+                    vulkan,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits until all recorded frames are written.
+    pub fn finish(self) -> Result<(), String> {
+        drop(self.sender);
+        self.writer.join().expect("Frame writer panicked!")?;
+        println!(
+            "Recorded {} frames into {}",
+            self.frames_recorded,
+            self.directory.display()
+        );
+        Ok(())
+    }
+}
@@ -2,8 +2,9 @@ use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, FRAC_PI_6, FRAC_PI_8, PI};
 
 use cgmath::{vec3, EuclideanSpace, Matrix4, Point3};
 
-use crate::color_mesh::{Color, ColorMesh, InstanceData};
+use crate::color_mesh::Color;
 use crate::coords::CylindricalPoint3;
+use crate::pbr_mesh::{PbrInstanceData, PbrMesh};
 use crate::vulkan::{Vertex, VertexIndexType};
 
 const PRECISION: VertexIndexType = 16;
@@ -14,8 +15,8 @@ struct Bauble {
     color: Color,
 }
 
-pub fn create_meshes() -> Vec<ColorMesh> {
-    let (vertices, indices) = gen_sphere();
+pub fn create_meshes() -> Vec<PbrMesh> {
+    let (vertices, indices) = gen_sphere(RADIUS);
 
     let red = Color {
         ambient: [0.1745, 0.01175, 0.01175],
@@ -171,37 +172,38 @@ pub fn create_meshes() -> Vec<ColorMesh> {
         },
     ];
 
-    let instances: Vec<InstanceData> = baubles
+    let instances: Vec<PbrInstanceData> = baubles
         .into_iter()
         .map(|b| {
             let point: Point3<f32> = b.center.into();
-            InstanceData {
-                color: b.color,
+            // their strong highlights make them metallic
+            PbrInstanceData {
+                material: b.color.into(),
                 model: Matrix4::from_translation(point.to_vec()).into(),
                 ..Default::default()
             }
         })
         .collect();
 
-    vec![ColorMesh {
+    vec![PbrMesh {
         vertices,
         indices,
         instances,
     }]
 }
 
-fn gen_sphere() -> (Vec<Vertex>, Vec<VertexIndexType>) {
-    let vertices = gen_vertices();
+pub(crate) fn gen_sphere(radius: f32) -> (Vec<Vertex>, Vec<VertexIndexType>) {
+    let vertices = gen_vertices(radius);
     let indices = gen_indices();
     (vertices, indices)
 }
 
-fn gen_vertices() -> Vec<Vertex> {
+fn gen_vertices(radius: f32) -> Vec<Vertex> {
     let mut vertices: Vec<Vertex> = Vec::with_capacity(2 * PRECISION.pow(2) as usize);
     let angle_diff = PI / PRECISION as f32;
 
     vertices.push(Vertex {
-        pos: Point3::new(0., RADIUS, 0.).into(),
+        pos: Point3::new(0., radius, 0.).into(),
         norm: vec3(0., 1., 0.).into(),
     });
 
@@ -209,10 +211,10 @@ fn gen_vertices() -> Vec<Vertex> {
         let v_angle = angle_diff * layer as f32; // vertically I'm doing only half rotation
         for slice in 0..(2 * PRECISION) {
             let h_angle = angle_diff * slice as f32; // horizontally I'm doing full circle
-            let layer_radius = RADIUS * v_angle.sin();
+            let layer_radius = radius * v_angle.sin();
             let vertex = Point3::new(
                 layer_radius * h_angle.sin(),
-                RADIUS * v_angle.cos(),
+                radius * v_angle.cos(),
                 layer_radius * h_angle.cos(),
             );
 
@@ -224,7 +226,7 @@ fn gen_vertices() -> Vec<Vertex> {
     }
 
     vertices.push(Vertex {
-        pos: Point3::new(0., -RADIUS, 0.).into(),
+        pos: Point3::new(0., -radius, 0.).into(),
         norm: vec3(0., -1., 0.).into(),
     });
 
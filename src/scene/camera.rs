@@ -1,4 +1,4 @@
-use cgmath::{perspective, vec3, Deg, Matrix4, Point3};
+use cgmath::{perspective, vec3, Deg, InnerSpace, Matrix, Matrix4, Point3, Vector4};
 use winit::dpi::PhysicalSize;
 
 use crate::coords::SphericalPoint3;
@@ -34,6 +34,15 @@ impl Camera {
         )
     }
 
+    /// Left, right, bottom, top, near and far planes of what the camera sees, in world
+    /// coordinates. A point p is inside when dot(plane.xyz, p) + plane.w >= 0 for all of them.
+    pub fn frustum_planes(&self) -> [Vector4<f32>; 6] {
+        // rows of the clip matrix, see Gribb & Hartmann, works for OpenGL depth range
+        let clip = self.projection * self.view;
+        let (x, y, z, w) = (clip.row(0), clip.row(1), clip.row(2), clip.row(3));
+        [w + x, w - x, w + y, w - y, w + z, w - z].map(|plane| plane / plane.truncate().magnitude())
+    }
+
     fn view(position: SphericalPoint3<f32>, look_at: Point3<f32>) -> Matrix4<f32> {
         Matrix4::look_at_rh(position.into(), look_at, vec3(0.0, 1.0, 0.0))
     }
@@ -61,3 +70,40 @@ impl Camera {
         vulkan.update_camera(&self);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Vector4};
+    use rstest::*;
+    use winit::dpi::PhysicalSize;
+
+    use crate::coords::SphericalPoint3;
+    use crate::scene::camera::Camera;
+
+    fn inside(planes: &[Vector4<f32>; 6], point: Point3<f32>) -> bool {
+        planes
+            .iter()
+            .all(|plane| plane.x * point.x + plane.y * point.y + plane.z * point.z + plane.w >= 0.)
+    }
+
+    #[rstest(
+        point,
+        visible,
+        case(Point3::new(0., 0., 0.), true),
+        case(Point3::new(0., 0., 5.), true),
+        case(Point3::new(0., 0., 25.), false), // behind the camera
+        case(Point3::new(0., 0., -95.), false), // beyond the far plane
+        case(Point3::new(50., 0., 0.), false),
+        case(Point3::new(0., -50., 0.), false)
+    )]
+    fn sees_only_whats_in_front(point: Point3<f32>, visible: bool) {
+        // on the Z axis, looking at the origin
+        let position = SphericalPoint3::from(Point3::new(0., 0., 20.));
+        let camera = Camera::new(
+            position,
+            Point3::new(0., 0., 0.),
+            PhysicalSize::new(800, 600),
+        );
+        assert_eq!(inside(&camera.frustum_planes(), point), visible);
+    }
+}
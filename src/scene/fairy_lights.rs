@@ -0,0 +1,221 @@
+use std::f32::consts::TAU;
+
+use cgmath::Point3;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::bulb_mesh::{BulbInstanceData, BulbMesh};
+use crate::coords::CylindricalPoint3;
+use crate::scene::baubles;
+use crate::vulkan::Vulkan;
+
+const BULBS: usize = 160;
+const BULB_RADIUS: f32 = 0.06;
+
+// the string winds around the tree from the top down, following the cone of its branches
+const TOP_H: f32 = -2.2;
+const BOTTOM_H: f32 = 4.2;
+const TURNS: f32 = 6.5;
+const CONE_APEX_H: f32 = -2.9;
+const CONE_SLOPE: f32 = 0.62; // radius grows that much with every unit of height
+
+const COLORS: [[f32; 3]; 4] = [
+    [1.0, 0.15, 0.1],
+    [0.1, 1.0, 0.2],
+    [0.2, 0.3, 1.0],
+    [1.0, 0.75, 0.1],
+];
+// switched off bulbs still glow a bit, just like real ones cooling down
+const MIN_BRIGHTNESS: f32 = 0.1;
+
+const CHASE_SPEED: f32 = 0.5; // cycles per second
+const CHASE_LENGTH: f32 = 12.; // bulbs per cycle, a third of them is lit
+const TWINKLE_SPEED: f32 = 0.7;
+const FADE_SPEED: f32 = 0.25;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum BlinkPattern {
+    /// Groups of lit bulbs running down the string.
+    #[default]
+    Chase,
+    /// Every bulb flashing every now and then on its own.
+    Twinkle,
+    /// All bulbs slowly fading in and out together.
+    Fade,
+}
+
+impl BlinkPattern {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "chase" => Some(BlinkPattern::Chase),
+            "twinkle" => Some(BlinkPattern::Twinkle),
+            "fade" => Some(BlinkPattern::Fade),
+            _ => None,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            BlinkPattern::Chase => BlinkPattern::Twinkle,
+            BlinkPattern::Twinkle => BlinkPattern::Fade,
+            BlinkPattern::Fade => BlinkPattern::Chase,
+        }
+    }
+
+    /// Brightness of the bulb with given index on the string and blink phase.
+    fn brightness(self, index: usize, phase: f32, time_secs: f32) -> f32 {
+        let level = match self {
+            BlinkPattern::Chase => {
+                let position = time_secs * CHASE_SPEED - index as f32 / CHASE_LENGTH;
+                if position.rem_euclid(1.) < 1. / 3. {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            BlinkPattern::Twinkle => {
+                let wave = 0.5 + 0.5 * (TAU * (time_secs * TWINKLE_SPEED + phase)).cos();
+                wave.powi(8) // short flashes, dark most of the time
+            }
+            BlinkPattern::Fade => 0.5 + 0.5 * (TAU * time_secs * FADE_SPEED).cos(),
+        };
+        MIN_BRIGHTNESS + (1. - MIN_BRIGHTNESS) * level
+    }
+}
+
+struct Bulb {
+    center: CylindricalPoint3<f32>,
+    color: [f32; 3],
+    phase: f32, // in cycles, [0; 1)
+}
+
+pub struct FairyLights {
+    bulbs: Vec<Bulb>,
+    pub pattern: BlinkPattern,
+    time_secs: f32,
+}
+
+impl FairyLights {
+    pub fn new(pattern: BlinkPattern) -> Self {
+        let mut rng = SmallRng::from_entropy();
+        let bulbs = (0..BULBS)
+            .map(|i| {
+                // lower turns are longer, this keeps the bulbs evenly spread along the string
+                let t = ((i as f32 + 0.5) / BULBS as f32).sqrt();
+                let h = TOP_H + (BOTTOM_H - TOP_H) * t;
+                let r = CONE_SLOPE * (h - CONE_APEX_H);
+                Bulb {
+                    center: CylindricalPoint3::new(r, TAU * TURNS * t, h),
+                    color: COLORS[i % COLORS.len()],
+                    phase: rng.gen(),
+                }
+            })
+            .collect();
+        FairyLights {
+            bulbs,
+            pattern,
+            time_secs: 0.,
+        }
+    }
+
+    pub fn create_mesh(&self) -> BulbMesh {
+        let (vertices, indices) = baubles::gen_sphere(1.);
+        BulbMesh {
+            vertices,
+            indices,
+            instances: self.instances(),
+        }
+    }
+
+    pub fn animate(&mut self, time_delta_secs: f32, vulkan: &mut Vulkan) {
+        self.time_secs += time_delta_secs;
+        vulkan.update_fairy_lights(&self.instances());
+    }
+
+    fn instances(&self) -> Vec<BulbInstanceData> {
+        self.bulbs
+            .iter()
+            .enumerate()
+            .map(|(i, bulb)| {
+                let position: Point3<f32> = bulb.center.into();
+                BulbInstanceData {
+                    position: position.into(),
+                    radius: BULB_RADIUS,
+                    color: bulb.color,
+                    brightness: self.pattern.brightness(i, bulb.phase, self.time_secs),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use crate::scene::fairy_lights::{
+        BlinkPattern, FairyLights, BOTTOM_H, CHASE_LENGTH, CHASE_SPEED, MIN_BRIGHTNESS, TOP_H,
+    };
+
+    #[rstest(
+        pattern,
+        case(BlinkPattern::Chase),
+        case(BlinkPattern::Twinkle),
+        case(BlinkPattern::Fade)
+    )]
+    fn brightness_stays_in_range(pattern: BlinkPattern) {
+        for step in 0..200 {
+            let time_secs = step as f32 * 0.05;
+            for index in 0..20 {
+                let brightness = pattern.brightness(index, index as f32 / 20., time_secs);
+                assert!(
+                    (MIN_BRIGHTNESS..=1.).contains(&brightness),
+                    "{}",
+                    brightness
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chase_runs_down_the_string() {
+        let bulb_time_secs = 1. / (CHASE_LENGTH * CHASE_SPEED);
+        for step in 0..50 {
+            let time_secs = 0.01 + step as f32 * 0.1;
+            assert_eq!(
+                BlinkPattern::Chase.brightness(3, 0., time_secs),
+                BlinkPattern::Chase.brightness(4, 0., time_secs + bulb_time_secs)
+            );
+        }
+    }
+
+    #[test]
+    fn fade_ignores_phase() {
+        assert_eq!(
+            BlinkPattern::Fade.brightness(0, 0., 1.3),
+            BlinkPattern::Fade.brightness(7, 0.6, 1.3)
+        );
+    }
+
+    #[test]
+    fn patterns_cycle_through_all() {
+        let mut pattern = BlinkPattern::default();
+        for _ in 0..3 {
+            pattern = pattern.next();
+        }
+        assert_eq!(pattern, BlinkPattern::default());
+    }
+
+    #[test]
+    fn bulbs_wind_down_around_the_tree() {
+        let fairy_lights = FairyLights::new(BlinkPattern::Chase);
+        for pair in fairy_lights.bulbs.windows(2) {
+            assert!(pair[0].center.h < pair[1].center.h);
+            assert!(pair[0].center.r < pair[1].center.r);
+        }
+        for bulb in fairy_lights.bulbs.iter() {
+            assert!(bulb.center.h >= TOP_H && bulb.center.h <= BOTTOM_H);
+            assert!(bulb.center.r > 0.);
+        }
+    }
+}
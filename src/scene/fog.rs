@@ -0,0 +1,33 @@
+/// Fog getting thicker with distance everywhere, and even more so close to the ground.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fog {
+    pub color: [f32; 3], // should match the background, so that far away things blend in
+    pub density: f32,    // per unit of distance, the same everywhere
+    pub height_density: f32, // per unit of distance, on top of density, at base_height
+    pub height_falloff: f32, // how quickly height fog thins out going up
+    pub base_height: f32, // Y where height fog is the thickest, up is -Y
+}
+
+impl Fog {
+    pub fn new(color: [f32; 3]) -> Self {
+        Fog {
+            color,
+            density: 0.,
+            height_density: 0.,
+            height_falloff: 0.,
+            base_height: 0.,
+        }
+    }
+
+    pub fn with_distance_fog(mut self, density: f32) -> Self {
+        self.density = density;
+        self
+    }
+
+    pub fn with_height_fog(mut self, density: f32, falloff: f32, base_height: f32) -> Self {
+        self.height_density = density;
+        self.height_falloff = falloff;
+        self.base_height = base_height;
+        self
+    }
+}
@@ -1,6 +1,8 @@
 use cgmath::{Matrix4, SquareMatrix};
 
-use crate::textured_mesh::{InstanceData, TexturedMesh, TexturedVertex};
+use crate::textured_mesh::{
+    normal_map_from_height, InstanceData, TexturedMesh, TexturedVertex, NO_TANGENT,
+};
 use crate::vulkan::VertexIndexType;
 
 const VERTICES_DATA: [TexturedVertex; 4] = [
@@ -8,29 +10,36 @@ const VERTICES_DATA: [TexturedVertex; 4] = [
         pos: [-10., 5., -10.],
         norm: [0., -1., 0.],
         texture_coordinates: [0., 0.],
+        tangent: NO_TANGENT,
     },
     TexturedVertex {
         pos: [-10., 5., 10.],
         norm: [0., -1., 0.],
         texture_coordinates: [0., 1.],
+        tangent: NO_TANGENT,
     },
     TexturedVertex {
         pos: [10., 5., -10.],
         norm: [0., -1., 0.],
         texture_coordinates: [1., 0.],
+        tangent: NO_TANGENT,
     },
     TexturedVertex {
         pos: [10., 5., 10.],
         norm: [0., -1., 0.],
         texture_coordinates: [1., 1.],
+        tangent: NO_TANGENT,
     },
 ];
 const INDICES_DATA: [VertexIndexType; 6] = [0, 2, 1, 1, 2, 3];
+// how much brighter parts of the snow stick out
+const BUMPINESS: f32 = 4.;
 
 pub fn create_meshes() -> Vec<TexturedMesh> {
     let texture = image::open("textures/TexturesCom_Snow0166_2_seamless_S.jpg")
         .unwrap()
         .into_rgba8();
+    let normal_map = normal_map_from_height(&texture, BUMPINESS);
     vec![TexturedMesh {
         vertices: Vec::from(VERTICES_DATA),
         indices: Vec::from(INDICES_DATA),
@@ -39,5 +48,6 @@ pub fn create_meshes() -> Vec<TexturedMesh> {
             ..Default::default()
         }],
         texture,
+        normal_map: Some(normal_map),
     }]
 }
@@ -1,13 +1,120 @@
-use cgmath::Point3;
+use cgmath::{ortho, perspective, vec3, InnerSpace, Matrix4, Point3, Rad, Vector3};
+
+// sphere containing everything that should cast or receive shadows, the ground is 20x20
+const SHADOW_FOCUS: Point3<f32> = Point3::new(0., 0., 0.);
+const SHADOW_RADIUS: f32 = 15.;
+// lights inside the sphere can't see all of it anyway
+const MAX_SHADOW_FOV_RAD: f32 = 2.2;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightType {
+    /// Infinitely far away, like the moon, shining everywhere in the same direction.
+    Directional { direction: Vector3<f32> },
+    /// Shining from its position in all directions.
+    Point,
+    /// Shining from its position in a cone around direction, fading out between the inner and
+    /// outer angles, both measured from the direction.
+    Spot {
+        direction: Vector3<f32>,
+        inner_cone: Rad<f32>,
+        outer_cone: Rad<f32>,
+    },
+}
+
+/// How light gets weaker with distance d: 1 / (constant + linear * d + quadratic * d^2).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub const NONE: Attenuation = Attenuation::new(1., 0., 0.);
+
+    pub const fn new(constant: f32, linear: f32, quadratic: f32) -> Self {
+        Attenuation {
+            constant,
+            linear,
+            quadratic,
+        }
+    }
+}
 
 #[derive(Debug, Copy, Clone)]
 pub struct Light {
-    pub position: [f32; 3],
+    pub light_type: LightType,
+    pub position: [f32; 3],       // ignored by directional lights
+    pub attenuation: Attenuation, // ignored by directional lights
     pub ambient: [f32; 3],
     pub diffuse: [f32; 3],
     pub specular: [f32; 3],
 }
 
+impl Light {
+    /// View and projection of the light looking at the scene, as used by its shadow map.
+    /// Just like the camera's projection it produces OpenGL depth range.
+    pub fn shadow_view_projection(&self) -> Matrix4<f32> {
+        let position = Point3::from(self.position);
+        match self.light_type {
+            LightType::Directional { direction } => {
+                // any position behind the scene will do
+                let direction = direction.normalize();
+                let eye = SHADOW_FOCUS - 2. * SHADOW_RADIUS * direction;
+                let projection = ortho(
+                    -SHADOW_RADIUS,
+                    SHADOW_RADIUS,
+                    -SHADOW_RADIUS,
+                    SHADOW_RADIUS,
+                    SHADOW_RADIUS,
+                    3. * SHADOW_RADIUS,
+                );
+                projection * Matrix4::look_at_rh(eye, SHADOW_FOCUS, Light::up(direction))
+            }
+            LightType::Point => {
+                let direction = SHADOW_FOCUS - position;
+                let distance = direction.magnitude();
+                let fov = if distance > SHADOW_RADIUS {
+                    (2. * (SHADOW_RADIUS / distance).asin()).min(MAX_SHADOW_FOV_RAD)
+                } else {
+                    MAX_SHADOW_FOV_RAD
+                };
+                let near = (distance - SHADOW_RADIUS).max(0.1);
+                let far = distance + SHADOW_RADIUS;
+                perspective(Rad(fov), 1., near, far)
+                    * Matrix4::look_at_rh(position, SHADOW_FOCUS, Light::up(direction))
+            }
+            LightType::Spot {
+                direction,
+                outer_cone,
+                ..
+            } => {
+                let fov = (2. * outer_cone.0).min(MAX_SHADOW_FOV_RAD);
+                let far = (SHADOW_FOCUS - position).magnitude() + SHADOW_RADIUS;
+                perspective(Rad(fov), 1., 0.1, far)
+                    * Matrix4::look_at_rh(position, position + direction, Light::up(direction))
+            }
+        }
+    }
+
+    fn up(direction: Vector3<f32>) -> Vector3<f32> {
+        if direction.normalize().y.abs() > 0.99 {
+            vec3(0., 0., 1.)
+        } else {
+            vec3(0., 1., 0.)
+        }
+    }
+}
+
+/// Where a spot light is and where it shines, see `LightType::Spot`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpotLight {
+    pub position: Point3<f32>,
+    pub look_at: Point3<f32>,
+    pub inner_cone: Rad<f32>,
+    pub outer_cone: Rad<f32>,
+}
+
 pub struct Lights {
     pub lights: Vec<Light>,
 }
@@ -17,19 +124,146 @@ impl Lights {
         Lights { lights: vec![] }
     }
 
-    pub fn add(
+    pub fn add_directional(
+        &mut self,
+        direction: Vector3<f32>,
+        ambient: [f32; 3],
+        diffuse: [f32; 3],
+        specular: [f32; 3],
+    ) {
+        self.lights.push(Light {
+            light_type: LightType::Directional { direction },
+            position: [0., 0., 0.],
+            attenuation: Attenuation::NONE,
+            ambient,
+            diffuse,
+            specular,
+        });
+    }
+
+    pub fn add_point(
         &mut self,
         position: Point3<f32>,
+        attenuation: Attenuation,
         ambient: [f32; 3],
         diffuse: [f32; 3],
         specular: [f32; 3],
     ) {
-        let light = Light {
+        self.lights.push(Light {
+            light_type: LightType::Point,
             position: position.into(),
+            attenuation,
+            ambient,
+            diffuse,
+            specular,
+        });
+    }
+
+    pub fn add_spot(
+        &mut self,
+        spot: SpotLight,
+        attenuation: Attenuation,
+        ambient: [f32; 3],
+        diffuse: [f32; 3],
+        specular: [f32; 3],
+    ) {
+        self.lights.push(Light {
+            light_type: LightType::Spot {
+                direction: spot.look_at - spot.position,
+                inner_cone: spot.inner_cone,
+                outer_cone: spot.outer_cone,
+            },
+            position: spot.position.into(),
+            attenuation,
             ambient,
             diffuse,
             specular,
-        };
-        self.lights.push(light);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{vec3, Point3, Rad, Transform};
+    use rstest::*;
+
+    use crate::scene::lights::{Attenuation, Light, LightType, Lights, SpotLight};
+
+    const GROUND_CORNERS: [Point3<f32>; 4] = [
+        Point3::new(-10., 5., -10.),
+        Point3::new(-10., 5., 10.),
+        Point3::new(10., 5., -10.),
+        Point3::new(10., 5., 10.),
+    ];
+
+    fn light(light_type: LightType, position: Point3<f32>) -> Light {
+        Light {
+            light_type,
+            position: position.into(),
+            attenuation: Attenuation::NONE,
+            ambient: [0.; 3],
+            diffuse: [0.; 3],
+            specular: [0.; 3],
+        }
+    }
+
+    #[rstest(light,
+    case(light(LightType::Point, Point3::new(10., -100., 10.))),
+    case(light(LightType::Point, Point3::new(0., -100., 0.))),
+    case(light(LightType::Directional { direction: vec3(-1., 10., -1.) }, Point3::new(0., 0., 0.))),
+    case(light(LightType::Directional { direction: vec3(0., 1., 0.) }, Point3::new(0., 0., 0.))),
+    )]
+    fn ground_corners_are_inside_shadow_frustum(light: Light) {
+        let shadow_view_projection = light.shadow_view_projection();
+        for corner in GROUND_CORNERS {
+            let projected = shadow_view_projection.transform_point(corner);
+            assert!(projected.x.abs() < 1., "{:?}", projected);
+            assert!(projected.y.abs() < 1., "{:?}", projected);
+            assert!(projected.z > -1. && projected.z < 1., "{:?}", projected);
+        }
+    }
+
+    #[test]
+    fn spot_light_looks_along_its_direction() {
+        let light = light(
+            LightType::Spot {
+                direction: vec3(-5., 6., -2.),
+                inner_cone: Rad(0.3),
+                outer_cone: Rad(0.5),
+            },
+            Point3::new(5., -6., 2.),
+        );
+        let projected = light
+            .shadow_view_projection()
+            .transform_point(Point3::new(0., 0., 0.));
+        assert!(projected.x.abs() < 0.001, "{:?}", projected);
+        assert!(projected.y.abs() < 0.001, "{:?}", projected);
+        assert!(projected.z > -1. && projected.z < 1., "{:?}", projected);
+    }
+
+    #[test]
+    fn spot_light_shines_towards_what_it_looks_at() {
+        let mut lights = Lights::setup();
+        lights.add_spot(
+            SpotLight {
+                position: Point3::new(5., -6., 2.),
+                look_at: Point3::new(1., 0., 1.),
+                inner_cone: Rad(0.3),
+                outer_cone: Rad(0.5),
+            },
+            Attenuation::NONE,
+            [0.; 3],
+            [1.; 3],
+            [1.; 3],
+        );
+        assert_eq!(
+            lights.lights[0].light_type,
+            LightType::Spot {
+                direction: vec3(-4., 6., -1.),
+                inner_cone: Rad(0.3),
+                outer_cone: Rad(0.5),
+            }
+        );
+        assert_eq!(lights.lights[0].position, [5., -6., 2.]);
     }
 }
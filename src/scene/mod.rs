@@ -1,71 +1,129 @@
-use cgmath::Point3;
+use cgmath::{vec3, Deg, Point3};
 use winit::dpi::PhysicalSize;
 
-use crate::color_mesh::ColorMesh;
 use crate::coords::SphericalPoint3;
+use crate::pbr_mesh::PbrMesh;
 use crate::scene::camera::Camera;
-use crate::scene::lights::Lights;
+use crate::scene::fairy_lights::{BlinkPattern, FairyLights};
+use crate::scene::fog::Fog;
+use crate::scene::lights::{Attenuation, Lights, SpotLight};
 use crate::textured_mesh::TexturedMesh;
+use crate::vulkan::error::VulkanResult;
 use crate::vulkan::Vulkan;
 
 mod baubles;
 pub mod camera;
+pub mod fairy_lights;
+pub mod fog;
 mod ground;
 pub mod lights;
+mod sky;
 pub mod snow;
+mod star;
 mod tree;
 
+// horizon's color, the sky and the fog blend into it
 const BACKGROUND_COLOR: [f32; 4] = [0.015_7, 0., 0.360_7, 1.];
+const GROUND_LEVEL: f32 = 5.;
 
 pub struct Scene {
     pub camera: Camera,
+    fairy_lights: FairyLights,
 }
 
 impl Scene {
-    pub fn setup(vulkan: &mut Vulkan, window: &winit::window::Window) -> Self {
+    pub fn setup(
+        vulkan: &mut Vulkan,
+        window_size: PhysicalSize<u32>,
+        blink_pattern: BlinkPattern,
+    ) -> VulkanResult<Self> {
         vulkan.set_clear_value(BACKGROUND_COLOR);
-        let camera = Scene::setup_camera(vulkan, window);
-        Scene::setup_lights(vulkan);
-        Scene::setup_meshes(vulkan);
+        let camera = Scene::setup_camera(vulkan, window_size);
+        Scene::setup_lights(vulkan)?;
+        Scene::setup_fog(vulkan);
+        let fairy_lights = FairyLights::new(blink_pattern);
+        Scene::setup_meshes(vulkan, &fairy_lights)?;
 
-        Self { camera }
+        Ok(Self {
+            camera,
+            fairy_lights,
+        })
     }
 
-    fn setup_camera(vulkan: &mut Vulkan, window: &winit::window::Window) -> Camera {
+    fn setup_camera(vulkan: &mut Vulkan, window_size: PhysicalSize<u32>) -> Camera {
         let camera_position: SphericalPoint3<f32> = SphericalPoint3::new(18., 1.7, 0.9);
         let look_at: Point3<f32> = Point3::new(0., 1., 0.);
-        let camera = Camera::new(camera_position, look_at, window.inner_size());
+        let camera = Camera::new(camera_position, look_at, window_size);
         vulkan.update_camera(&camera);
         camera
     }
 
-    fn setup_lights(vulkan: &mut Vulkan) {
+    fn setup_lights(vulkan: &mut Vulkan) -> VulkanResult<()> {
         let mut lights = Lights::setup();
-        lights.add(
-            Point3::new(10., -100., 10.),
-            [0.3, 0.3, 0.3],
-            [0.2, 0.2, 0.2],
+        // moonlight
+        lights.add_directional(
+            vec3(-0.1, 1., -0.1),
+            [0.2, 0.2, 0.25],
+            [0.15, 0.15, 0.25],
             [0., 0., 0.],
         );
-        lights.add(
-            Point3::new(5., -6., 2.),
-            [0.2, 0.2, 0.2],
-            [2., 2., 2.],
-            [0.5, 0.5, 0.5],
+        // warm spotlight on the tree
+        lights.add_spot(
+            SpotLight {
+                position: Point3::new(5., -6., 5.),
+                look_at: Point3::new(0., 0., 0.),
+                inner_cone: Deg(25.).into(),
+                outer_cone: Deg(35.).into(),
+            },
+            Attenuation::new(1., 0.01, 0.005),
+            [0.1, 0.08, 0.05],
+            [2.2, 1.8, 1.2],
+            [0.6, 0.5, 0.4],
         );
-        vulkan.update_lights(&lights);
+        // faint glow of the star, only reaching the branches around it
+        lights.add_point(
+            Point3::new(0., -3.4, 0.),
+            Attenuation::new(1., 0.7, 1.8),
+            [0., 0., 0.],
+            [1., 0.85, 0.45],
+            [0.3, 0.25, 0.15],
+        );
+        vulkan.update_lights(&lights)
+    }
+
+    fn setup_fog(vulkan: &mut Vulkan) {
+        let [r, g, b, _] = BACKGROUND_COLOR;
+        // thin haze hiding the ground's edges, thicker mist right above the snow
+        let fog =
+            Fog::new([r, g, b])
+                .with_distance_fog(0.02)
+                .with_height_fog(0.08, 0.6, GROUND_LEVEL);
+        vulkan.update_fog(&fog);
     }
 
-    fn setup_meshes(vulkan: &mut Vulkan) {
-        let mut color_meshes: Vec<ColorMesh> = Vec::new();
-        color_meshes.extend(baubles::create_meshes());
-        color_meshes.extend(tree::create_meshes());
+    fn setup_meshes(vulkan: &mut Vulkan, fairy_lights: &FairyLights) -> VulkanResult<()> {
+        let mut pbr_meshes: Vec<PbrMesh> = Vec::new();
+        pbr_meshes.extend(baubles::create_meshes());
+        pbr_meshes.extend(tree::create_meshes());
         let mut textured_meshes: Vec<TexturedMesh> = Vec::new();
         textured_meshes.extend(ground::create_meshes());
-        vulkan.set_static_meshes(&color_meshes, &textured_meshes);
+        let bulb_meshes = star::create_meshes();
+        vulkan.set_static_meshes(&pbr_meshes, &textured_meshes, &bulb_meshes)?;
         let (snowflakes, snow_meshes) = snow::create_meshes();
-        vulkan.set_snow_mesh(&snowflakes, &snow_meshes);
-        vulkan.scene_complete();
+        vulkan.set_snow_mesh(&snowflakes, &snow_meshes)?;
+        vulkan.set_fairy_lights_mesh(&fairy_lights.create_mesh())?;
+        let [r, g, b, _] = BACKGROUND_COLOR;
+        vulkan.set_skybox(&sky::create_skybox([r, g, b]))?;
+        vulkan.scene_complete()
+    }
+
+    /// Moves everything that's animated by the time that passed since the last frame.
+    pub fn animate(&mut self, time_delta_secs: f32, vulkan: &mut Vulkan) {
+        self.fairy_lights.animate(time_delta_secs, vulkan);
+    }
+
+    pub fn next_blink_pattern(&mut self) {
+        self.fairy_lights.pattern = self.fairy_lights.pattern.next();
     }
 
     pub fn rotate_camera_horizontally(&mut self, angle: f32, vulkan: &mut Vulkan) {
@@ -0,0 +1,104 @@
+use std::f32::consts::PI;
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::skybox::Skybox;
+
+const WIDTH: u32 = 2048;
+const HEIGHT: u32 = WIDTH / 2;
+const FACE_SIZE: u32 = 512;
+
+// almost black, in sRGB like the rest of the image
+const ZENITH_COLOR: [f32; 3] = [0.005, 0.005, 0.06];
+const STARS: usize = 6000;
+const STAR_SEED: u64 = 1224;
+
+// drop six faces in there to replace the generated sky
+const FACE_FILES: [&str; 6] = [
+    "textures/skybox/px.png",
+    "textures/skybox/nx.png",
+    "textures/skybox/py.png",
+    "textures/skybox/ny.png",
+    "textures/skybox/pz.png",
+    "textures/skybox/nz.png",
+];
+// or a single panorama, used only without the faces
+const EQUIRECTANGULAR_FILE: &str = "textures/skybox/equirectangular.png";
+
+/// Horizon color is linear, just like the clear color and the fog's one.
+pub fn create_skybox(horizon_color: [f32; 3]) -> Skybox {
+    let loaded = if FACE_FILES.iter().all(|path| Path::new(path).exists()) {
+        Some(Skybox::from_files(FACE_FILES))
+    } else if Path::new(EQUIRECTANGULAR_FILE).exists() {
+        Some(load_equirectangular(EQUIRECTANGULAR_FILE))
+    } else {
+        None
+    };
+    match loaded {
+        Some(Ok(skybox)) => skybox,
+        Some(Err(message)) => {
+            log::error!("{}, using the generated sky instead", message);
+            generate_night_sky(horizon_color.map(linear_to_srgb))
+        }
+        None => generate_night_sky(horizon_color.map(linear_to_srgb)),
+    }
+}
+
+/// Each face covers a quarter of the image's width.
+fn load_equirectangular(path: &str) -> Result<Skybox, String> {
+    let image = image::open(path)
+        .map_err(|error| format!("Failed to open {}: {}", path, error))?
+        .into_rgba8();
+    Ok(Skybox::from_equirectangular(
+        &image,
+        (image.width() / 4).max(1),
+    ))
+}
+
+/// Starry winter night, fading from the horizon's color to almost black.
+fn generate_night_sky(horizon_color: [f32; 3]) -> Skybox {
+    let mut image = RgbaImage::from_fn(WIDTH, HEIGHT, |_, y| {
+        let latitude = PI * (0.5 - (y as f32 + 0.5) / HEIGHT as f32);
+        let height = latitude.max(0.).sin().sqrt(); // darkens quickly above the horizon
+        let channel = |c: usize| horizon_color[c] + (ZENITH_COLOR[c] - horizon_color[c]) * height;
+        Rgba([to_u8(channel(0)), to_u8(channel(1)), to_u8(channel(2)), 255])
+    });
+
+    // same stars every time
+    let mut rng = SmallRng::seed_from_u64(STAR_SEED);
+    for _ in 0..STARS {
+        // evenly spread over the upper half of the sphere, not crowded at the zenith
+        let latitude = rng.gen::<f32>().asin();
+        let x = rng.gen_range(0..WIDTH);
+        let y = ((0.5 - latitude / PI) * HEIGHT as f32) as u32;
+        let brightness: f32 = rng.gen::<f32>().powi(3); // most stars are faint
+        let warmth: f32 = rng.gen_range(-0.15..0.15);
+        let star = [
+            brightness * (1. + warmth),
+            brightness,
+            brightness * (1. - warmth),
+        ];
+        let pixel = image.get_pixel_mut(x, y.min(HEIGHT - 1));
+        for c in 0..3 {
+            pixel[c] = pixel[c].saturating_add(to_u8(star[c]));
+        }
+    }
+
+    Skybox::from_equirectangular(&image, FACE_SIZE)
+}
+
+// faces get uploaded as sRGB textures, sampling turns them back into linear
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1. / 2.4) - 0.055
+    }
+}
+
+fn to_u8(value: f32) -> u8 {
+    (value.clamp(0., 1.) * 255.).round() as u8
+}
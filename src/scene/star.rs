@@ -0,0 +1,58 @@
+use std::f32::consts::PI;
+
+use cgmath::{InnerSpace, Point3};
+
+use crate::bulb_mesh::{BulbInstanceData, BulbMesh};
+use crate::vulkan::Vertex;
+
+const POINTS: u32 = 5;
+const INNER_RADIUS: f32 = 0.45; // relative to the tips
+const THICKNESS: f32 = 0.3; // relative to the tips
+
+/// Glowing star on the top of the tree.
+pub fn create_meshes() -> Vec<BulbMesh> {
+    let vertices = gen_vertices();
+    let indices = (0..vertices.len() as u32).collect();
+    let instance = BulbInstanceData {
+        position: [0., -3.4, 0.],
+        radius: 0.5,
+        color: [1., 0.85, 0.45],
+        brightness: 1.,
+    };
+    vec![BulbMesh {
+        vertices,
+        indices,
+        instances: vec![instance],
+    }]
+}
+
+/// Flat shaded, so every triangle gets its own vertices. Star's tip points up, that's -Y.
+fn gen_vertices() -> Vec<Vertex> {
+    let outline: Vec<Point3<f32>> = (0..2 * POINTS)
+        .map(|i| {
+            let angle = PI * i as f32 / POINTS as f32;
+            let radius = if i % 2 == 0 { 1. } else { INNER_RADIUS };
+            Point3::new(radius * angle.sin(), -radius * angle.cos(), 0.)
+        })
+        .collect();
+    let front = Point3::new(0., 0., THICKNESS);
+    let back = Point3::new(0., 0., -THICKNESS);
+
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(3 * 2 * outline.len());
+    for i in 0..outline.len() {
+        let current = outline[i];
+        let next = outline[(i + 1) % outline.len()];
+        for triangle in [[front, current, next], [back, next, current]] {
+            let normal = (triangle[1] - triangle[0])
+                .cross(triangle[2] - triangle[0])
+                .normalize();
+            for corner in triangle {
+                vertices.push(Vertex {
+                    pos: corner.into(),
+                    norm: normal.into(),
+                });
+            }
+        }
+    }
+    vertices
+}
@@ -3,10 +3,11 @@ use std::f32::consts::PI;
 use cgmath::{vec3, Matrix4, Point3, Rad};
 use tobj::{load_mtl_buf, load_obj_buf};
 
-use crate::color_mesh::{Color, ColorMesh, InstanceData};
+use crate::color_mesh::Color;
+use crate::pbr_mesh::{PbrInstanceData, PbrMesh};
 use crate::vulkan::Vertex;
 
-pub fn create_meshes() -> Vec<ColorMesh> {
+pub fn create_meshes() -> Vec<PbrMesh> {
     let object_source = include_str!("../../models/tree.obj");
     let materials_source = include_str!("../../models/tree.mtl");
     let load_options = tobj::LoadOptions {
@@ -18,7 +19,7 @@ pub fn create_meshes() -> Vec<ColorMesh> {
     });
     let (models, model_materials) = tree.unwrap();
     let materials = model_materials.unwrap();
-    let mut meshes: Vec<ColorMesh> = vec![];
+    let mut meshes: Vec<PbrMesh> = vec![];
     for mi in 0..models.len() {
         let mut vertices: Vec<Vertex> = vec![];
         let mut indices: Vec<u32> = vec![];
@@ -49,12 +50,12 @@ pub fn create_meshes() -> Vec<ColorMesh> {
         };
         let model: Matrix4<f32> =
             Matrix4::from_angle_z(Rad(PI)) * Matrix4::from_nonuniform_scale(1.8, 1., 1.8);
-        let instance = InstanceData {
-            color,
+        let instance = PbrInstanceData {
+            material: color.into(),
             model,
             ..Default::default()
         };
-        let mesh = ColorMesh {
+        let mesh = PbrMesh {
             vertices,
             indices,
             instances: vec![instance],
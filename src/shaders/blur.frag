@@ -0,0 +1,26 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Direction {
+    vec2 texelStep; // one texel either horizontally or vertically
+} direction;
+
+layout(location = 0) in vec2 fragUV;
+
+layout(location = 0) out vec4 outColor;
+
+// 9 tap gaussian blur done in 5 samples, thanks to linear filtering sampling between texels
+const float OFFSETS[3] = float[](0.0, 1.3846153846, 3.2307692308);
+const float WEIGHTS[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+
+void main() {
+    vec3 result = texture(source, fragUV).rgb * WEIGHTS[0];
+    for (int i = 1; i < 3; i++) {
+        vec2 offset = OFFSETS[i] * direction.texelStep;
+        result += texture(source, fragUV + offset).rgb * WEIGHTS[i];
+        result += texture(source, fragUV - offset).rgb * WEIGHTS[i];
+    }
+    outColor = vec4(result, 1.0);
+}
@@ -0,0 +1,22 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+
+layout(location = 0) in vec2 fragUV;
+
+layout(location = 0) out vec4 outColor;
+
+// only what's brighter than that glows
+const float THRESHOLD = 1.0;
+// softens the threshold, so that things don't start glowing all of a sudden
+const float KNEE = 0.5;
+
+void main() {
+    vec3 color = texture(scene, fragUV).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    float soft = clamp(brightness - THRESHOLD + KNEE, 0.0, 2.0 * KNEE);
+    soft = soft * soft / (4.0 * KNEE);
+    float contribution = max(soft, brightness - THRESHOLD) / max(brightness, 0.0001);
+    outColor = vec4(color * contribution, 1.0);
+}
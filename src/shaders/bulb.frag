@@ -0,0 +1,27 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform CameraUBO {
+    vec3 position;
+    mat4 view;
+    mat4 projection;
+} camera;
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec3 fragColor;
+layout(location = 3) in float fragBrightness;
+
+layout(location = 0) out vec4 outColor;
+
+// way over 1, so that lit bulbs glow through the bloom
+const float EMISSION_STRENGTH = 4.0;
+
+void main() {
+    // bulbs shine on their own, no lights needed, the middle facing the camera is the hottest
+    vec3 viewDir = normalize(camera.position - fragPosition);
+    float facing = max(dot(normalize(fragNormal), viewDir), 0.0);
+    vec3 glow = fragColor * fragBrightness * (0.5 + 0.5 * facing);
+    vec3 hotSpot = vec3(1.0) * fragBrightness * pow(facing, 8.0) * 0.5;
+    outColor = vec4(EMISSION_STRENGTH * (glow + hotSpot), 1.0);
+}
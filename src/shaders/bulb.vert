@@ -0,0 +1,36 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform CameraUBO {
+    vec3 position;
+    mat4 view;
+    mat4 projection;
+} camera;
+
+// per-vertex data, a unit sphere
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+
+// per-instance data
+layout (location = 2) in vec3 center;
+layout (location = 3) in float radius;
+layout (location = 4) in vec3 color;
+layout (location = 5) in float brightness;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+layout(location = 0) out vec3 fragPosition;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec3 fragColor;
+layout(location = 3) out float fragBrightness;
+
+void main() {
+    vec3 pos = center + radius * position;
+    gl_Position = camera.projection * camera.view * vec4(pos, 1.0);
+    fragPosition = pos;
+    fragNormal = normal;
+    fragColor = color;
+    fragBrightness = brightness;
+}
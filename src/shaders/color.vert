@@ -32,7 +32,7 @@ layout(location = 5) out float fragShininess;
 void main() {
     vec4 pos = model * vec4(position, 1.0);
     gl_Position = camera.projection * camera.view * pos;
-    fragPosition = vec3(gl_Position);
+    fragPosition = vec3(pos);
     fragAmbient = ambient;
     fragDiffuse = diffuse;
     fragSpecular = specular;
@@ -0,0 +1,177 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#define DIRECTIONAL_LIGHT 0
+#define POINT_LIGHT 1
+#define SPOT_LIGHT 2
+
+struct Light {
+    vec3 position;
+    int type;
+    vec3 direction;
+    float innerConeCos;
+    vec3 ambient;
+    float outerConeCos;
+    vec3 diffuse;
+    vec3 specular;
+    vec3 attenuation; // constant, linear, quadratic
+
+    mat4 shadowViewProjection;
+};
+
+layout(set = 0, binding = 0) uniform CameraUBO {
+    vec3 position;
+    mat4 view;
+    mat4 projection;
+} camera;
+
+layout(std430, set = 0, binding = 1) readonly buffer LightsSSBO {
+    int count;
+    int shadowCasters; // only the first lights have a shadow map
+    Light light[];
+} lights;
+
+layout(set = 0, binding = 3) uniform sampler2DArrayShadow shadowMap;
+
+layout(set = 0, binding = 5) uniform FogUBO {
+    vec3 color;
+    float density;
+    float heightDensity;
+    float heightFalloff;
+    float baseHeight; // Y where the height fog is the thickest, up is -Y
+} fog;
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec3 fragBaseColor;
+layout(location = 3) in float fragMetallic;
+layout(location = 4) in float fragRoughness;
+
+layout(location = 0) out vec4 outColor;
+
+const float PI = 3.14159265359;
+// how much light any non-metal reflects when looked at straight on
+const vec3 DIELECTRIC_F0 = vec3(0.04);
+
+vec3 calcLight(Light light, int index, vec3 normal, vec3 viewDir, vec3 f0);
+float distributionGGX(float nDotH, float roughness);
+float geometrySmith(float nDotV, float nDotL, float roughness);
+vec3 fresnelSchlick(float cosTheta, vec3 f0);
+vec3 calcLightDirection(Light light);
+float calcAttenuation(Light light, vec3 lightDir);
+float calcShadow(Light light, int index);
+vec3 applyFog(vec3 color);
+
+void main() {
+    vec3 normal = normalize(fragNormal);
+    vec3 viewDir = normalize(camera.position - fragPosition);
+    vec3 f0 = mix(DIELECTRIC_F0, fragBaseColor, fragMetallic);
+    vec3 result = vec3(0.0);
+    for (int i = 0; i < lights.count; i++) {
+        result += calcLight(lights.light[i], i, normal, viewDir, f0);
+    }
+    outColor = vec4(applyFog(result), 1.0);
+}
+
+// Cook-Torrance specular with Lambertian diffuse
+vec3 calcLight(Light light, int index, vec3 normal, vec3 viewDir, vec3 f0) {
+    vec3 ambient = light.ambient * fragBaseColor;
+
+    vec3 lightDir = calcLightDirection(light);
+    vec3 halfwayDir = normalize(lightDir + viewDir);
+    float nDotL = max(dot(normal, lightDir), 0.0);
+    float nDotV = max(dot(normal, viewDir), 0.0001);
+    float nDotH = max(dot(normal, halfwayDir), 0.0);
+
+    vec3 fresnel = fresnelSchlick(max(dot(halfwayDir, viewDir), 0.0), f0);
+    float d = distributionGGX(nDotH, fragRoughness);
+    float g = geometrySmith(nDotV, nDotL, fragRoughness);
+    vec3 specular = d * g * fresnel / (4.0 * nDotV * nDotL + 0.0001);
+
+    // whatever isn't reflected gets diffused, unless it's metal
+    vec3 kD = (vec3(1.0) - fresnel) * (1.0 - fragMetallic);
+    vec3 diffuse = kD * fragBaseColor / PI;
+
+    // lights are set up for Phong, where a white surface facing the light reflects all of its diffuse color
+    vec3 radiance = PI * light.diffuse;
+
+    float lit = index < lights.shadowCasters ? calcShadow(light, index) : 1.0;
+    return ambient + lit * calcAttenuation(light, lightDir) * (diffuse + specular) * radiance * nDotL;
+}
+
+// Trowbridge-Reitz, share of microfacets aligned with the halfway vector
+float distributionGGX(float nDotH, float roughness) {
+    float alpha = roughness * roughness;
+    float alpha2 = alpha * alpha;
+    float denominator = nDotH * nDotH * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * denominator * denominator);
+}
+
+// Schlick-GGX for both the view and the light direction, microfacets shadowing each other
+float geometrySmith(float nDotV, float nDotL, float roughness) {
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float ggxV = nDotV / (nDotV * (1.0 - k) + k);
+    float ggxL = nDotL / (nDotL * (1.0 - k) + k);
+    return ggxV * ggxL;
+}
+
+// more light gets reflected at grazing angles
+vec3 fresnelSchlick(float cosTheta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+// direction from the fragment towards the light
+vec3 calcLightDirection(Light light) {
+    if (light.type == DIRECTIONAL_LIGHT) {
+        return normalize(-light.direction);
+    }
+    return normalize(light.position - fragPosition);
+}
+
+// how much of the light reaches the fragment, 1.0 for directional lights
+float calcAttenuation(Light light, vec3 lightDir) {
+    if (light.type == DIRECTIONAL_LIGHT) {
+        return 1.0;
+    }
+    float distance = length(light.position - fragPosition);
+    float attenuation = 1.0 / (light.attenuation.x + light.attenuation.y * distance + light.attenuation.z * distance * distance);
+    if (light.type == SPOT_LIGHT) {
+        // fade out between the inner and the outer cone
+        float cosAngle = dot(-lightDir, normalize(light.direction));
+        attenuation *= clamp((cosAngle - light.outerConeCos) / (light.innerConeCos - light.outerConeCos), 0.0, 1.0);
+    }
+    return attenuation;
+}
+
+// 1.0 when fully lit, 0.0 when fully in shadow
+float calcShadow(Light light, int index) {
+    vec4 lightSpacePosition = light.shadowViewProjection * vec4(fragPosition, 1.0);
+    vec3 projected = lightSpacePosition.xyz / lightSpacePosition.w;
+    if (projected.z > 1.0) {
+        return 1.0;
+    }
+    vec2 uv = projected.xy * 0.5 + 0.5;
+    // average 3x3 neighbouring texels for softer edges
+    vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0).xy);
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadowMap, vec4(uv + vec2(x, y) * texelSize, index, projected.z));
+        }
+    }
+    return lit / 9.0;
+}
+
+// exponential fog everywhere plus thicker one close to the ground, thinning out going up
+vec3 applyFog(vec3 color) {
+    vec3 ray = fragPosition - camera.position;
+    float distance = length(ray);
+    float cameraHeight = fog.baseHeight - camera.position.y;
+    float rise = -ray.y;
+    // height fog's density integrated along the ray, it changes exponentially with height
+    float b = fog.heightFalloff * rise;
+    float heightFactor = abs(b) < 0.0001 ? 1.0 : (1.0 - exp(-b)) / b;
+    float heightFog = fog.heightDensity * exp(-fog.heightFalloff * cameraHeight) * heightFactor;
+    float amount = 1.0 - exp(-(fog.density + heightFog) * distance);
+    return mix(color, fog.color, amount);
+}
@@ -0,0 +1,40 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+struct Light {
+    vec3 position;
+    int type;
+    vec3 direction;
+    float innerConeCos;
+    vec3 ambient;
+    float outerConeCos;
+    vec3 diffuse;
+    vec3 specular;
+    vec3 attenuation; // constant, linear, quadratic
+
+    mat4 shadowViewProjection;
+};
+
+layout(std430, set = 0, binding = 1) readonly buffer LightsSSBO {
+    int count;
+    int shadowCasters; // only the first lights have a shadow map
+    Light light[];
+} lights;
+
+layout(push_constant) uniform Constants {
+    int lightIndex;
+} constants;
+
+// per-vertex data
+layout(location = 0) in vec3 position;
+
+// per-instance data
+layout (location = 6) in mat4 model;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    gl_Position = lights.light[constants.lightIndex].shadowViewProjection * model * vec4(position, 1.0);
+}
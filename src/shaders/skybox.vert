@@ -0,0 +1,43 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform CameraUBO {
+    vec3 position;
+    mat4 view;
+    mat4 projection;
+} camera;
+
+// unit cube around the camera, 2 triangles per side
+const vec3 CORNERS[8] = vec3[](
+    vec3(-1.0, -1.0, -1.0),
+    vec3( 1.0, -1.0, -1.0),
+    vec3(-1.0,  1.0, -1.0),
+    vec3( 1.0,  1.0, -1.0),
+    vec3(-1.0, -1.0,  1.0),
+    vec3( 1.0, -1.0,  1.0),
+    vec3(-1.0,  1.0,  1.0),
+    vec3( 1.0,  1.0,  1.0)
+);
+const int INDICES[36] = int[](
+    0, 1, 2, 2, 1, 3, // -Z
+    4, 6, 5, 5, 6, 7, // +Z
+    0, 2, 4, 4, 2, 6, // -X
+    1, 5, 3, 3, 5, 7, // +X
+    0, 4, 1, 1, 4, 5, // -Y
+    2, 3, 6, 6, 3, 7  // +Y
+);
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+layout(location = 0) out vec3 fragDirection;
+
+void main() {
+    vec3 position = CORNERS[INDICES[gl_VertexIndex]];
+    fragDirection = position;
+    // no translation, the sky is infinitely far away and only turns along with the camera
+    vec4 pos = camera.projection * mat4(mat3(camera.view)) * vec4(position, 1.0);
+    // depth of w / w, so always at the far plane
+    gl_Position = pos.xyww;
+}
@@ -1,12 +1,22 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
+#define DIRECTIONAL_LIGHT 0
+#define POINT_LIGHT 1
+#define SPOT_LIGHT 2
+
 struct Light {
     vec3 position;
+    int type;
+    vec3 direction;
+    float innerConeCos;
+    vec3 ambient;
+    float outerConeCos;
+    vec3 diffuse;
+    vec3 specular;
+    vec3 attenuation; // constant, linear, quadratic
 
-    vec4 ambient;
-    vec4 diffuse;
-    vec4 specular;
+    mat4 shadowViewProjection;
 };
 
 layout(set = 0, binding = 0) uniform CameraUBO {
@@ -15,40 +25,125 @@ layout(set = 0, binding = 0) uniform CameraUBO {
     mat4 projection;
 } camera;
 
-layout(set = 0, binding = 1) uniform LightsUBO {
+layout(std430, set = 0, binding = 1) readonly buffer LightsSSBO {
     int count;
-    Light light[2];
+    int shadowCasters; // only the first lights have a shadow map
+    Light light[];
 } lights;
 
 layout(binding = 2) uniform sampler2D texSampler;
+layout(binding = 3) uniform sampler2DArrayShadow shadowMap;
+layout(binding = 4) uniform sampler2D normalMap; // flat one when the mesh has none
+
+layout(set = 0, binding = 5) uniform FogUBO {
+    vec3 color;
+    float density;
+    float heightDensity;
+    float heightFalloff;
+    float baseHeight; // Y where the height fog is the thickest, up is -Y
+} fog;
 
 layout(location = 0) in vec3 fragPosition;
 layout(location = 1) in vec3 fragNormal;
 layout(location = 2) in vec2 fragTexCoord;
+layout(location = 3) in vec4 fragTangent;
 
 layout(location = 0) out vec4 outColor;
 
-vec4 calcLight(Light light);
+vec3 calcNormal();
+vec3 calcLight(Light light, int index, vec3 normal);
+vec3 calcLightDirection(Light light);
+float calcAttenuation(Light light, vec3 lightDir);
+float calcShadow(Light light, int index);
+vec3 applyFog(vec3 color);
 
 void main() {
-    vec4 result = vec4(0.0);
+    vec3 normal = calcNormal();
+    vec3 result = vec3(0.0);
     for (int i = 0; i < lights.count; i++) {
-        result += calcLight(lights.light[i]);
+        result += calcLight(lights.light[i], i, normal);
     }
-    outColor = result;
+    outColor = vec4(applyFog(result), 1.0);
 }
 
-vec4 calcLight(Light light) {
-    vec4 ambient = light.ambient * texture(texSampler, fragTexCoord);
+// normal from the normal map, turned from tangent into world space
+vec3 calcNormal() {
+    vec3 normal = normalize(fragNormal);
+    vec3 tangent = normalize(fragTangent.xyz - normal * dot(normal, fragTangent.xyz));
+    vec3 bitangent = cross(normal, tangent) * fragTangent.w;
+    vec3 mapped = texture(normalMap, fragTexCoord).xyz * 2.0 - 1.0;
+    return normalize(mat3(tangent, bitangent, normal) * mapped);
+}
 
-    vec3 lightDir = normalize(light.position - fragPosition);
-    //    float diff = max(dot(fragNormal, lightDir), 0.0);
-    //    vec4 diffuse = diff * light.diffuse * texture(texSampler, fragTexCoord);
+vec3 calcLight(Light light, int index, vec3 normal) {
+    vec3 texColor = texture(texSampler, fragTexCoord).rgb;
+    vec3 ambient = light.ambient * texColor;
+
+    vec3 lightDir = calcLightDirection(light);
+    float diff = max(dot(normal, lightDir), 0.0);
+    vec3 diffuse = diff * light.diffuse * texColor;
 
     vec3 viewDir = normalize(camera.position - fragPosition);
     vec3 halfwayDir = normalize(lightDir + viewDir);
-    float spec = max(dot(fragNormal, halfwayDir), 0.0);
-    vec4 specular = spec * light.specular * texture(texSampler, fragTexCoord);
+    float spec = max(dot(normal, halfwayDir), 0.0);
+    vec3 specular = spec * light.specular * texColor;
+
+    float lit = index < lights.shadowCasters ? calcShadow(light, index) : 1.0;
+    return ambient + lit * calcAttenuation(light, lightDir) * (diffuse + specular);
+}
+
+// direction from the fragment towards the light
+vec3 calcLightDirection(Light light) {
+    if (light.type == DIRECTIONAL_LIGHT) {
+        return normalize(-light.direction);
+    }
+    return normalize(light.position - fragPosition);
+}
+
+// how much of the light reaches the fragment, 1.0 for directional lights
+float calcAttenuation(Light light, vec3 lightDir) {
+    if (light.type == DIRECTIONAL_LIGHT) {
+        return 1.0;
+    }
+    float distance = length(light.position - fragPosition);
+    float attenuation = 1.0 / (light.attenuation.x + light.attenuation.y * distance + light.attenuation.z * distance * distance);
+    if (light.type == SPOT_LIGHT) {
+        // fade out between the inner and the outer cone
+        float cosAngle = dot(-lightDir, normalize(light.direction));
+        attenuation *= clamp((cosAngle - light.outerConeCos) / (light.innerConeCos - light.outerConeCos), 0.0, 1.0);
+    }
+    return attenuation;
+}
+
+// 1.0 when fully lit, 0.0 when fully in shadow
+float calcShadow(Light light, int index) {
+    vec4 lightSpacePosition = light.shadowViewProjection * vec4(fragPosition, 1.0);
+    vec3 projected = lightSpacePosition.xyz / lightSpacePosition.w;
+    if (projected.z > 1.0) {
+        return 1.0;
+    }
+    vec2 uv = projected.xy * 0.5 + 0.5;
+    // average 3x3 neighbouring texels for softer edges
+    vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0).xy);
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadowMap, vec4(uv + vec2(x, y) * texelSize, index, projected.z));
+        }
+    }
+    return lit / 9.0;
+}
 
-    return 2.5 * ambient + specular;
+// exponential fog everywhere plus thicker one close to the ground, thinning out going up
+vec3 applyFog(vec3 color) {
+    vec3 ray = fragPosition - camera.position;
+    float distance = length(ray);
+    float cameraHeight = fog.baseHeight - camera.position.y;
+    float rise = -ray.y;
+    // height fog's density integrated along the ray, it changes exponentially with height
+    float b = fog.heightFalloff * rise;
+    float heightFactor = abs(b) < 0.0001 ? 1.0 : (1.0 - exp(-b)) / b;
+    float heightFog = fog.heightDensity * exp(-fog.heightFalloff * cameraHeight) * heightFactor;
+    float amount = 1.0 - exp(-(fog.density + heightFog) * distance);
+    return mix(color, fog.color, amount);
 }
@@ -11,9 +11,10 @@ layout(set = 0, binding = 0) uniform CameraUBO {
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec3 normal;
 layout(location = 2) in vec2 texCoord;
+layout(location = 3) in vec4 tangent;
 
 // per-instance data
-layout (location = 3) in mat4 model;
+layout (location = 4) in mat4 model;
 
 out gl_PerVertex {
     vec4 gl_Position;
@@ -22,11 +23,14 @@ out gl_PerVertex {
 layout(location = 0) out vec3 fragPosition;
 layout(location = 1) out vec3 fragNormal;
 layout(location = 2) out vec2 fragTexCoord;
+layout(location = 3) out vec4 fragTangent;
 
 void main() {
     vec4 pos = model * vec4(position, 1.0);
     gl_Position = camera.projection * camera.view * pos;
-    fragPosition = vec3(gl_Position);
+    fragPosition = vec3(pos);
     fragNormal = normalize(mat3(transpose(inverse(model))) * normal);
     fragTexCoord = texCoord;
+    // tangents run along the surface, so unlike normals they transform just like positions
+    fragTangent = vec4(normalize(mat3(model) * tangent.xyz), tangent.w);
 }
@@ -0,0 +1,22 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+layout(set = 0, binding = 1) uniform sampler2D bloom;
+
+layout(location = 0) in vec2 fragUV;
+
+layout(location = 0) out vec4 outColor;
+
+const float BLOOM_STRENGTH = 0.8;
+const float EXPOSURE = 0.8;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec3 color = texture(scene, fragUV).rgb + BLOOM_STRENGTH * texture(bloom, fragUV).rgb;
+    outColor = vec4(aces(color * EXPOSURE), 1.0);
+}
@@ -0,0 +1,209 @@
+use std::f32::consts::{PI, TAU};
+
+use cgmath::{vec3, InnerSpace, Vector3};
+use image::{Rgba, RgbaImage};
+
+/// Cubemap drawn behind everything else, infinitely far away.
+#[derive(Debug)]
+pub struct Skybox {
+    pub faces: [RgbaImage; 6], // +X, -X, +Y, -Y, +Z, -Z, just like Vulkan's cubemap layers
+}
+
+impl Skybox {
+    /// Same order as the faces, all of them square and of the same size.
+    pub fn from_files(paths: [&str; 6]) -> Result<Self, String> {
+        let mut faces = vec![];
+        for path in paths {
+            let face = image::open(path)
+                .map_err(|error| format!("Failed to open {}: {}", path, error))?
+                .into_rgba8();
+            faces.push((path, face));
+        }
+        Skybox::from_faces(faces)
+    }
+
+    fn from_faces(faces: Vec<(&str, RgbaImage)>) -> Result<Self, String> {
+        let (first_path, first_face) = &faces[0];
+        for (path, face) in faces.iter() {
+            let (width, height) = face.dimensions();
+            if width != height {
+                return Err(format!(
+                    "Skybox face {} is {}x{}, it has to be square",
+                    path, width, height
+                ));
+            }
+            if face.dimensions() != first_face.dimensions() {
+                return Err(format!(
+                    "Skybox face {} is {}x{}, unlike {} which is {}x{}",
+                    path,
+                    width,
+                    height,
+                    first_path,
+                    first_face.width(),
+                    first_face.height()
+                ));
+            }
+        }
+        let faces: [RgbaImage; 6] = faces
+            .into_iter()
+            .map(|(_, face)| face)
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| "A skybox needs 6 faces".to_string())?;
+        Ok(Skybox { faces })
+    }
+
+    /// Image covers 360 degrees horizontally and 180 vertically, with the sky's zenith at the top.
+    pub fn from_equirectangular(image: &RgbaImage, face_size: u32) -> Self {
+        let faces = [0, 1, 2, 3, 4, 5].map(|face| {
+            RgbaImage::from_fn(face_size, face_size, |x, y| {
+                let s = (x as f32 + 0.5) / face_size as f32;
+                let t = (y as f32 + 0.5) / face_size as f32;
+                let (u, v) = equirectangular_coordinates(face_direction(face, s, t));
+                sample_bilinear(image, u, v)
+            })
+        });
+        Skybox { faces }
+    }
+}
+
+/// Direction in the world shown by the given point of a cubemap face, as Vulkan sees it.
+pub(crate) fn face_direction(face: usize, s: f32, t: f32) -> Vector3<f32> {
+    let a = 2. * s - 1.;
+    let b = 2. * t - 1.;
+    let direction = match face {
+        0 => vec3(1., -b, -a),
+        1 => vec3(-1., -b, a),
+        2 => vec3(a, 1., b),
+        3 => vec3(a, -1., -b),
+        4 => vec3(a, -b, 1.),
+        5 => vec3(-a, -b, -1.),
+        _ => panic!("Cubemaps have only 6 faces"),
+    };
+    direction.normalize()
+}
+
+/// Position in an equirectangular image, both in [0; 1], of the given direction. Up is -Y, so
+/// zenith is at the top of the image.
+pub(crate) fn equirectangular_coordinates(direction: Vector3<f32>) -> (f32, f32) {
+    let longitude = direction.z.atan2(direction.x);
+    let latitude = (-direction.y).clamp(-1., 1.).asin();
+    ((longitude / TAU + 0.5).rem_euclid(1.), 0.5 - latitude / PI)
+}
+
+/// Wraps around horizontally, so there's no seam where the image's edges meet.
+fn sample_bilinear(image: &RgbaImage, u: f32, v: f32) -> Rgba<u8> {
+    let (width, height) = image.dimensions();
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0., (height - 1) as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let pixel = |x: f32, y: f32| {
+        let x = (x as i64).rem_euclid(width as i64) as u32;
+        let y = (y as u32).min(height - 1);
+        image.get_pixel(x, y).0.map(|channel| channel as f32)
+    };
+    let top = [pixel(x0, y0), pixel(x0 + 1., y0)];
+    let bottom = [pixel(x0, y0 + 1.), pixel(x0 + 1., y0 + 1.)];
+    let mut result = [0; 4];
+    for c in 0..4 {
+        let upper = top[0][c] + (top[1][c] - top[0][c]) * fx;
+        let lower = bottom[0][c] + (bottom[1][c] - bottom[0][c]) * fx;
+        result[c] = (upper + (lower - upper) * fy).round() as u8;
+    }
+    Rgba(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{vec3, InnerSpace};
+    use image::{Rgba, RgbaImage};
+    use rstest::*;
+
+    use crate::skybox::{equirectangular_coordinates, face_direction, Skybox};
+
+    #[rstest(
+        face,
+        expected,
+        case(0, vec3(1., 0., 0.)),
+        case(1, vec3(-1., 0., 0.)),
+        case(2, vec3(0., 1., 0.)),
+        case(3, vec3(0., -1., 0.)),
+        case(4, vec3(0., 0., 1.)),
+        case(5, vec3(0., 0., -1.))
+    )]
+    fn faces_look_along_their_axes(face: usize, expected: cgmath::Vector3<f32>) {
+        assert!((face_direction(face, 0.5, 0.5) - expected).magnitude() < 0.0001);
+    }
+
+    #[test]
+    fn zenith_is_at_the_top() {
+        let (_, v) = equirectangular_coordinates(vec3(0., -1., 0.));
+        assert!(v.abs() < 0.0001, "{}", v);
+        let (_, v) = equirectangular_coordinates(vec3(1., 0., 0.));
+        assert!((v - 0.5).abs() < 0.0001, "{}", v);
+    }
+
+    fn faces(sizes: [(u32, u32); 6]) -> Vec<(&'static str, RgbaImage)> {
+        let paths = ["px.png", "nx.png", "py.png", "ny.png", "pz.png", "nz.png"];
+        paths
+            .into_iter()
+            .zip(sizes)
+            .map(|(path, (width, height))| (path, RgbaImage::new(width, height)))
+            .collect()
+    }
+
+    #[test]
+    fn takes_square_faces_of_the_same_size() {
+        let skybox = Skybox::from_faces(faces([(4, 4); 6])).unwrap();
+        assert!(skybox.faces.iter().all(|face| face.dimensions() == (4, 4)));
+    }
+
+    #[rstest(
+        sizes,
+        expected,
+        case(
+            [(4, 4), (4, 4), (4, 2), (4, 4), (4, 4), (4, 4)],
+            "Skybox face py.png is 4x2, it has to be square"
+        ),
+        case(
+            [(4, 4), (4, 4), (4, 4), (4, 4), (8, 8), (4, 4)],
+            "Skybox face pz.png is 8x8, unlike px.png which is 4x4"
+        )
+    )]
+    fn rejects_mismatched_faces(sizes: [(u32, u32); 6], expected: &str) {
+        assert_eq!(Skybox::from_faces(faces(sizes)).unwrap_err(), expected);
+    }
+
+    #[test]
+    fn tells_which_file_is_missing() {
+        let paths = ["missing/px.png"; 6];
+        let error = Skybox::from_files(paths).unwrap_err();
+        assert!(
+            error.starts_with("Failed to open missing/px.png: "),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn sky_goes_to_upper_faces() {
+        // bright sky above the horizon, dark ground below it
+        let image = RgbaImage::from_fn(64, 32, |_, y| {
+            if y < 16 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        });
+        let skybox = Skybox::from_equirectangular(&image, 8);
+        // -Y is up
+        assert!(skybox.faces[3].pixels().all(|p| p[0] == 255));
+        assert!(skybox.faces[2].pixels().all(|p| p[0] == 0));
+        // Vulkan puts +Y at the top of the side faces, that's down here
+        for face in [0, 1, 4, 5] {
+            assert_eq!(skybox.faces[face].get_pixel(3, 1)[0], 0);
+            assert_eq!(skybox.faces[face].get_pixel(3, 6)[0], 255);
+        }
+    }
+}
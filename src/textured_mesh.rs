@@ -1,14 +1,18 @@
 use ash::vk;
-use cgmath::{Matrix4, SquareMatrix};
-use image::RgbaImage;
+use cgmath::{vec3, InnerSpace, Matrix4, SquareMatrix, Vector3, Zero};
+use image::{Rgba, RgbaImage};
 use memoffset::offset_of;
 
+/// Tangent of vertices that don't have one, it gets generated from the UVs.
+pub const NO_TANGENT: [f32; 4] = [0., 0., 0., 0.];
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct TexturedVertex {
     pub pos: [f32; 3],
     pub norm: [f32; 3],
     pub texture_coordinates: [f32; 2],
+    pub tangent: [f32; 4], // points along U, w is the handedness of the bitangent pointing along V
 }
 
 impl TexturedVertex {
@@ -40,6 +44,12 @@ impl TexturedVertex {
                 format: vk::Format::R32G32_SFLOAT, // aka vec2
                 offset: offset_of!(Self, texture_coordinates) as u32,
             },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 3,
+                format: vk::Format::R32G32B32A32_SFLOAT, // aka vec4
+                offset: offset_of!(Self, tangent) as u32,
+            },
         ]
     }
 }
@@ -49,7 +59,97 @@ pub struct TexturedMesh {
     pub vertices: Vec<TexturedVertex>,
     pub indices: Vec<u32>,
     pub instances: Vec<InstanceData>,
-    pub texture: RgbaImage, // TODO: do not use external structures
+    pub texture: RgbaImage,            // TODO: do not use external structures
+    pub normal_map: Option<RgbaImage>, // in tangent space, surface stays flat without it
+}
+
+impl TexturedMesh {
+    /// Vertices with tangents generated for the ones that have NO_TANGENT, from UVs of their
+    /// triangles.
+    pub fn vertices_with_tangents(&self) -> Vec<TexturedVertex> {
+        let mut tangents = vec![Vector3::zero(); self.vertices.len()];
+        let mut bitangents = vec![Vector3::zero(); self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [v0, v1, v2] = [0, 1, 2].map(|i| self.vertices[triangle[i] as usize]);
+            let edge1 = Vector3::from(v1.pos) - Vector3::from(v0.pos);
+            let edge2 = Vector3::from(v2.pos) - Vector3::from(v0.pos);
+            let du1 = v1.texture_coordinates[0] - v0.texture_coordinates[0];
+            let dv1 = v1.texture_coordinates[1] - v0.texture_coordinates[1];
+            let du2 = v2.texture_coordinates[0] - v0.texture_coordinates[0];
+            let dv2 = v2.texture_coordinates[1] - v0.texture_coordinates[1];
+            let determinant = du1 * dv2 - du2 * dv1;
+            if determinant.abs() < f32::EPSILON {
+                continue; // UVs don't span the triangle
+            }
+            let tangent = (edge1 * dv2 - edge2 * dv1) / determinant;
+            let bitangent = (edge2 * du1 - edge1 * du2) / determinant;
+            for &index in triangle {
+                tangents[index as usize] += tangent;
+                bitangents[index as usize] += bitangent;
+            }
+        }
+
+        self.vertices
+            .iter()
+            .enumerate()
+            .map(|(i, vertex)| {
+                if vertex.tangent != NO_TANGENT {
+                    return *vertex;
+                }
+                let normal = Vector3::from(vertex.norm).normalize();
+                // any direction along the surface will do when UVs don't tell
+                let mut tangent = tangents[i] - normal * normal.dot(tangents[i]);
+                if tangent.magnitude2() < f32::EPSILON {
+                    tangent = any_perpendicular(normal);
+                }
+                let tangent = tangent.normalize();
+                let handedness = if normal.cross(tangent).dot(bitangents[i]) < 0. {
+                    -1.
+                } else {
+                    1.
+                };
+                TexturedVertex {
+                    tangent: [tangent.x, tangent.y, tangent.z, handedness],
+                    ..*vertex
+                }
+            })
+            .collect()
+    }
+}
+
+fn any_perpendicular(normal: Vector3<f32>) -> Vector3<f32> {
+    let axis = if normal.x.abs() < 0.9 {
+        vec3(1., 0., 0.)
+    } else {
+        vec3(0., 1., 0.)
+    };
+    normal.cross(axis)
+}
+
+/// Normal map that doesn't bend normals at all, for meshes without one.
+pub fn flat_normal_map() -> RgbaImage {
+    RgbaImage::from_pixel(1, 1, Rgba([128, 128, 255, 255]))
+}
+
+/// Treats brightness of the image as height and turns its slopes into a tangent space normal map.
+/// Wraps around the edges, so seamless textures give seamless normal maps.
+pub fn normal_map_from_height(image: &RgbaImage, strength: f32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let height_at = |x: i64, y: i64| {
+        let pixel = image.get_pixel(
+            x.rem_euclid(width as i64) as u32,
+            y.rem_euclid(height as i64) as u32,
+        );
+        (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32) / 255.
+    };
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let slope_u = (height_at(x + 1, y) - height_at(x - 1, y)) / 2.;
+        let slope_v = (height_at(x, y + 1) - height_at(x, y - 1)) / 2.;
+        let normal = vec3(-strength * slope_u, -strength * slope_v, 1.).normalize();
+        let encode = |value: f32| ((value * 0.5 + 0.5) * 255.).round() as u8;
+        Rgba([encode(normal.x), encode(normal.y), encode(normal.z), 255])
+    })
 }
 
 #[repr(C)]
@@ -73,25 +173,25 @@ impl InstanceData {
             // need four because I'm sending a 4x4 matrix
             vk::VertexInputAttributeDescription {
                 binding: 1,
-                location: 3,
+                location: 4,
                 format: vk::Format::R32G32B32A32_SFLOAT, // aka vec4
                 offset: offset_of!(Self, model) as u32 + 0 * matrix_quarter,
             },
             vk::VertexInputAttributeDescription {
                 binding: 1,
-                location: 4,
+                location: 5,
                 format: vk::Format::R32G32B32A32_SFLOAT, // aka vec4
                 offset: offset_of!(Self, model) as u32 + 1 * matrix_quarter,
             },
             vk::VertexInputAttributeDescription {
                 binding: 1,
-                location: 5,
+                location: 6,
                 format: vk::Format::R32G32B32A32_SFLOAT, // aka vec4
                 offset: offset_of!(Self, model) as u32 + 2 * matrix_quarter,
             },
             vk::VertexInputAttributeDescription {
                 binding: 1,
-                location: 6,
+                location: 7,
                 format: vk::Format::R32G32B32A32_SFLOAT, // aka vec4
                 offset: offset_of!(Self, model) as u32 + 3 * matrix_quarter,
             },
@@ -106,3 +206,76 @@ impl Default for InstanceData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use rstest::*;
+
+    use crate::textured_mesh::{
+        flat_normal_map, normal_map_from_height, TexturedMesh, TexturedVertex, NO_TANGENT,
+    };
+
+    fn vertex(pos: [f32; 3], texture_coordinates: [f32; 2]) -> TexturedVertex {
+        TexturedVertex {
+            pos,
+            norm: [0., -1., 0.],
+            texture_coordinates,
+            tangent: NO_TANGENT,
+        }
+    }
+
+    fn quad(flip_v: bool) -> TexturedMesh {
+        let v = |v: f32| if flip_v { 1. - v } else { v };
+        TexturedMesh {
+            vertices: vec![
+                vertex([-1., 0., -1.], [0., v(0.)]),
+                vertex([-1., 0., 1.], [0., v(1.)]),
+                vertex([1., 0., -1.], [1., v(0.)]),
+                vertex([1., 0., 1.], [1., v(1.)]),
+            ],
+            indices: vec![0, 2, 1, 1, 2, 3],
+            instances: vec![],
+            texture: flat_normal_map(),
+            normal_map: None,
+        }
+    }
+
+    #[rstest(flip_v, handedness, case(false, 1.), case(true, -1.))]
+    fn tangents_follow_uvs(flip_v: bool, handedness: f32) {
+        for vertex in quad(flip_v).vertices_with_tangents() {
+            assert_eq!(vertex.tangent, [1., 0., 0., handedness]);
+        }
+    }
+
+    #[test]
+    fn supplied_tangents_are_kept() {
+        let mut mesh = quad(false);
+        mesh.vertices[2].tangent = [0., 0., 1., -1.];
+        let vertices = mesh.vertices_with_tangents();
+        assert_eq!(vertices[2].tangent, [0., 0., 1., -1.]);
+        assert_eq!(vertices[1].tangent, [1., 0., 0., 1.]);
+    }
+
+    #[test]
+    fn flat_image_gives_flat_normals() {
+        let image = RgbaImage::from_pixel(4, 4, Rgba([90, 90, 90, 255]));
+        let normal_map = normal_map_from_height(&image, 5.);
+        for pixel in normal_map.pixels() {
+            assert_eq!(pixel, flat_normal_map().get_pixel(0, 0));
+        }
+    }
+
+    #[test]
+    fn normals_lean_away_from_slopes() {
+        // brighter, so higher, towards bigger U
+        let image = RgbaImage::from_fn(8, 8, |x, _| {
+            Rgba([(x * 30) as u8, (x * 30) as u8, (x * 30) as u8, 255])
+        });
+        let normal_map = normal_map_from_height(&image, 5.);
+        let pixel = normal_map.get_pixel(3, 3);
+        assert!(pixel[0] < 128, "{:?}", pixel);
+        assert_eq!(pixel[1], 128);
+        assert!(pixel[2] > 128, "{:?}", pixel);
+    }
+}
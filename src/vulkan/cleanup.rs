@@ -0,0 +1,105 @@
+type Step<'a, T> = Box<dyn FnOnce(&T) + 'a>;
+
+/// Destroys whatever a setup created so far when it fails halfway, so that nothing is left behind
+/// and it can be tried again, maybe on another GPU but with the same window.
+pub(crate) struct Cleanup<'a, T> {
+    owner: &'a T, // what everything got created with, like the core
+    steps: Vec<Step<'a, T>>,
+}
+
+impl<'a, T> Cleanup<'a, T> {
+    pub(crate) fn new(owner: &'a T) -> Self {
+        Cleanup {
+            owner,
+            steps: vec![],
+        }
+    }
+
+    /// Runs when the cleanup is dropped before being disarmed, in reverse order of being added.
+    pub(crate) fn push(&mut self, step: impl FnOnce(&T) + 'a) {
+        self.steps.push(Box::new(step));
+    }
+
+    /// The setup succeeded, everything is kept.
+    pub(crate) fn disarm(mut self) {
+        self.steps.clear();
+    }
+}
+
+impl<T> Drop for Cleanup<'_, T> {
+    fn drop(&mut self) {
+        while let Some(step) = self.steps.pop() {
+            step(self.owner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use rstest::*;
+
+    use crate::vulkan::cleanup::Cleanup;
+
+    // like a window's surface, there can only be one at a time
+    struct Exclusive {
+        in_use: Cell<bool>,
+    }
+
+    impl Exclusive {
+        fn acquire(&self) -> Result<(), &'static str> {
+            if self.in_use.replace(true) {
+                Err("already in use")
+            } else {
+                Ok(())
+            }
+        }
+
+        fn release(&self) {
+            self.in_use.set(false);
+        }
+    }
+
+    fn setup(exclusive: &Exclusive, fail: bool) -> Result<(), &'static str> {
+        let mut cleanup = Cleanup::new(exclusive);
+        exclusive.acquire()?;
+        cleanup.push(|exclusive| exclusive.release());
+        if fail {
+            return Err("failed halfway");
+        }
+        cleanup.disarm();
+        Ok(())
+    }
+
+    #[rstest]
+    fn runs_steps_in_reverse_order() {
+        let order = RefCell::new(vec![]);
+        {
+            let mut cleanup = Cleanup::new(&order);
+            for step in 0..3 {
+                cleanup.push(move |order| order.borrow_mut().push(step));
+            }
+        }
+        assert_eq!(*order.borrow(), vec![2, 1, 0]);
+    }
+
+    #[rstest]
+    fn keeps_everything_when_disarmed() {
+        let ran = Cell::new(false);
+        let mut cleanup = Cleanup::new(&ran);
+        cleanup.push(|ran| ran.set(true));
+        cleanup.disarm();
+        assert!(!ran.get());
+    }
+
+    #[rstest]
+    fn failed_setup_can_be_tried_again() {
+        let exclusive = Exclusive {
+            in_use: Cell::new(false),
+        };
+        assert_eq!(setup(&exclusive, true), Err("failed halfway"));
+        assert_eq!(setup(&exclusive, false), Ok(()));
+        assert!(exclusive.in_use.get());
+    }
+}
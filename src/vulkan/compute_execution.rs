@@ -1,83 +1,118 @@
 use std::ptr;
 
 use ash::vk;
+use memoffset::offset_of;
 
 use crate::scene::snow::{Snowflake, MAX_SNOWFLAKES};
-use crate::vulkan::compute_setup::VulkanComputeSetup;
+use crate::vulkan::compute_setup::{SnowConstants, VulkanComputeSetup};
 use crate::vulkan::core::VulkanCore;
+use crate::vulkan::error::{Context, VulkanResult};
+use crate::vulkan::memory::Allocation;
 
 const WORKGROUP_SIZE: u32 = 64;
 
+/// Everything one frame's calculations write into or get recorded in, so that the next frame can
+/// be calculated while this one is still being drawn.
+struct ComputeFrame {
+    descriptor_set: vk::DescriptorSet,
+    command_buffer: vk::CommandBuffer,
+    drawing_buffer: vk::Buffer,      // owned by the snow mesh
+    draw_command_buffer: vk::Buffer, // owned by the snow mesh
+    calculation: u64,                // timeline value signalled when its last one is done
+}
+
+/// What snow.comp reads and writes for one frame, in the order of its bindings.
+struct SnowBuffers {
+    snowflakes: vk::Buffer,
+    snowflakes_size: usize,
+    drawing: vk::Buffer,
+    drawing_size: usize,
+    draw_command: vk::Buffer,
+}
+
 pub struct VulkanComputeExecution {
     core: VulkanCore,
     compute_setup: VulkanComputeSetup,
 
-    descriptor_set: vk::DescriptorSet,
-    command_buffer: vk::CommandBuffer,
+    frames: Vec<ComputeFrame>, // one per frame in flight, like the snow meshes drawn from them
 
     snowflakes_buffer: vk::Buffer,
-    snowflakes_buffer_memory: vk::DeviceMemory,
+    snowflakes_buffer_memory: Allocation,
 
-    fence: vk::Fence,
+    timeline_semaphore: vk::Semaphore, // its value is the number of finished calculations
+    calculations: u64,                 // submitted so far
 }
 
 impl VulkanComputeExecution {
     pub fn new(
         core: VulkanCore,
         compute_setup: VulkanComputeSetup,
-        snowflakes: &Vec<Snowflake>,
-        drawing_buffer: vk::Buffer,
+        snowflakes: &[Snowflake],
+        snow_buffers: &[(vk::Buffer, vk::Buffer)], // drawing and draw command buffer per frame
         drawing_buffer_size: usize,
-    ) -> Self {
+    ) -> VulkanResult<Self> {
         let (snowflakes_buffer, snowflakes_buffer_memory) = core.create_data_buffer(
             compute_setup.command_pool,
             vk::BufferUsageFlags::STORAGE_BUFFER,
             snowflakes,
-        );
-        let descriptor_set = VulkanComputeExecution::create_descriptor_set(
-            &core.device,
-            compute_setup.descriptor_pool,
-            compute_setup.descriptor_set_layout,
-            snowflakes_buffer,
-            std::mem::size_of::<Snowflake>() * snowflakes.len(),
-            drawing_buffer,
-            drawing_buffer_size,
-        );
-        let command_buffer = VulkanComputeExecution::create_command_buffer(
+            "snowflakes",
+        )?;
+        let command_buffers = VulkanComputeExecution::create_command_buffers(
             &core,
             &compute_setup,
-            descriptor_set,
-            0.0,
-        );
-        let fence = core.create_fence();
-
-        VulkanComputeExecution {
+            snow_buffers.len(),
+        )?;
+        let frames = snow_buffers
+            .iter()
+            .zip(command_buffers)
+            .map(|(&(drawing_buffer, draw_command_buffer), command_buffer)| {
+                let descriptor_set = VulkanComputeExecution::create_descriptor_set(
+                    &core.device,
+                    compute_setup.descriptor_pool,
+                    compute_setup.descriptor_set_layout,
+                    &SnowBuffers {
+                        snowflakes: snowflakes_buffer,
+                        snowflakes_size: std::mem::size_of_val(snowflakes),
+                        drawing: drawing_buffer,
+                        drawing_size: drawing_buffer_size,
+                        draw_command: draw_command_buffer,
+                    },
+                )?;
+                Ok(ComputeFrame {
+                    descriptor_set,
+                    command_buffer,
+                    drawing_buffer,
+                    draw_command_buffer,
+                    calculation: 0,
+                })
+            })
+            .collect::<VulkanResult<Vec<ComputeFrame>>>()?;
+        let timeline_semaphore = core.create_timeline_semaphore()?;
+
+        Ok(VulkanComputeExecution {
             core,
             compute_setup,
 
-            descriptor_set,
-            command_buffer,
+            frames,
 
             snowflakes_buffer,
             snowflakes_buffer_memory,
 
-            fence,
-        }
+            timeline_semaphore,
+            calculations: 0,
+        })
     }
 
     fn create_descriptor_set(
         device: &ash::Device,
         descriptor_pool: vk::DescriptorPool,
         descriptor_set_layout: vk::DescriptorSetLayout,
-        snowflakes_buffer: vk::Buffer,
-        snowflakes_buffer_size: usize,
-        drawing_buffer: vk::Buffer,
-        drawing_buffer_size: usize,
-    ) -> vk::DescriptorSet {
+        buffers: &SnowBuffers,
+    ) -> VulkanResult<vk::DescriptorSet> {
         let descriptor_set_layouts = [descriptor_set_layout];
         let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo {
             descriptor_pool,
-            descriptor_set_count: 1 as u32,
+            descriptor_set_count: 1,
             p_set_layouts: descriptor_set_layouts.as_ptr(),
             ..Default::default()
         };
@@ -85,20 +120,25 @@ impl VulkanComputeExecution {
         let descriptor_sets = unsafe {
             device
                 .allocate_descriptor_sets(&descriptor_set_allocate_info)
-                .expect("Failed to allocate descriptor sets!")
+                .context("Failed to allocate descriptor sets")?
         };
 
         for &descritptor_set in descriptor_sets.iter() {
             let descriptor_buffer_info = [
                 vk::DescriptorBufferInfo {
-                    buffer: snowflakes_buffer,
+                    buffer: buffers.snowflakes,
+                    offset: 0,
+                    range: buffers.snowflakes_size as u64,
+                },
+                vk::DescriptorBufferInfo {
+                    buffer: buffers.drawing,
                     offset: 0,
-                    range: snowflakes_buffer_size as u64,
+                    range: buffers.drawing_size as u64,
                 },
                 vk::DescriptorBufferInfo {
-                    buffer: drawing_buffer,
+                    buffer: buffers.draw_command,
                     offset: 0,
-                    range: drawing_buffer_size as u64,
+                    range: std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u64,
                 },
             ];
 
@@ -119,47 +159,96 @@ impl VulkanComputeExecution {
             }
         }
 
-        descriptor_sets[0]
+        Ok(descriptor_sets[0])
     }
 
-    fn create_command_buffer(
+    fn create_command_buffers(
         core: &VulkanCore,
         compute_setup: &VulkanComputeSetup,
-        descriptor_set: vk::DescriptorSet,
-        last_frame_time_secs: f32,
-    ) -> vk::CommandBuffer {
-        let device = &core.device;
+        count: usize,
+    ) -> VulkanResult<Vec<vk::CommandBuffer>> {
         let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
-            command_buffer_count: 1,
+            command_buffer_count: count as u32,
             command_pool: compute_setup.command_pool,
             level: vk::CommandBufferLevel::PRIMARY,
             ..Default::default()
         };
 
-        let command_buffer = unsafe {
-            device
+        let command_buffers = unsafe {
+            core.device
                 .allocate_command_buffers(&command_buffer_allocate_info)
-                .expect("Failed to allocate Command Buffers!")[0]
+                .context("Failed to allocate Command Buffers")?
         };
+        for (i, &command_buffer) in command_buffers.iter().enumerate() {
+            core.debug_utils
+                .name(command_buffer, &format!("snow commands for frame {}", i));
+        }
+        Ok(command_buffers)
+    }
+
+    fn record_command_buffer(
+        &self,
+        frame: &ComputeFrame,
+        constants: &SnowConstants,
+    ) -> VulkanResult<()> {
+        let device = &self.core.device;
+        let compute_setup = &self.compute_setup;
+        let command_buffer = frame.command_buffer;
+        let draw_command_buffer = frame.draw_command_buffer;
 
         let command_buffer_begin_info = vk::CommandBufferBeginInfo {
             p_inheritance_info: ptr::null(),
-            flags: vk::CommandBufferUsageFlags::SIMULTANEOUS_USE,
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
             ..Default::default()
         };
 
         unsafe {
+            device
+                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                .context("Failed to reset Command Buffer")?;
             device
                 .begin_command_buffer(command_buffer, &command_buffer_begin_info)
-                .expect("Failed to begin recording Command Buffer at beginning!");
+                .context("Failed to begin recording Command Buffer at beginning")?;
 
+            // visible snowflakes get counted from scratch every time
+            device.cmd_fill_buffer(
+                command_buffer,
+                draw_command_buffer,
+                offset_of!(vk::DrawIndexedIndirectCommand, instance_count) as vk::DeviceSize,
+                std::mem::size_of::<u32>() as vk::DeviceSize,
+                0,
+            );
+            // the previous frame's calculations might still be moving the same snowflakes
+            let barriers = [
+                buffer_barrier(
+                    draw_command_buffer,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                ),
+                buffer_barrier(
+                    self.snowflakes_buffer,
+                    vk::AccessFlags::SHADER_WRITE,
+                    vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                ),
+            ];
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &barriers,
+                &[],
+            );
+
+            self.core.debug_utils.begin_label(command_buffer, "snow");
             device.cmd_bind_pipeline(
                 command_buffer,
                 vk::PipelineBindPoint::COMPUTE,
                 compute_setup.pipeline,
             );
 
-            let descriptor_sets_to_bind = [descriptor_set];
+            let descriptor_sets_to_bind = [frame.descriptor_set];
             device.cmd_bind_descriptor_sets(
                 command_buffer,
                 vk::PipelineBindPoint::COMPUTE,
@@ -169,13 +258,16 @@ impl VulkanComputeExecution {
                 &[],
             );
 
-            let constants = last_frame_time_secs.to_le_bytes();
+            let constants = std::slice::from_raw_parts(
+                (constants as *const SnowConstants) as *const u8,
+                std::mem::size_of::<SnowConstants>(),
+            );
             device.cmd_push_constants(
                 command_buffer,
                 compute_setup.pipeline_layout,
                 vk::ShaderStageFlags::COMPUTE,
                 0,
-                &constants,
+                constants,
             );
 
             device.cmd_dispatch(
@@ -184,75 +276,121 @@ impl VulkanComputeExecution {
                 1,
                 1,
             );
+            self.core.debug_utils.end_label(command_buffer);
+
+            // graphics uses the same queue family, see VulkanCore::find_queue_family, so no
+            // ownership transfer, only making the writes visible to vertex input
+            let barriers = [
+                buffer_barrier(
+                    frame.drawing_buffer,
+                    vk::AccessFlags::SHADER_WRITE,
+                    vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+                ),
+                buffer_barrier(
+                    draw_command_buffer,
+                    vk::AccessFlags::SHADER_WRITE,
+                    vk::AccessFlags::INDIRECT_COMMAND_READ,
+                ),
+            ];
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::DependencyFlags::empty(),
+                &[],
+                &barriers,
+                &[],
+            );
 
             device
                 .end_command_buffer(command_buffer)
-                .expect("Failed to record Command Buffer at Ending!");
+                .context("Failed to record Command Buffer at Ending")?;
         }
-
-        command_buffer
+        Ok(())
     }
 
+    /// Calculates snow for that frame in flight. Returns the timeline semaphore and the value it
+    /// gets once done, for drawing to wait for. Other frames can still be being calculated or drawn
+    /// in the meantime, but not the previous one in the same slot, drawing waited for it already.
     pub fn do_calculations(
         &mut self,
-        snow_calculated_semaphore: vk::Semaphore,
+        frame_index: usize,
         last_frame_time_secs: f32,
-    ) {
-        let new_command_buffer = VulkanComputeExecution::create_command_buffer(
-            &self.core,
-            &self.compute_setup,
-            self.descriptor_set,
-            last_frame_time_secs,
-        );
-        let command_buffers = [new_command_buffer];
-
-        let wait_fences = [self.fence];
+        frustum: [[f32; 4]; 6],
+    ) -> VulkanResult<(vk::Semaphore, u64)> {
+        // only this frame's previous calculations have to be done, to record its commands again
+        let semaphores = [self.timeline_semaphore];
+        let values = [self.frames[frame_index].calculation];
+        let semaphore_wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+        self.core.wait_semaphores(&semaphore_wait_info, u64::MAX)?;
+
+        let frame = &self.frames[frame_index];
+        self.record_command_buffer(
+            frame,
+            &SnowConstants {
+                frustum,
+                last_frame_time_secs,
+            },
+        )?;
+        let command_buffers = [frame.command_buffer];
+
+        self.calculations += 1;
+        let signal_semaphores = [self.timeline_semaphore];
+        let signal_values = [self.calculations];
+        let mut timeline_submit_info =
+            vk::TimelineSemaphoreSubmitInfo::default().signal_semaphore_values(&signal_values);
+        let submit_infos = [vk::SubmitInfo::default()
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores)
+            .push_next(&mut timeline_submit_info)];
         unsafe {
             self.core
                 .device
-                .wait_for_fences(&wait_fences, true, u64::MAX)
-                .expect("Failed to wait for Fence!");
-
-            // only now I'm sure it's not used any more
-            self.core
-                .device
-                .free_command_buffers(self.compute_setup.command_pool, &vec![self.command_buffer]);
+                .queue_submit(self.core.compute_queue, &submit_infos, vk::Fence::null())
+                .context("Failed to execute queue submit")?;
         }
+        self.frames[frame_index].calculation = self.calculations;
 
-        self.command_buffer = new_command_buffer;
-
-        let wait_stages = [vk::PipelineStageFlags::COMPUTE_SHADER];
-        let signal_semaphores = [snow_calculated_semaphore];
-        let submit_infos = [vk::SubmitInfo {
-            wait_semaphore_count: 0,
-            p_wait_semaphores: ptr::null(),
-            p_wait_dst_stage_mask: wait_stages.as_ptr(),
-            command_buffer_count: command_buffers.len() as u32,
-            p_command_buffers: command_buffers.as_ptr(),
-            signal_semaphore_count: signal_semaphores.len() as u32,
-            p_signal_semaphores: signal_semaphores.as_ptr(),
-            ..Default::default()
-        }];
-        unsafe {
-            self.core
-                .device
-                .reset_fences(&wait_fences)
-                .expect("Failed to reset Fence!");
+        Ok((self.timeline_semaphore, self.calculations))
+    }
 
-            self.core
-                .device
-                .queue_submit(self.core.compute_queue, &submit_infos, self.fence)
-                .expect("Failed to execute queue submit.");
-        }
+    /// Its pipeline got recreated.
+    #[cfg(feature = "hot-reload")]
+    pub fn set_compute_setup(&mut self, compute_setup: VulkanComputeSetup) {
+        self.compute_setup = compute_setup;
     }
 
     pub fn drop(&self, compute_setup: &VulkanComputeSetup) {
         unsafe {
             let device = &self.core.device;
             device.destroy_buffer(self.snowflakes_buffer, None);
-            device.free_memory(self.snowflakes_buffer_memory, None);
-            device.free_command_buffers(compute_setup.command_pool, &vec![self.command_buffer]);
-            device.destroy_fence(self.fence, None);
+            self.core.free_memory(self.snowflakes_buffer_memory);
+            let command_buffers: Vec<vk::CommandBuffer> = self
+                .frames
+                .iter()
+                .map(|frame| frame.command_buffer)
+                .collect();
+            device.free_command_buffers(compute_setup.command_pool, &command_buffers);
+            device.destroy_semaphore(self.timeline_semaphore, None);
         }
     }
 }
+
+fn buffer_barrier(
+    buffer: vk::Buffer,
+    src_access_mask: vk::AccessFlags,
+    dst_access_mask: vk::AccessFlags,
+) -> vk::BufferMemoryBarrier<'static> {
+    vk::BufferMemoryBarrier {
+        src_access_mask,
+        dst_access_mask,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        buffer,
+        offset: 0,
+        size: vk::WHOLE_SIZE,
+        ..Default::default()
+    }
+}
@@ -2,7 +2,16 @@ use std::ffi::CString;
 
 use ash::vk;
 
+use crate::vulkan::cleanup::Cleanup;
 use crate::vulkan::core::VulkanCore;
+use crate::vulkan::error::{Context, VulkanResult};
+
+/// Must match snow.comp's push constants.
+#[repr(C)]
+pub struct SnowConstants {
+    pub frustum: [[f32; 4]; 6],
+    pub last_frame_time_secs: f32,
+}
 
 #[derive(Clone)]
 pub struct VulkanComputeSetup {
@@ -17,14 +26,30 @@ pub struct VulkanComputeSetup {
 }
 
 impl VulkanComputeSetup {
-    pub fn new(core: VulkanCore) -> Self {
-        let descriptor_set_layout = VulkanComputeSetup::create_descriptor_set_layout(&core.device);
+    /// With descriptor sets for that many frames calculated at once.
+    pub fn new(core: VulkanCore, frames: usize) -> VulkanResult<Self> {
+        let mut cleanup = Cleanup::new(&core);
+        let descriptor_set_layout = VulkanComputeSetup::create_descriptor_set_layout(&core.device)?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_descriptor_set_layout(descriptor_set_layout, None)
+        });
         let (pipeline, pipeline_layout) =
-            VulkanComputeSetup::create_pipeline(&core, descriptor_set_layout);
-        let command_pool = core.create_command_pool(core.queue_family.compute_family.unwrap());
-        let descriptor_pool = VulkanComputeSetup::create_descriptor_pool(&core.device);
-
-        VulkanComputeSetup {
+            VulkanComputeSetup::create_pipeline(&core, descriptor_set_layout)?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_pipeline(pipeline, None);
+            core.device.destroy_pipeline_layout(pipeline_layout, None);
+        });
+        // command buffers get re-recorded every frame
+        let command_pool = core.create_command_pool(
+            core.queue_family.compute_family.unwrap(),
+            vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+        )?;
+        cleanup.push(move |core| unsafe { core.device.destroy_command_pool(command_pool, None) });
+        let descriptor_pool = VulkanComputeSetup::create_descriptor_pool(&core.device, frames)?;
+        cleanup.disarm();
+
+        Ok(VulkanComputeSetup {
             core,
 
             descriptor_set_layout,
@@ -33,10 +58,10 @@ impl VulkanComputeSetup {
 
             command_pool,
             descriptor_pool,
-        }
+        })
     }
 
-    fn create_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
+    fn create_descriptor_set_layout(device: &ash::Device) -> VulkanResult<vk::DescriptorSetLayout> {
         let descriptor_set_layout_bindings = [
             vk::DescriptorSetLayoutBinding {
                 binding: 0,
@@ -52,6 +77,13 @@ impl VulkanComputeSetup {
                 stage_flags: vk::ShaderStageFlags::COMPUTE,
                 ..Default::default()
             },
+            vk::DescriptorSetLayoutBinding {
+                binding: 2,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                ..Default::default()
+            },
         ];
 
         let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
@@ -63,16 +95,15 @@ impl VulkanComputeSetup {
         unsafe {
             device
                 .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
-                .expect("Failed to create Descriptor Set Layout!")
+                .context("Failed to create Descriptor Set Layout")
         }
     }
 
     fn create_pipeline(
         core: &VulkanCore,
         descriptor_set_layout: vk::DescriptorSetLayout,
-    ) -> (vk::Pipeline, vk::PipelineLayout) {
-        let comp_shader_module =
-            core.create_shader_module(include_bytes!("../../target/shaders/snow.comp.spv"));
+    ) -> VulkanResult<(vk::Pipeline, vk::PipelineLayout)> {
+        let comp_shader_module = core.create_shader_module(&core.shaders.get("snow.comp"))?;
 
         let main_function_name = CString::new("main").unwrap(); // the beginning function name in shader code.
 
@@ -85,12 +116,12 @@ impl VulkanComputeSetup {
 
         let set_layouts = [descriptor_set_layout];
 
-        let last_frame_time_secs = vk::PushConstantRange {
+        let constants = vk::PushConstantRange {
             stage_flags: vk::ShaderStageFlags::COMPUTE,
-            size: 4,
+            size: std::mem::size_of::<SnowConstants>() as u32,
             offset: 0,
         };
-        let push_constant_ranges = [last_frame_time_secs];
+        let push_constant_ranges = [constants];
 
         let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
             flags: vk::PipelineLayoutCreateFlags::empty(),
@@ -104,7 +135,7 @@ impl VulkanComputeSetup {
         let pipeline_layout = unsafe {
             core.device
                 .create_pipeline_layout(&pipeline_layout_create_info, None)
-                .expect("Failed to create pipeline layout!")
+                .context("Failed to create pipeline layout")?
         };
 
         let compute_pipeline_create_infos = [vk::ComputePipelineCreateInfo {
@@ -117,36 +148,75 @@ impl VulkanComputeSetup {
         let compute_pipelines = unsafe {
             core.device
                 .create_compute_pipelines(
-                    vk::PipelineCache::null(),
+                    core.pipeline_cache.cache,
                     &compute_pipeline_create_infos,
                     None,
                 )
-                .expect("Failed to create Compute Pipeline!.")
+                .map_err(|(_, result)| result)
+                .context("Failed to create Compute Pipeline")?
         };
+        core.debug_utils.name(compute_pipelines[0], "snow pipeline");
 
         unsafe {
             core.device.destroy_shader_module(comp_shader_module, None);
         }
 
-        (compute_pipelines[0], pipeline_layout)
+        Ok((compute_pipelines[0], pipeline_layout))
+    }
+
+    /// Only once nothing uses the old pipeline any more.
+    #[cfg(feature = "hot-reload")]
+    pub fn recreate_pipeline(&mut self) -> VulkanResult<()> {
+        unsafe {
+            self.core.device.destroy_pipeline(self.pipeline, None);
+            self.core
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+        let (pipeline, pipeline_layout) =
+            VulkanComputeSetup::create_pipeline(&self.core, self.descriptor_set_layout)?;
+        self.pipeline = pipeline;
+        self.pipeline_layout = pipeline_layout;
+        Ok(())
+    }
+
+    /// Builds the pipeline with `core`'s shaders and destroys it right away, see
+    /// `VulkanGraphicsSetup::try_pipelines`.
+    #[cfg(feature = "hot-reload")]
+    pub fn try_pipeline(&self, core: &VulkanCore) -> VulkanResult<()> {
+        let (pipeline, pipeline_layout) =
+            VulkanComputeSetup::create_pipeline(core, self.descriptor_set_layout)?;
+        unsafe {
+            core.device.destroy_pipeline(pipeline, None);
+            core.device.destroy_pipeline_layout(pipeline_layout, None);
+        }
+        Ok(())
     }
 
-    fn create_descriptor_pool(device: &ash::Device) -> vk::DescriptorPool {
+    fn create_descriptor_pool(
+        device: &ash::Device,
+        frames: usize,
+    ) -> VulkanResult<vk::DescriptorPool> {
         let pool_sizes = [
             vk::DescriptorPoolSize {
                 // snowflakes
                 ty: vk::DescriptorType::STORAGE_BUFFER,
-                descriptor_count: 1,
+                descriptor_count: frames as u32,
             },
             vk::DescriptorPoolSize {
                 // drawing buffer
                 ty: vk::DescriptorType::STORAGE_BUFFER,
-                descriptor_count: 1,
+                descriptor_count: frames as u32,
+            },
+            vk::DescriptorPoolSize {
+                // indirect draw command
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: frames as u32,
             },
         ];
 
         let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo {
-            max_sets: 1,
+            max_sets: frames as u32,
             pool_size_count: pool_sizes.len() as u32,
             p_pool_sizes: pool_sizes.as_ptr(),
             ..Default::default()
@@ -155,7 +225,7 @@ impl VulkanComputeSetup {
         unsafe {
             device
                 .create_descriptor_pool(&descriptor_pool_create_info, None)
-                .expect("Failed to create Descriptor Pool!")
+                .context("Failed to create Descriptor Pool")
         }
     }
 
@@ -1,19 +1,71 @@
+use std::cell::RefCell;
 use std::collections::HashSet;
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
-#[cfg(any(feature = "validation-layers", target_os = "windows"))]
 use std::os::raw::c_void;
 use std::ptr;
+use std::rc::Rc;
 
-use crate::vulkan::{QueueFamilyIndices, SurfaceComposite, VulkanGraphicsSetup};
 #[cfg(feature = "validation-layers")]
-use ash::{ext, khr, vk};
+use crate::vulkan::debug;
+use crate::vulkan::debug::DebugUtils;
+use crate::vulkan::error::{Context, VulkanError, VulkanResult};
+use crate::vulkan::gpu::{Gpu, GpuSelection};
+use crate::vulkan::memory::{Allocation, MemoryAllocator, MemoryHeapStats, Strategy};
+use crate::vulkan::pipeline_cache::PipelineCache;
+use crate::vulkan::rendering::{DynamicRendering, DYNAMIC_RENDERING_EXTENSION};
+use crate::vulkan::shaders::Shaders;
+use crate::vulkan::{gpu, QueueFamilyIndices, SurfaceComposite, VulkanGraphicsSetup};
+#[cfg(feature = "validation-layers")]
+use ash::ext;
+use ash::{khr, vk};
 use raw_window_handle::RawDisplayHandle;
 use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
 const APPLICATION_VERSION: u32 = vk::make_api_version(0, 0, 1, 0);
 const ENGINE_VERSION: u32 = vk::make_api_version(0, 0, 1, 0);
-const VULKAN_API_VERSION: u32 = vk::make_api_version(0, 1, 1, 0);
+// older devices work too, with extensions or without what's newer, down to Vulkan 1.1
+const VULKAN_API_VERSION: u32 = vk::make_api_version(0, 1, 3, 0);
+
+const TIMELINE_SEMAPHORE_EXTENSION: &str = "VK_KHR_timeline_semaphore";
+
+/// A 2D image for `VulkanCore::create_image`, by default a single layer without mips or
+/// multisampling, optimally tiled in device local memory.
+#[derive(Clone, Copy)]
+pub(crate) struct ImageDescription {
+    pub extent: vk::Extent2D,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub flags: vk::ImageCreateFlags,
+    pub samples: vk::SampleCountFlags,
+    pub format: vk::Format,
+    pub tiling: vk::ImageTiling,
+    pub usage: vk::ImageUsageFlags,
+    pub memory_properties: vk::MemoryPropertyFlags,
+}
+
+impl Default for ImageDescription {
+    fn default() -> Self {
+        ImageDescription {
+            extent: vk::Extent2D::default(),
+            mip_levels: 1,
+            array_layers: 1,
+            flags: vk::ImageCreateFlags::empty(),
+            samples: vk::SampleCountFlags::TYPE_1,
+            format: vk::Format::UNDEFINED,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: vk::ImageUsageFlags::empty(),
+            memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        }
+    }
+}
+
+/// Timeline semaphore functions, core since Vulkan 1.2, see `VulkanCore::wait_semaphores`.
+#[derive(Clone)]
+enum TimelineSemaphores {
+    Core,
+    Extension(khr::timeline_semaphore::Device),
+}
 
 #[derive(Clone)]
 pub struct VulkanCore {
@@ -28,124 +80,223 @@ pub struct VulkanCore {
     pub physical_device: vk::PhysicalDevice,
     pub physical_device_memory_properties: vk::PhysicalDeviceMemoryProperties,
     pub device: ash::Device,
+    pub debug_utils: DebugUtils,
+    memory_allocator: Rc<RefCell<MemoryAllocator>>,
+    pub dynamic_rendering: Option<DynamicRendering>, // None means render passes and framebuffers
+    timeline_semaphores: TimelineSemaphores,
+    pub pipeline_cache: PipelineCache,
+    pub shaders: Shaders,
 
     pub queue_family: QueueFamilyIndices,
     pub compute_queue: vk::Queue,
     pub graphics_queue: vk::Queue,
     pub present_queue: vk::Queue,
-    transfer_queue: vk::Queue,
+    pub transfer_queue: vk::Queue, // a dedicated one if there is any, see `Uploader`
 }
 
 impl VulkanCore {
-    pub fn new(window: &winit::window::Window, application_name: &str) -> (Self, SurfaceComposite) {
+    pub fn new(
+        window: &winit::window::Window,
+        application_name: &str,
+        gpu_selection: Option<&GpuSelection>,
+    ) -> VulkanResult<(Self, SurfaceComposite)> {
         let entry = ash::Entry::linked();
         let instance = VulkanCore::create_instance(
             &entry,
             application_name,
-            window.display_handle().unwrap().as_raw(),
-        );
+            Some(window.display_handle().unwrap().as_raw()),
+        )?;
+        let surface_composite = VulkanCore::create_surface(&entry, &instance, window)
+            .inspect_err(|_| unsafe { instance.destroy_instance(None) })?;
+        match VulkanCore::init(
+            entry,
+            instance.clone(),
+            Some(&surface_composite),
+            gpu_selection,
+        ) {
+            Ok(core) => Ok((core, surface_composite)),
+            Err(error) => {
+                // nothing left behind, so that another GPU can be tried
+                unsafe {
+                    surface_composite
+                        .loader
+                        .destroy_surface(surface_composite.surface, None);
+                    instance.destroy_instance(None);
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// Creates the core without any window, surface or swapchain support, for offscreen rendering.
+    pub fn new_headless(
+        application_name: &str,
+        gpu_selection: Option<&GpuSelection>,
+    ) -> VulkanResult<Self> {
+        let entry = ash::Entry::linked();
+        let instance = VulkanCore::create_instance(&entry, application_name, None)?;
+        VulkanCore::init(entry, instance.clone(), None, gpu_selection)
+            .inspect_err(|_| unsafe { instance.destroy_instance(None) })
+    }
+
+    /// All GPUs, in the order `GpuSelection::Index` refers to. Whether they're suitable is checked
+    /// for offscreen rendering, without any window.
+    pub(crate) fn list_gpus(application_name: &str) -> VulkanResult<Vec<Gpu>> {
+        let entry = ash::Entry::linked();
+        let instance = VulkanCore::create_instance(&entry, application_name, None)?;
+        let physical_devices = unsafe { instance.enumerate_physical_devices() };
+        let gpus = physical_devices.map(|physical_devices| {
+            physical_devices
+                .iter()
+                .map(|&physical_device| VulkanCore::describe_gpu(&instance, physical_device, None))
+                .collect()
+        });
+        unsafe { instance.destroy_instance(None) };
+        gpus.context("Failed to enumerate Physical Devices")
+    }
+
+    fn init(
+        entry: ash::Entry,
+        instance: ash::Instance,
+        surface_composite: Option<&SurfaceComposite>,
+        gpu_selection: Option<&GpuSelection>,
+    ) -> VulkanResult<Self> {
         #[cfg(feature = "validation-layers")]
         let (debug_utils_loader, debug_messenger) =
-            VulkanCore::setup_debug_utils(&entry, &instance);
-        let surface_composite = VulkanCore::create_surface(&entry, &instance, &window);
-        let physical_device = VulkanCore::pick_physical_device(&instance, &surface_composite);
+            VulkanCore::setup_debug_utils(&entry, &instance)?;
+        // the instance is up to whoever created it
+        let destroy_debug_utils = || {
+            #[cfg(feature = "validation-layers")]
+            unsafe {
+                debug_utils_loader.destroy_debug_utils_messenger(debug_messenger, None)
+            };
+        };
+        let physical_device =
+            VulkanCore::pick_physical_device(&instance, surface_composite, gpu_selection)
+                .inspect_err(|_| destroy_debug_utils())?;
         let physical_device_memory_properties =
             unsafe { instance.get_physical_device_memory_properties(physical_device) };
-        let (device, queue_family) =
-            VulkanCore::create_logical_device(&instance, physical_device, &surface_composite);
+        let dynamic_rendering_extension =
+            VulkanCore::find_dynamic_rendering_support(&instance, physical_device);
+        // suitable devices support them one way or the other
+        let timeline_semaphore_extension =
+            VulkanCore::find_timeline_semaphore_support(&instance, physical_device) == Some(true);
+        let (device, queue_family) = VulkanCore::create_logical_device(
+            &instance,
+            physical_device,
+            surface_composite,
+            dynamic_rendering_extension,
+            timeline_semaphore_extension,
+        )
+        .inspect_err(|_| destroy_debug_utils())?;
+        let debug_utils = DebugUtils::new(&instance, &device);
+        let dynamic_rendering = dynamic_rendering_extension.map(|extension| {
+            if extension {
+                DynamicRendering::Extension(khr::dynamic_rendering::Device::new(&instance, &device))
+            } else {
+                DynamicRendering::Core
+            }
+        });
+        let timeline_semaphores = if timeline_semaphore_extension {
+            TimelineSemaphores::Extension(khr::timeline_semaphore::Device::new(&instance, &device))
+        } else {
+            TimelineSemaphores::Core
+        };
+        let pipeline_cache =
+            PipelineCache::new(&instance, physical_device, &device).inspect_err(|_| {
+                unsafe { device.destroy_device(None) };
+                destroy_debug_utils();
+            })?;
+        let buffer_image_granularity =
+            unsafe { instance.get_physical_device_properties(physical_device) }
+                .limits
+                .buffer_image_granularity;
+        let memory_allocator = MemoryAllocator::new(
+            device.clone(),
+            physical_device_memory_properties,
+            buffer_image_granularity,
+        );
         let compute_queue =
             unsafe { device.get_device_queue(queue_family.compute_family.unwrap(), 0) };
         let graphics_queue =
             unsafe { device.get_device_queue(queue_family.graphics_family.unwrap(), 0) };
-        let present_queue =
-            unsafe { device.get_device_queue(queue_family.present_family.unwrap(), 0) };
+        let present_queue = match queue_family.present_family {
+            Some(present_family) => unsafe { device.get_device_queue(present_family, 0) },
+            None => vk::Queue::null(), // headless, nothing gets presented
+        };
         let transfer_queue =
             unsafe { device.get_device_queue(queue_family.transfer_family.unwrap(), 0) };
-        (
-            VulkanCore {
-                _entry: entry,
-                instance,
-
-                #[cfg(feature = "validation-layers")]
-                debug_utils_loader,
-                #[cfg(feature = "validation-layers")]
-                debug_messenger,
+        Ok(VulkanCore {
+            _entry: entry,
+            instance,
 
-                physical_device,
-                physical_device_memory_properties,
-
-                device,
-                queue_family,
-                compute_queue,
-                graphics_queue,
-                present_queue,
-                transfer_queue,
-            },
-            surface_composite,
-        )
+            #[cfg(feature = "validation-layers")]
+            debug_utils_loader,
+            #[cfg(feature = "validation-layers")]
+            debug_messenger,
+
+            physical_device,
+            physical_device_memory_properties,
+
+            device,
+            debug_utils,
+            memory_allocator: Rc::new(RefCell::new(memory_allocator)),
+            dynamic_rendering,
+            timeline_semaphores,
+            pipeline_cache,
+            shaders: Shaders::embedded(),
+            queue_family,
+            compute_queue,
+            graphics_queue,
+            present_queue,
+            transfer_queue,
+        })
     }
 
     pub(crate) fn create_image(
         &self,
-        width: u32,
-        height: u32,
-        mip_levels: u32,
-        num_samples: vk::SampleCountFlags,
-        format: vk::Format,
-        tiling: vk::ImageTiling,
-        usage: vk::ImageUsageFlags,
-        required_memory_properties: vk::MemoryPropertyFlags,
-    ) -> (vk::Image, vk::DeviceMemory) {
+        description: &ImageDescription,
+        name: &str,
+    ) -> VulkanResult<(vk::Image, Allocation)> {
         let image_create_info = vk::ImageCreateInfo {
+            flags: description.flags,
             image_type: vk::ImageType::TYPE_2D,
-            format,
-            mip_levels,
-            array_layers: 1,
-            samples: num_samples,
-            tiling,
-            usage,
+            format: description.format,
+            mip_levels: description.mip_levels,
+            array_layers: description.array_layers,
+            samples: description.samples,
+            tiling: description.tiling,
+            usage: description.usage,
             sharing_mode: vk::SharingMode::EXCLUSIVE,
             queue_family_index_count: 0,
             p_queue_family_indices: ptr::null(),
             initial_layout: vk::ImageLayout::UNDEFINED,
-            extent: vk::Extent3D {
-                width,
-                height,
-                depth: 1,
-            },
+            extent: description.extent.into(),
             ..Default::default()
         };
 
         let image = unsafe {
             self.device
                 .create_image(&image_create_info, None)
-                .expect("Failed to create Texture Image!")
+                .context("Failed to create Texture Image")?
         };
+        self.debug_utils.name(image, name);
 
         let image_memory_requirement = unsafe { self.device.get_image_memory_requirements(image) };
-        let memory_allocate_info = vk::MemoryAllocateInfo {
-            allocation_size: image_memory_requirement.size,
-            memory_type_index: VulkanCore::find_memory_type(
-                image_memory_requirement.memory_type_bits,
-                required_memory_properties,
-                &self.physical_device_memory_properties,
-            ),
-            ..Default::default()
-        };
-
-        let image_memory = unsafe {
-            self.device
-                .allocate_memory(&memory_allocate_info, None)
-                .expect("Failed to allocate Texture Image memory!")
-        };
+        let image_memory = self.allocate_memory(
+            image_memory_requirement,
+            description.memory_properties,
+            Strategy::FreeList,
+            description.tiling == vk::ImageTiling::LINEAR,
+        )?;
 
         unsafe {
             self.device
-                .bind_image_memory(image, image_memory, 0)
-                .expect("Failed to bind Image Memmory!");
+                .bind_image_memory(image, image_memory.memory, image_memory.offset)
+                .context("Failed to bind Image Memmory")?;
         }
 
-        (image, image_memory)
+        Ok((image, image_memory))
     }
 
     pub(crate) fn create_image_view(
@@ -154,9 +305,30 @@ impl VulkanCore {
         format: vk::Format,
         aspect_flags: vk::ImageAspectFlags,
         mip_levels: u32,
-    ) -> vk::ImageView {
+    ) -> VulkanResult<vk::ImageView> {
+        self.create_layered_image_view(
+            image,
+            vk::ImageViewType::TYPE_2D,
+            format,
+            vk::ImageSubresourceRange {
+                aspect_mask: aspect_flags,
+                base_mip_level: 0,
+                level_count: mip_levels,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+        )
+    }
+
+    pub(crate) fn create_layered_image_view(
+        &self,
+        image: vk::Image,
+        view_type: vk::ImageViewType,
+        format: vk::Format,
+        subresource_range: vk::ImageSubresourceRange,
+    ) -> VulkanResult<vk::ImageView> {
         let imageview_create_info = vk::ImageViewCreateInfo {
-            view_type: vk::ImageViewType::TYPE_2D,
+            view_type,
             format,
             components: vk::ComponentMapping {
                 r: vk::ComponentSwizzle::IDENTITY,
@@ -164,13 +336,7 @@ impl VulkanCore {
                 b: vk::ComponentSwizzle::IDENTITY,
                 a: vk::ComponentSwizzle::IDENTITY,
             },
-            subresource_range: vk::ImageSubresourceRange {
-                aspect_mask: aspect_flags,
-                base_mip_level: 0,
-                level_count: mip_levels,
-                base_array_layer: 0,
-                layer_count: 1,
-            },
+            subresource_range,
             image,
             ..Default::default()
         };
@@ -178,7 +344,7 @@ impl VulkanCore {
         unsafe {
             self.device
                 .create_image_view(&imageview_create_info, None)
-                .expect("Failed to create Image View!")
+                .context("Failed to create Image View")
         }
     }
 
@@ -187,44 +353,31 @@ impl VulkanCore {
         command_pool: vk::CommandPool,
         usage: vk::BufferUsageFlags,
         data: &[T],
-    ) -> (vk::Buffer, vk::DeviceMemory) {
+        name: &str,
+    ) -> VulkanResult<(vk::Buffer, Allocation)> {
         let buffer_size = size_of_val(data) as vk::DeviceSize;
-        let (staging_buffer, staging_buffer_memory) = self.create_buffer(
-            buffer_size,
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-        );
+        let (staging_buffer, staging_buffer_memory) = self.create_staging_buffer(buffer_size)?;
 
         unsafe {
-            let data_ptr = self
-                .device
-                .map_memory(
-                    staging_buffer_memory,
-                    0,
-                    buffer_size,
-                    vk::MemoryMapFlags::empty(),
-                )
-                .expect("Failed to Map Memory") as *mut T;
-
+            let data_ptr = staging_buffer_memory.mapped_ptr::<T>();
             data_ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
-
-            self.device.unmap_memory(staging_buffer_memory);
         }
 
         let (buffer, buffer_memory) = self.create_buffer(
             buffer_size,
             usage | vk::BufferUsageFlags::TRANSFER_DST,
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        );
+            name,
+        )?;
 
-        self.copy_buffer(command_pool, staging_buffer, buffer, buffer_size);
+        self.copy_buffer(command_pool, staging_buffer, buffer, buffer_size)?;
 
         unsafe {
             self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_buffer_memory, None);
         }
+        self.free_memory(staging_buffer_memory);
 
-        (buffer, buffer_memory)
+        Ok((buffer, buffer_memory))
     }
 
     pub(crate) fn create_buffer(
@@ -232,7 +385,39 @@ impl VulkanCore {
         size: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
         required_memory_properties: vk::MemoryPropertyFlags,
-    ) -> (vk::Buffer, vk::DeviceMemory) {
+        name: &str,
+    ) -> VulkanResult<(vk::Buffer, Allocation)> {
+        self.create_buffer_with_strategy(
+            size,
+            usage,
+            required_memory_properties,
+            Strategy::FreeList,
+            name,
+        )
+    }
+
+    /// Host-visible buffer to copy from, gone soon after.
+    pub(crate) fn create_staging_buffer(
+        &self,
+        size: vk::DeviceSize,
+    ) -> VulkanResult<(vk::Buffer, Allocation)> {
+        self.create_buffer_with_strategy(
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            Strategy::Linear,
+            "staging buffer",
+        )
+    }
+
+    fn create_buffer_with_strategy(
+        &self,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        required_memory_properties: vk::MemoryPropertyFlags,
+        strategy: Strategy,
+        name: &str,
+    ) -> VulkanResult<(vk::Buffer, Allocation)> {
         let buffer_create_info = vk::BufferCreateInfo {
             size,
             usage,
@@ -244,35 +429,47 @@ impl VulkanCore {
         let buffer = unsafe {
             self.device
                 .create_buffer(&buffer_create_info, None)
-                .expect("Failed to create Vertex Buffer")
+                .context("Failed to create Vertex Buffer")?
         };
+        self.debug_utils.name(buffer, name);
 
         let mem_requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
-        let memory_type = VulkanCore::find_memory_type(
-            mem_requirements.memory_type_bits,
-            required_memory_properties,
-            &self.physical_device_memory_properties,
-        );
-
-        let allocate_info = vk::MemoryAllocateInfo {
-            allocation_size: mem_requirements.size,
-            memory_type_index: memory_type,
-            ..Default::default()
-        };
-
-        let buffer_memory = unsafe {
-            self.device
-                .allocate_memory(&allocate_info, None)
-                .expect("Failed to allocate vertex buffer memory!")
-        };
+        let buffer_memory =
+            self.allocate_memory(mem_requirements, required_memory_properties, strategy, true)?;
 
         unsafe {
             self.device
-                .bind_buffer_memory(buffer, buffer_memory, 0)
-                .expect("Failed to bind Buffer");
+                .bind_buffer_memory(buffer, buffer_memory.memory, buffer_memory.offset)
+                .context("Failed to bind Buffer")?;
         }
 
-        (buffer, buffer_memory)
+        Ok((buffer, buffer_memory))
+    }
+
+    fn allocate_memory(
+        &self,
+        requirements: vk::MemoryRequirements,
+        required_memory_properties: vk::MemoryPropertyFlags,
+        strategy: Strategy,
+        linear: bool,
+    ) -> VulkanResult<Allocation> {
+        let memory_type = VulkanCore::find_memory_type(
+            requirements.memory_type_bits,
+            required_memory_properties,
+            &self.physical_device_memory_properties,
+        )?;
+        self.memory_allocator
+            .borrow_mut()
+            .allocate(memory_type, requirements, strategy, linear)
+    }
+
+    /// Of a buffer or image created here, once it's destroyed.
+    pub(crate) fn free_memory(&self, allocation: Allocation) {
+        self.memory_allocator.borrow_mut().free(allocation);
+    }
+
+    pub fn memory_stats(&self) -> Vec<MemoryHeapStats> {
+        self.memory_allocator.borrow().stats()
     }
 
     pub(crate) fn copy_buffer(
@@ -281,8 +478,8 @@ impl VulkanCore {
         src_buffer: vk::Buffer,
         dst_buffer: vk::Buffer,
         size: vk::DeviceSize,
-    ) {
-        let (command_buffers, command_buffer) = self.begin_one_time_commands(command_pool);
+    ) -> VulkanResult<()> {
+        let (command_buffers, command_buffer) = self.begin_one_time_commands(command_pool)?;
 
         unsafe {
             let copy_regions = [vk::BufferCopy {
@@ -295,13 +492,13 @@ impl VulkanCore {
                 .cmd_copy_buffer(command_buffer, src_buffer, dst_buffer, &copy_regions);
         }
 
-        self.end_one_time_commands(command_pool, &command_buffers, command_buffer);
+        self.end_one_time_commands(command_pool, &command_buffers, command_buffer)
     }
 
     pub fn begin_one_time_commands(
         &self,
         command_pool: vk::CommandPool,
-    ) -> (Vec<vk::CommandBuffer>, vk::CommandBuffer) {
+    ) -> VulkanResult<(Vec<vk::CommandBuffer>, vk::CommandBuffer)> {
         let allocate_info = vk::CommandBufferAllocateInfo {
             command_buffer_count: 1,
             command_pool,
@@ -312,9 +509,10 @@ impl VulkanCore {
         let command_buffers = unsafe {
             self.device
                 .allocate_command_buffers(&allocate_info)
-                .expect("Failed to allocate Command Buffer")
+                .context("Failed to allocate Command Buffer")?
         };
         let command_buffer = command_buffers[0];
+        self.debug_utils.name(command_buffer, "one-time commands");
 
         let begin_info = vk::CommandBufferBeginInfo {
             flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
@@ -324,21 +522,21 @@ impl VulkanCore {
         unsafe {
             self.device
                 .begin_command_buffer(command_buffer, &begin_info)
-                .expect("Failed to begin Command Buffer");
+                .context("Failed to begin Command Buffer")?;
         }
-        (command_buffers, command_buffer)
+        Ok((command_buffers, command_buffer))
     }
 
     pub fn end_one_time_commands(
         &self,
         command_pool: vk::CommandPool,
-        command_buffers: &Vec<vk::CommandBuffer>,
+        command_buffers: &[vk::CommandBuffer],
         command_buffer: vk::CommandBuffer,
-    ) {
+    ) -> VulkanResult<()> {
         unsafe {
             self.device
                 .end_command_buffer(command_buffer)
-                .expect("Failed to end Command Buffer");
+                .context("Failed to end Command Buffer")?;
         }
 
         let submit_info = [vk::SubmitInfo {
@@ -350,20 +548,22 @@ impl VulkanCore {
             ..Default::default()
         }];
 
+        // command pools for these are graphics or compute ones, both share the graphics family
         unsafe {
             self.device
-                .queue_submit(self.transfer_queue, &submit_info, vk::Fence::null())
-                .expect("Failed to Submit Queue.");
+                .queue_submit(self.graphics_queue, &submit_info, vk::Fence::null())
+                .context("Failed to Submit Queue")?;
             self.device
-                .queue_wait_idle(self.transfer_queue)
-                .expect("Failed to wait Queue idle");
+                .queue_wait_idle(self.graphics_queue)
+                .context("Failed to wait Queue idle")?;
 
             self.device
-                .free_command_buffers(command_pool, &command_buffers);
+                .free_command_buffers(command_pool, command_buffers);
         }
+        Ok(())
     }
 
-    pub(crate) fn create_shader_module(&self, shader_spv: &[u8]) -> vk::ShaderModule {
+    pub(crate) fn create_shader_module(&self, shader_spv: &[u8]) -> VulkanResult<vk::ShaderModule> {
         let shader_module_create_info = vk::ShaderModuleCreateInfo {
             flags: vk::ShaderModuleCreateFlags::empty(),
             code_size: shader_spv.len(),
@@ -374,12 +574,17 @@ impl VulkanCore {
         unsafe {
             self.device
                 .create_shader_module(&shader_module_create_info, None)
-                .expect("Failed to create Shader Module!")
+                .context("Failed to create Shader Module")
         }
     }
 
-    pub(crate) fn create_command_pool(&self, queue_family_index: u32) -> vk::CommandPool {
+    pub(crate) fn create_command_pool(
+        &self,
+        queue_family_index: u32,
+        flags: vk::CommandPoolCreateFlags,
+    ) -> VulkanResult<vk::CommandPool> {
         let command_pool_create_info = vk::CommandPoolCreateInfo {
+            flags,
             queue_family_index,
             ..Default::default()
         };
@@ -387,22 +592,53 @@ impl VulkanCore {
         unsafe {
             self.device
                 .create_command_pool(&command_pool_create_info, None)
-                .expect("Failed to create Command Pool!")
+                .context("Failed to create Command Pool")
         }
     }
 
-    pub(crate) fn create_semaphore(&self) -> vk::Semaphore {
+    pub(crate) fn create_semaphore(&self) -> VulkanResult<vk::Semaphore> {
         let semaphore_create_info = vk::SemaphoreCreateInfo {
             ..Default::default()
         };
         unsafe {
             self.device
                 .create_semaphore(&semaphore_create_info, None)
-                .expect("Failed to create Semaphore Object!")
+                .context("Failed to create Semaphore Object")
+        }
+    }
+
+    /// Its value only ever grows, waiting for a value waits for whatever signals it.
+    pub(crate) fn create_timeline_semaphore(&self) -> VulkanResult<vk::Semaphore> {
+        let mut semaphore_type_create_info = vk::SemaphoreTypeCreateInfo {
+            semaphore_type: vk::SemaphoreType::TIMELINE,
+            initial_value: 0,
+            ..Default::default()
+        };
+        let semaphore_create_info =
+            vk::SemaphoreCreateInfo::default().push_next(&mut semaphore_type_create_info);
+        unsafe {
+            self.device
+                .create_semaphore(&semaphore_create_info, None)
+                .context("Failed to create timeline Semaphore")
         }
     }
 
-    pub(crate) fn create_fence(&self) -> vk::Fence {
+    /// Until timeline semaphores reach the values, see `create_timeline_semaphore`.
+    pub(crate) fn wait_semaphores(
+        &self,
+        wait_info: &vk::SemaphoreWaitInfo,
+        timeout: u64,
+    ) -> VulkanResult<()> {
+        unsafe {
+            match &self.timeline_semaphores {
+                TimelineSemaphores::Core => self.device.wait_semaphores(wait_info, timeout),
+                TimelineSemaphores::Extension(loader) => loader.wait_semaphores(wait_info, timeout),
+            }
+        }
+        .context("Failed to wait for timeline Semaphore")
+    }
+
+    pub(crate) fn create_fence(&self) -> VulkanResult<vk::Fence> {
         let fence_create_info = vk::FenceCreateInfo {
             flags: vk::FenceCreateFlags::SIGNALED,
             ..Default::default()
@@ -411,7 +647,7 @@ impl VulkanCore {
         unsafe {
             self.device
                 .create_fence(&fence_create_info, None)
-                .expect("Failed to create Fence Object!")
+                .context("Failed to create Fence Object")
         }
     }
 
@@ -419,24 +655,24 @@ impl VulkanCore {
         type_filter: u32,
         required_properties: vk::MemoryPropertyFlags,
         mem_properties: &vk::PhysicalDeviceMemoryProperties,
-    ) -> u32 {
+    ) -> VulkanResult<u32> {
         for (i, memory_type) in mem_properties.memory_types.iter().enumerate() {
             // same implementation
             if (type_filter & (1 << i)) > 0
                 && memory_type.property_flags.contains(required_properties)
             {
-                return i as u32;
+                return Ok(i as u32);
             }
         }
 
-        panic!("Failed to find suitable memory type!")
+        Err(VulkanError::Unsupported("suitable memory type"))
     }
 
     fn create_instance(
         entry: &ash::Entry,
         application_name: &str,
-        display_handle: RawDisplayHandle,
-    ) -> ash::Instance {
+        display_handle: Option<RawDisplayHandle>,
+    ) -> VulkanResult<ash::Instance> {
         let app_name = CString::new(application_name).unwrap();
         let engine_name = CString::new("Vulkan Engine").unwrap();
         let app_info = vk::ApplicationInfo {
@@ -468,7 +704,7 @@ impl VulkanCore {
             .collect();
 
         #[cfg(feature = "validation-layers")]
-        let debug_utils_messenger_ci = VulkanCore::build_messenger_create_info();
+        let debug_utils_messenger_ci = debug::messenger_create_info();
 
         #[cfg(not(target_os = "macos"))]
         let flags = vk::InstanceCreateFlags::empty();
@@ -488,53 +724,36 @@ impl VulkanCore {
             ..Default::default()
         };
 
-        let instance: ash::Instance = unsafe {
+        unsafe {
             entry
                 .create_instance(&create_info, None)
-                .expect("Failed to create instance!")
-        };
-
-        instance
+                .context("Failed to create instance")
+        }
     }
 
     #[cfg(feature = "validation-layers")]
     fn setup_debug_utils(
         entry: &ash::Entry,
         instance: &ash::Instance,
-    ) -> (ext::debug_utils::Instance, vk::DebugUtilsMessengerEXT) {
+    ) -> VulkanResult<(ext::debug_utils::Instance, vk::DebugUtilsMessengerEXT)> {
         let debug_utils_loader = ext::debug_utils::Instance::new(entry, instance);
 
-        let messenger_ci = VulkanCore::build_messenger_create_info();
+        let messenger_ci = debug::messenger_create_info();
 
         let utils_messenger = unsafe {
             debug_utils_loader
                 .create_debug_utils_messenger(&messenger_ci, None)
-                .expect("Debug Utils Callback")
+                .context("Failed to create debug messenger")?
         };
 
-        (debug_utils_loader, utils_messenger)
-    }
-
-    #[cfg(feature = "validation-layers")]
-    fn build_messenger_create_info() -> vk::DebugUtilsMessengerCreateInfoEXT<'static> {
-        vk::DebugUtilsMessengerCreateInfoEXT {
-            message_severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                // | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
-                // | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
-                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
-            message_type: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
-                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
-            pfn_user_callback: Some(vulkan_debug_utils_callback),
-            ..Default::default()
-        }
+        Ok((debug_utils_loader, utils_messenger))
     }
 
     fn create_surface(
         entry: &ash::Entry,
         instance: &ash::Instance,
         window: &winit::window::Window,
-    ) -> SurfaceComposite {
+    ) -> VulkanResult<SurfaceComposite> {
         unsafe {
             let surface = ash_window::create_surface(
                 entry,
@@ -543,62 +762,142 @@ impl VulkanCore {
                 window.window_handle().unwrap().as_raw(),
                 None,
             )
-            .expect("Unable to create surface");
+            .context("Failed to create surface")?;
             let surface_loader = khr::surface::Instance::new(entry, instance);
 
-            SurfaceComposite {
+            Ok(SurfaceComposite {
                 loader: surface_loader,
                 surface,
-            }
+            })
         }
     }
 
     fn pick_physical_device(
         instance: &ash::Instance,
-        surface_composite: &SurfaceComposite,
-    ) -> vk::PhysicalDevice {
+        surface_composite: Option<&SurfaceComposite>,
+        gpu_selection: Option<&GpuSelection>,
+    ) -> VulkanResult<vk::PhysicalDevice> {
         let physical_devices: Vec<vk::PhysicalDevice> = unsafe {
             instance
                 .enumerate_physical_devices()
-                .expect("Failed to enumerate Physical Devices!")
+                .context("Failed to enumerate Physical Devices")?
         };
+        let gpus: Vec<Gpu> = physical_devices
+            .iter()
+            .map(|&physical_device| {
+                VulkanCore::describe_gpu(instance, physical_device, surface_composite)
+            })
+            .collect();
 
-        let result = physical_devices.iter().find(|physical_device| {
-            VulkanCore::is_physical_device_suitable(instance, **physical_device, &surface_composite)
-        });
+        let index = match gpu_selection {
+            Some(gpu_selection) => gpu_selection
+                .select(&gpus)
+                .ok_or_else(|| VulkanError::GpuNotFound(gpu_selection.clone()))?,
+            None => gpu::preferred(&gpus).ok_or(VulkanError::NoSuitableGpu)?,
+        };
+        log::info!("Using GPU {}: {}", index, gpus[index]);
+        Ok(physical_devices[index])
+    }
 
-        match result {
-            None => panic!("Failed to find a suitable GPU!"),
-            Some(physical_device) => *physical_device,
+    fn describe_gpu(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        surface_composite: Option<&SurfaceComposite>,
+    ) -> Gpu {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let memory_bytes: u64 = memory_properties
+            .memory_heaps_as_slice()
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+        Gpu {
+            name: properties
+                .device_name_as_c_str()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            device_type: properties.device_type,
+            api_version: properties.api_version,
+            memory_mib: memory_bytes / (1024 * 1024),
+            suitable: VulkanCore::is_physical_device_suitable(
+                instance,
+                physical_device,
+                surface_composite,
+            ),
         }
     }
 
     fn is_physical_device_suitable(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
-        surface_composite: &SurfaceComposite,
+        surface_composite: Option<&SurfaceComposite>,
     ) -> bool {
-        let indices = VulkanCore::find_queue_family(instance, physical_device, &surface_composite);
-        let is_queue_family_supported = indices.is_complete();
+        let indices = VulkanCore::find_queue_family(instance, physical_device, surface_composite);
+        let is_queue_family_supported = indices.is_complete(surface_composite.is_some());
 
-        let is_device_extension_supported =
-            VulkanCore::check_device_extension_support(instance, physical_device);
+        let is_device_extension_supported = VulkanCore::check_device_extension_support(
+            instance,
+            physical_device,
+            surface_composite.is_some(),
+        );
 
-        let is_swapchain_supported = if is_device_extension_supported {
-            let swapchain_support =
-                VulkanGraphicsSetup::find_swapchain_support(physical_device, surface_composite);
-            !swapchain_support.formats.is_empty() && !swapchain_support.present_modes.is_empty()
-        } else {
+        let is_swapchain_supported = match surface_composite {
+            // no surface, no swapchain needed
+            None => true,
+            Some(surface_composite) if is_device_extension_supported => {
+                VulkanGraphicsSetup::find_swapchain_support(physical_device, surface_composite)
+                    .is_ok_and(|swapchain_support| {
+                        !swapchain_support.formats.is_empty()
+                            && !swapchain_support.present_modes.is_empty()
+                    })
+            }
+            Some(_) => false,
+        };
+
+        is_queue_family_supported
+            && is_device_extension_supported
+            && is_swapchain_supported
+            && VulkanCore::find_timeline_semaphore_support(instance, physical_device).is_some()
+    }
+
+    /// Compute and graphics get synchronized with them. Whether they're supported, and if so,
+    /// whether they need the extension, they're core since Vulkan 1.2. The extension needs 1.1.
+    fn find_timeline_semaphore_support(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Option<bool> {
+        let api_version =
+            unsafe { instance.get_physical_device_properties(physical_device) }.api_version;
+        let extension = if api_version >= vk::API_VERSION_1_2 {
             false
+        } else if api_version >= vk::API_VERSION_1_1
+            && VulkanCore::available_device_extensions(instance, physical_device)
+                .iter()
+                .any(|name| name == TIMELINE_SEMAPHORE_EXTENSION)
+        {
+            true
+        } else {
+            return None;
         };
 
-        is_queue_family_supported && is_device_extension_supported && is_swapchain_supported
+        let mut timeline_semaphore_features =
+            vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+        let mut features =
+            vk::PhysicalDeviceFeatures2::default().push_next(&mut timeline_semaphore_features);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        if timeline_semaphore_features.timeline_semaphore == vk::TRUE {
+            Some(extension)
+        } else {
+            None
+        }
     }
 
     fn find_queue_family(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
-        surface_composite: &SurfaceComposite,
+        surface_composite: Option<&SurfaceComposite>,
     ) -> QueueFamilyIndices {
         let queue_families =
             unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
@@ -608,12 +907,13 @@ impl VulkanCore {
         let mut index: u32 = 0;
         for queue_family in queue_families.iter() {
             if queue_family.queue_count > 0 {
-                if queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
+                // one family for both, so snow computed there gets drawn without any queue
+                // family ownership transfer
+                if queue_family
+                    .queue_flags
+                    .contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+                {
                     queue_family_indices.graphics_family = Some(index);
-                    queue_family_indices.transfer_family = Some(index);
-                }
-
-                if queue_family.queue_flags.contains(vk::QueueFlags::COMPUTE) {
                     queue_family_indices.compute_family = Some(index);
                     queue_family_indices.transfer_family = Some(index);
                 }
@@ -622,39 +922,74 @@ impl VulkanCore {
                     queue_family_indices.transfer_family = Some(index);
                 }
 
-                let is_present_support = unsafe {
-                    surface_composite
-                        .loader
-                        .get_physical_device_surface_support(
-                            physical_device,
-                            index as u32,
-                            surface_composite.surface,
-                        )
-                        .unwrap()
-                };
-                if is_present_support {
-                    queue_family_indices.present_family = Some(index);
+                if let Some(surface_composite) = surface_composite {
+                    let is_present_support = unsafe {
+                        surface_composite
+                            .loader
+                            .get_physical_device_surface_support(
+                                physical_device,
+                                index,
+                                surface_composite.surface,
+                            )
+                            .unwrap_or(false)
+                    };
+                    if is_present_support {
+                        queue_family_indices.present_family = Some(index);
+                    }
                 }
             }
 
-            if queue_family_indices.is_complete() {
+            if queue_family_indices.is_complete(surface_composite.is_some()) {
                 break;
             }
 
             index += 1;
         }
 
+        // copies there run alongside rendering instead of in between
+        let dedicated_transfer_family = queue_families.iter().position(|queue_family| {
+            queue_family.queue_count > 0
+                && queue_family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                && !queue_family
+                    .queue_flags
+                    .intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        });
+        if let Some(transfer_family) = dedicated_transfer_family {
+            queue_family_indices.transfer_family = Some(transfer_family as u32);
+        }
+
         queue_family_indices
     }
 
     fn check_device_extension_support(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
+        presenting: bool,
     ) -> bool {
+        let available_extension_names =
+            VulkanCore::available_device_extensions(instance, physical_device);
+
+        let mut required_extensions = HashSet::new();
+        for extension in VulkanCore::required_device_extensions(presenting).iter() {
+            required_extensions.insert(extension.to_string());
+        }
+
+        for extension_name in available_extension_names.iter() {
+            required_extensions.remove(extension_name);
+        }
+
+        required_extensions.is_empty()
+    }
+
+    fn available_device_extensions(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Vec<String> {
+        // none at all if they can't be listed, that's not a GPU to use anyway
         let available_extensions = unsafe {
             instance
                 .enumerate_device_extension_properties(physical_device)
-                .expect("Failed to get device extension properties.")
+                .unwrap_or_default()
         };
 
         let mut available_extension_names = vec![];
@@ -667,39 +1002,83 @@ impl VulkanCore {
             available_extension_names.push(extension_name);
         }
 
-        let mut required_extensions = HashSet::new();
-        for extension in VulkanCore::required_device_extensions().iter() {
-            required_extensions.insert(extension.to_string());
-        }
+        available_extension_names
+    }
 
-        for extension_name in available_extension_names.iter() {
-            required_extensions.remove(extension_name);
-        }
+    /// Whether dynamic rendering is supported, and if so, whether it needs the extension for it.
+    /// The extension needs Vulkan 1.2 for the extensions it depends on.
+    fn find_dynamic_rendering_support(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Option<bool> {
+        let api_version =
+            unsafe { instance.get_physical_device_properties(physical_device) }.api_version;
+        let extension = if api_version >= vk::API_VERSION_1_3 {
+            false
+        } else if api_version >= vk::API_VERSION_1_2
+            && VulkanCore::available_device_extensions(instance, physical_device)
+                .iter()
+                .any(|name| name == DYNAMIC_RENDERING_EXTENSION)
+        {
+            true
+        } else {
+            return None;
+        };
 
-        return required_extensions.is_empty();
+        let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+        let mut features =
+            vk::PhysicalDeviceFeatures2::default().push_next(&mut dynamic_rendering_features);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        if dynamic_rendering_features.dynamic_rendering == vk::TRUE {
+            Some(extension)
+        } else {
+            None
+        }
     }
 
     fn create_logical_device(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
-        surface_composite: &SurfaceComposite,
-    ) -> (ash::Device, QueueFamilyIndices) {
+        surface_composite: Option<&SurfaceComposite>,
+        dynamic_rendering_extension: Option<bool>,
+        timeline_semaphore_extension: bool,
+    ) -> VulkanResult<(ash::Device, QueueFamilyIndices)> {
         let indices = VulkanCore::find_queue_family(instance, physical_device, surface_composite);
 
+        let mut queue_families = vec![
+            indices.graphics_family.unwrap(),
+            indices.compute_family.unwrap(),
+            indices.transfer_family.unwrap(),
+        ];
+        queue_families.extend(indices.present_family);
+        queue_families.sort();
+        queue_families.dedup();
+
         let queue_priorities: [f32; 1] = [1.0];
-        let queue_create_infos = [vk::DeviceQueueCreateInfo {
-            queue_family_index: indices.graphics_family.unwrap(),
-            queue_count: queue_priorities.len() as u32,
-            p_queue_priorities: queue_priorities.as_ptr(),
-            ..Default::default()
-        }];
+        let queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = queue_families
+            .iter()
+            .map(|&queue_family_index| vk::DeviceQueueCreateInfo {
+                queue_family_index,
+                queue_count: queue_priorities.len() as u32,
+                p_queue_priorities: queue_priorities.as_ptr(),
+                ..Default::default()
+            })
+            .collect();
 
         let physical_device_features = vk::PhysicalDeviceFeatures {
             sampler_anisotropy: vk::TRUE,
             ..Default::default()
         };
 
-        let enabled_extension_raw_names: Vec<CString> = VulkanCore::required_device_extensions()
+        let mut enabled_extensions =
+            VulkanCore::required_device_extensions(surface_composite.is_some());
+        if dynamic_rendering_extension == Some(true) {
+            enabled_extensions.push(DYNAMIC_RENDERING_EXTENSION);
+        }
+        if timeline_semaphore_extension {
+            enabled_extensions.push(TIMELINE_SEMAPHORE_EXTENSION);
+        }
+        let enabled_extension_raw_names: Vec<CString> = enabled_extensions
             .iter()
             .map(|name| CString::new(*name).unwrap())
             .collect();
@@ -708,7 +1087,27 @@ impl VulkanCore {
             .map(|name| name.as_ptr())
             .collect();
 
+        let dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures {
+            dynamic_rendering: vk::TRUE,
+            ..Default::default()
+        };
+
+        let timeline_semaphore_features = vk::PhysicalDeviceTimelineSemaphoreFeatures {
+            p_next: match dynamic_rendering_extension {
+                Some(_) => {
+                    &dynamic_rendering_features as *const vk::PhysicalDeviceDynamicRenderingFeatures
+                        as *mut c_void
+                }
+                None => ptr::null_mut(),
+            },
+            timeline_semaphore: vk::TRUE,
+            ..Default::default()
+        };
+
         let device_create_info = vk::DeviceCreateInfo {
+            p_next: &timeline_semaphore_features
+                as *const vk::PhysicalDeviceTimelineSemaphoreFeatures
+                as *const c_void,
             queue_create_info_count: queue_create_infos.len() as u32,
             p_queue_create_infos: queue_create_infos.as_ptr(),
             p_enabled_features: &physical_device_features,
@@ -720,10 +1119,10 @@ impl VulkanCore {
         let device: ash::Device = unsafe {
             instance
                 .create_device(physical_device, &device_create_info, None)
-                .expect("Failed to create logical Device!")
+                .context("Failed to create logical Device")?
         };
 
-        (device, indices)
+        Ok((device, indices))
     }
 
     fn required_layer_names() -> Vec<&'static str> {
@@ -733,7 +1132,7 @@ impl VulkanCore {
         ]
     }
 
-    fn required_instance_extensions(display_handle: RawDisplayHandle) -> Vec<&'static str> {
+    fn required_instance_extensions(display_handle: Option<RawDisplayHandle>) -> Vec<&'static str> {
         let mut required_extensions = vec![
             #[cfg(feature = "validation-layers")]
             "VK_EXT_debug_utils",
@@ -741,26 +1140,33 @@ impl VulkanCore {
             "VK_KHR_portability_enumeration",
         ];
 
-        for extension in ash_window::enumerate_required_extensions(display_handle)
-            .unwrap()
-            .iter()
-        {
-            let extension_name = unsafe { CStr::from_ptr(*extension) }.to_str().unwrap();
-            required_extensions.push(extension_name);
+        if let Some(display_handle) = display_handle {
+            for extension in ash_window::enumerate_required_extensions(display_handle)
+                .unwrap()
+                .iter()
+            {
+                let extension_name = unsafe { CStr::from_ptr(*extension) }.to_str().unwrap();
+                required_extensions.push(extension_name);
+            }
         }
 
         required_extensions
     }
 
-    fn required_device_extensions() -> Vec<&'static str> {
-        vec![
-            "VK_KHR_swapchain",
+    fn required_device_extensions(presenting: bool) -> Vec<&'static str> {
+        let mut required_extensions = vec![
             #[cfg(target_os = "macos")]
             "VK_KHR_portability_subset",
-        ]
+        ];
+        if presenting {
+            required_extensions.push("VK_KHR_swapchain");
+        }
+        required_extensions
     }
 
     pub fn drop(&self) {
+        self.pipeline_cache.drop(&self.device);
+        self.memory_allocator.borrow_mut().drop();
         unsafe {
             self.device.destroy_device(None);
             #[cfg(feature = "validation-layers")]
@@ -770,30 +1176,3 @@ impl VulkanCore {
         }
     }
 }
-
-/// the callback function used in Debug Utils.
-#[cfg(feature = "validation-layers")]
-unsafe extern "system" fn vulkan_debug_utils_callback(
-    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
-    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
-    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut c_void,
-) -> vk::Bool32 {
-    let severity = match message_severity {
-        vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => "[Verbose]",
-        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => "[Warning]",
-        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => "[Error]",
-        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => "[Info]",
-        _ => "[Unknown]",
-    };
-    let types = match message_type {
-        vk::DebugUtilsMessageTypeFlagsEXT::GENERAL => "[General]",
-        vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE => "[Performance]",
-        vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION => "[Validation]",
-        _ => "[Unknown]",
-    };
-    let message = CStr::from_ptr((*p_callback_data).p_message);
-    println!("[Debug]{}{}{:?}", severity, types, message);
-
-    vk::FALSE
-}
@@ -0,0 +1,165 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "validation-layers")]
+use std::ffi::{c_void, CStr, CString};
+
+#[cfg(feature = "validation-layers")]
+use ash::ext;
+use ash::vk;
+
+static VALIDATION_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// How many errors the validation layers reported so far, always none without them.
+pub fn validation_errors() -> usize {
+    VALIDATION_ERRORS.load(Ordering::Relaxed)
+}
+
+/// Object names and command buffer labels, showing up in validation messages and in debuggers
+/// like RenderDoc. They need the validation-layers feature, without it they're simply skipped.
+#[derive(Clone)]
+pub(crate) struct DebugUtils {
+    #[cfg(feature = "validation-layers")]
+    loader: ext::debug_utils::Device,
+}
+
+#[cfg(feature = "validation-layers")]
+impl DebugUtils {
+    pub fn new(instance: &ash::Instance, device: &ash::Device) -> Self {
+        DebugUtils {
+            loader: ext::debug_utils::Device::new(instance, device),
+        }
+    }
+
+    pub fn name<T: vk::Handle>(&self, handle: T, name: &str) {
+        let name = CString::new(name).unwrap();
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(handle)
+            .object_name(&name);
+        // it only fails when out of host memory, which something else is going to notice
+        let _ = unsafe { self.loader.set_debug_utils_object_name(&name_info) };
+    }
+
+    /// Until the matching `end_label`, labels can be nested.
+    pub fn begin_label(&self, command_buffer: vk::CommandBuffer, label: &str) {
+        let label = CString::new(label).unwrap();
+        let label_info = vk::DebugUtilsLabelEXT::default().label_name(&label);
+        unsafe {
+            self.loader
+                .cmd_begin_debug_utils_label(command_buffer, &label_info)
+        };
+    }
+
+    pub fn end_label(&self, command_buffer: vk::CommandBuffer) {
+        unsafe { self.loader.cmd_end_debug_utils_label(command_buffer) };
+    }
+}
+
+#[cfg(not(feature = "validation-layers"))]
+impl DebugUtils {
+    pub fn new(_instance: &ash::Instance, _device: &ash::Device) -> Self {
+        DebugUtils {}
+    }
+
+    pub fn name<T: vk::Handle>(&self, _handle: T, _name: &str) {}
+
+    pub fn begin_label(&self, _command_buffer: vk::CommandBuffer, _label: &str) {}
+
+    pub fn end_label(&self, _command_buffer: vk::CommandBuffer) {}
+}
+
+/// Severities follow the max log level at the time, so that the layers don't even bother with
+/// messages nobody is going to see.
+#[cfg(feature = "validation-layers")]
+pub(crate) fn messenger_create_info() -> vk::DebugUtilsMessengerCreateInfoEXT<'static> {
+    vk::DebugUtilsMessengerCreateInfoEXT {
+        message_severity: severities(log::max_level()),
+        message_type: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+            | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
+            | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+        pfn_user_callback: Some(vulkan_debug_utils_callback),
+        ..Default::default()
+    }
+}
+
+/// Errors always, they get counted even when not logged.
+#[cfg(feature = "validation-layers")]
+fn severities(max_level: log::LevelFilter) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+    [
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING,
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
+        vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
+    ]
+    .into_iter()
+    .filter(|&severity| {
+        severity == vk::DebugUtilsMessageSeverityFlagsEXT::ERROR || log_level(severity) <= max_level
+    })
+    .fold(
+        vk::DebugUtilsMessageSeverityFlagsEXT::empty(),
+        |all, severity| all | severity,
+    )
+}
+
+#[cfg(feature = "validation-layers")]
+fn log_level(severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> log::Level {
+    match severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => log::Level::Error,
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => log::Level::Warn,
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => log::Level::Info,
+        _ => log::Level::Trace, // verbose, mostly the loader talking
+    }
+}
+
+/// the callback function used in Debug Utils.
+#[cfg(feature = "validation-layers")]
+unsafe extern "system" fn vulkan_debug_utils_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    _p_user_data: *mut c_void,
+) -> vk::Bool32 {
+    if message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
+        VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+    }
+    let types = match message_type {
+        vk::DebugUtilsMessageTypeFlagsEXT::GENERAL => "[General]",
+        vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE => "[Performance]",
+        vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION => "[Validation]",
+        _ => "[Unknown]",
+    };
+    let message = CStr::from_ptr((*p_callback_data).p_message);
+    log::log!(
+        target: "vulkan",
+        log_level(message_severity),
+        "{}{}",
+        types,
+        message.to_string_lossy()
+    );
+
+    vk::FALSE
+}
+
+#[cfg(all(test, feature = "validation-layers"))]
+mod tests {
+    use ash::vk::DebugUtilsMessageSeverityFlagsEXT as Severity;
+    use log::LevelFilter;
+    use rstest::*;
+
+    use crate::vulkan::debug::severities;
+
+    #[rstest(
+        max_level,
+        expected,
+        case(LevelFilter::Off, Severity::ERROR),
+        case(LevelFilter::Error, Severity::ERROR),
+        case(LevelFilter::Warn, Severity::ERROR | Severity::WARNING),
+        case(LevelFilter::Debug, Severity::ERROR | Severity::WARNING | Severity::INFO),
+        case(
+            LevelFilter::Trace,
+            Severity::ERROR | Severity::WARNING | Severity::INFO | Severity::VERBOSE
+        )
+    )]
+    fn reports_errors_and_whatever_gets_logged(max_level: LevelFilter, expected: Severity) {
+        assert_eq!(severities(max_level), expected);
+    }
+}
@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::fmt;
+
+use ash::vk;
+
+use crate::vulkan::gpu::GpuSelection;
+
+pub type VulkanResult<T> = Result<T, VulkanError>;
+
+/// What went wrong, readable enough to be shown as it is.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VulkanError {
+    /// A Vulkan call failed, with what it was supposed to do.
+    Call {
+        message: &'static str,
+        result: vk::Result,
+    },
+    /// No GPU supports everything needed.
+    NoSuitableGpu,
+    /// None of the suitable GPUs matches the selection.
+    GpuNotFound(GpuSelection),
+    /// The GPU doesn't support something needed, like a memory type or a depth format.
+    Unsupported(&'static str),
+    /// More lights than fit into the GPU's lights buffer.
+    TooManyLights { requested: usize, supported: usize },
+}
+
+impl VulkanError {
+    /// Another GPU may do better, unlike the same one once again.
+    pub fn is_gpu_specific(&self) -> bool {
+        match self {
+            VulkanError::Call { result, .. } => matches!(
+                *result,
+                vk::Result::ERROR_DEVICE_LOST
+                    | vk::Result::ERROR_INITIALIZATION_FAILED
+                    | vk::Result::ERROR_OUT_OF_DEVICE_MEMORY
+                    | vk::Result::ERROR_FEATURE_NOT_PRESENT
+                    | vk::Result::ERROR_EXTENSION_NOT_PRESENT
+                    | vk::Result::ERROR_INCOMPATIBLE_DRIVER
+            ),
+            VulkanError::NoSuitableGpu => false,
+            VulkanError::GpuNotFound(_) => false,
+            VulkanError::Unsupported(_) => true,
+            // the supported count depends on the GPU's buffer size limit
+            VulkanError::TooManyLights { .. } => true,
+        }
+    }
+}
+
+impl fmt::Display for VulkanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VulkanError::Call { message, result } => write!(f, "{}: {}", message, result),
+            VulkanError::NoSuitableGpu => write!(f, "Failed to find a suitable GPU"),
+            VulkanError::GpuNotFound(gpu_selection) => {
+                write!(f, "Failed to find a suitable GPU with {}", gpu_selection)
+            }
+            VulkanError::Unsupported(what) => write!(f, "Failed to find {}", what),
+            VulkanError::TooManyLights {
+                requested,
+                supported,
+            } => write!(
+                f,
+                "Too many lights: {}, at most {} are supported",
+                requested, supported
+            ),
+        }
+    }
+}
+
+impl Error for VulkanError {}
+
+/// For results of ash calls, saying what failed instead of just how.
+pub(crate) trait Context<T> {
+    fn context(self, message: &'static str) -> VulkanResult<T>;
+}
+
+impl<T> Context<T> for Result<T, vk::Result> {
+    fn context(self, message: &'static str) -> VulkanResult<T> {
+        self.map_err(|result| VulkanError::Call { message, result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use rstest::*;
+
+    use crate::vulkan::error::{Context, VulkanError};
+    use crate::vulkan::gpu::GpuSelection;
+
+    #[test]
+    fn context_keeps_successful_result() {
+        let result: Result<u32, vk::Result> = Ok(7);
+        assert_eq!(result.context("Failed to count"), Ok(7));
+    }
+
+    #[test]
+    fn context_says_what_failed() {
+        let result: Result<u32, vk::Result> = Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+        let error = result.context("Failed to create Swapchain").unwrap_err();
+        assert_eq!(
+            error,
+            VulkanError::Call {
+                message: "Failed to create Swapchain",
+                result: vk::Result::ERROR_OUT_OF_DEVICE_MEMORY,
+            }
+        );
+        assert!(error
+            .to_string()
+            .starts_with("Failed to create Swapchain: "));
+    }
+
+    #[rstest(
+        error,
+        expected,
+        case(VulkanError::Call { message: "", result: vk::Result::ERROR_DEVICE_LOST }, true),
+        case(VulkanError::Call { message: "", result: vk::Result::ERROR_OUT_OF_HOST_MEMORY }, false),
+        case(VulkanError::Call { message: "", result: vk::Result::ERROR_SURFACE_LOST_KHR }, false),
+        case(VulkanError::NoSuitableGpu, false),
+        case(VulkanError::GpuNotFound(GpuSelection::Index(3)), false),
+        case(VulkanError::Unsupported("suitable memory type"), true),
+        case(VulkanError::TooManyLights { requested: 9, supported: 8 }, true)
+    )]
+    fn tells_whether_another_gpu_may_help(error: VulkanError, expected: bool) {
+        assert_eq!(error.is_gpu_specific(), expected);
+    }
+
+    #[test]
+    fn displays_gpu_selection() {
+        let error = VulkanError::GpuNotFound(GpuSelection::Name("geforce".to_string()));
+        assert_eq!(
+            error.to_string(),
+            "Failed to find a suitable GPU with name \"geforce\""
+        );
+    }
+}
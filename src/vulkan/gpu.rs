@@ -0,0 +1,211 @@
+use std::fmt;
+
+use ash::vk;
+
+/// Which GPU to render with, instead of the best suitable one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GpuSelection {
+    /// As listed by `--list-gpus`.
+    Index(usize),
+    /// The first one of that type.
+    Type(vk::PhysicalDeviceType),
+    /// The first one with that in its name, ignoring case.
+    Name(String),
+}
+
+impl GpuSelection {
+    pub fn parse(selection: &str) -> Option<Self> {
+        if selection.is_empty() {
+            return None;
+        }
+        if let Ok(index) = selection.parse() {
+            return Some(GpuSelection::Index(index));
+        }
+        Some(match selection {
+            "discrete" => GpuSelection::Type(vk::PhysicalDeviceType::DISCRETE_GPU),
+            "integrated" => GpuSelection::Type(vk::PhysicalDeviceType::INTEGRATED_GPU),
+            "virtual" => GpuSelection::Type(vk::PhysicalDeviceType::VIRTUAL_GPU),
+            "cpu" => GpuSelection::Type(vk::PhysicalDeviceType::CPU),
+            name => GpuSelection::Name(name.to_lowercase()),
+        })
+    }
+
+    /// Index of the first suitable GPU matching it.
+    pub(crate) fn select(&self, gpus: &[Gpu]) -> Option<usize> {
+        gpus.iter()
+            .enumerate()
+            .filter(|(_, gpu)| gpu.suitable)
+            .find(|(index, gpu)| match self {
+                GpuSelection::Index(selected) => index == selected,
+                GpuSelection::Type(device_type) => gpu.device_type == *device_type,
+                GpuSelection::Name(name) => gpu.name.to_lowercase().contains(name),
+            })
+            .map(|(index, _)| index)
+    }
+}
+
+impl fmt::Display for GpuSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpuSelection::Index(index) => write!(f, "index {}", index),
+            GpuSelection::Type(device_type) => write!(f, "type {}", type_name(*device_type)),
+            GpuSelection::Name(name) => write!(f, "name \"{}\"", name),
+        }
+    }
+}
+
+/// What's worth knowing when choosing one.
+pub struct Gpu {
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub api_version: u32,
+    pub memory_mib: u64, // device local
+    pub suitable: bool,  // supports everything needed
+}
+
+impl fmt::Display for Gpu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, Vulkan {}.{}.{}, {} MiB)",
+            self.name,
+            type_name(self.device_type),
+            vk::api_version_major(self.api_version),
+            vk::api_version_minor(self.api_version),
+            vk::api_version_patch(self.api_version),
+            self.memory_mib
+        )
+    }
+}
+
+/// Index of the suitable GPU most likely to be the fastest one, judging by its type.
+pub(crate) fn preferred(gpus: &[Gpu]) -> Option<usize> {
+    gpus.iter()
+        .enumerate()
+        .filter(|(_, gpu)| gpu.suitable)
+        .min_by_key(|(_, gpu)| rank(gpu.device_type))
+        .map(|(index, _)| index)
+}
+
+/// Other suitable GPUs to try when the one picked with that selection failed, most likely the
+/// fastest first.
+pub fn fallbacks(gpus: &[Gpu], failed: Option<&GpuSelection>) -> Vec<usize> {
+    let failed = match failed {
+        Some(gpu_selection) => gpu_selection.select(gpus),
+        None => preferred(gpus),
+    };
+    let mut fallbacks: Vec<usize> = (0..gpus.len())
+        .filter(|&index| gpus[index].suitable && Some(index) != failed)
+        .collect();
+    fallbacks.sort_by_key(|&index| rank(gpus[index].device_type));
+    fallbacks
+}
+
+fn rank(device_type: vk::PhysicalDeviceType) -> u32 {
+    match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 0,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        vk::PhysicalDeviceType::CPU => 3,
+        _ => 4,
+    }
+}
+
+fn type_name(device_type: vk::PhysicalDeviceType) -> &'static str {
+    match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => "discrete",
+        vk::PhysicalDeviceType::INTEGRATED_GPU => "integrated",
+        vk::PhysicalDeviceType::VIRTUAL_GPU => "virtual",
+        vk::PhysicalDeviceType::CPU => "cpu",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use rstest::*;
+
+    use crate::vulkan::gpu::{fallbacks, preferred, Gpu, GpuSelection};
+
+    fn gpu(name: &str, device_type: vk::PhysicalDeviceType, suitable: bool) -> Gpu {
+        Gpu {
+            name: name.to_string(),
+            device_type,
+            api_version: vk::make_api_version(0, 1, 3, 0),
+            memory_mib: 1024,
+            suitable,
+        }
+    }
+
+    fn laptop() -> Vec<Gpu> {
+        vec![
+            gpu(
+                "Intel(R) UHD Graphics 620",
+                vk::PhysicalDeviceType::INTEGRATED_GPU,
+                true,
+            ),
+            gpu(
+                "NVIDIA GeForce MX150",
+                vk::PhysicalDeviceType::DISCRETE_GPU,
+                true,
+            ),
+            gpu("llvmpipe", vk::PhysicalDeviceType::CPU, true),
+            gpu("Broken", vk::PhysicalDeviceType::DISCRETE_GPU, false),
+        ]
+    }
+
+    #[rstest(
+        selection,
+        expected,
+        case("0", Some(0)),
+        case("2", Some(2)),
+        case("3", None), // not suitable
+        case("4", None),
+        case("integrated", Some(0)),
+        case("discrete", Some(1)),
+        case("cpu", Some(2)),
+        case("virtual", None),
+        case("geforce", Some(1)),
+        case("LLVM", Some(2)),
+        case("broken", None),
+        case("radeon", None)
+    )]
+    fn selects_matching_suitable_gpu(selection: &str, expected: Option<usize>) {
+        let selection = GpuSelection::parse(selection).unwrap();
+        assert_eq!(selection.select(&laptop()), expected);
+    }
+
+    #[test]
+    fn nothing_to_select_from_empty_string() {
+        assert_eq!(GpuSelection::parse(""), None);
+    }
+
+    #[test]
+    fn prefers_discrete_gpu() {
+        assert_eq!(preferred(&laptop()), Some(1));
+    }
+
+    #[test]
+    fn prefers_anything_suitable_over_nothing() {
+        let gpus = vec![
+            gpu("Broken", vk::PhysicalDeviceType::DISCRETE_GPU, false),
+            gpu("lavapipe", vk::PhysicalDeviceType::CPU, true),
+        ];
+        assert_eq!(preferred(&gpus), Some(1));
+        assert_eq!(preferred(&gpus[..1]), None);
+    }
+
+    #[rstest(
+        selection,
+        expected,
+        case(None, vec![0, 2]),
+        case(Some("integrated"), vec![1, 2]),
+        case(Some("cpu"), vec![1, 0]),
+        case(Some("radeon"), vec![1, 0, 2])
+    )]
+    fn falls_back_on_other_suitable_gpus(selection: Option<&str>, expected: Vec<usize>) {
+        let selection = selection.map(|selection| GpuSelection::parse(selection).unwrap());
+        assert_eq!(fallbacks(&laptop(), selection.as_ref()), expected);
+    }
+}
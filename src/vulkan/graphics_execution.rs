@@ -1,71 +1,144 @@
+use std::cell::RefCell;
+use std::ffi::c_void;
 use std::ptr;
 
 use ash::vk;
-use cgmath::{Matrix4, Point3};
+use cgmath::{Angle, Matrix4, Point3, Vector3, Zero};
+use image::imageops::FilterType;
 use image::RgbaImage;
 
+use crate::bulb_mesh::{BulbInstanceData, BulbMesh};
 use crate::color_mesh::ColorMesh;
+use crate::pbr_mesh::PbrMesh;
 use crate::scene::camera::Camera;
-use crate::scene::lights::{Light, Lights};
-use crate::textured_mesh::TexturedMesh;
-use crate::vulkan::core::VulkanCore;
-use crate::vulkan::graphics_setup::{VulkanGraphicsSetup, CAMERA_UBO_INDEX, LIGHTS_UBO_INDEX};
-
-const MAX_FRAMES_IN_FLIGHT: usize = 2;
+use crate::scene::fog::Fog;
+use crate::scene::lights::{Light, LightType, Lights};
+use crate::skybox::Skybox;
+use crate::textured_mesh::{flat_normal_map, TexturedMesh};
+use crate::vulkan::cleanup::Cleanup;
+use crate::vulkan::core::{ImageDescription, VulkanCore};
+use crate::vulkan::error::{Context, VulkanError, VulkanResult};
+use crate::vulkan::graphics_setup::{
+    VulkanGraphicsSetup, CAMERA_UBO_INDEX, COMBINED_IMAGE_SAMPLER_INDEX, FOG_UBO_INDEX,
+    LIGHTS_SSBO_INDEX, NORMAL_MAP_INDEX, SHADOW_MAP_INDEX, SHADOW_MAP_LAYERS,
+};
+use crate::vulkan::memory::Allocation;
+use crate::vulkan::rendering;
+use crate::vulkan::uploader::Uploader;
+use crate::vulkan::Vertex;
+
+pub(crate) const MAX_FRAMES_IN_FLIGHT: usize = 2;
+// more passes make the glow wider and smoother
+const BLOOM_BLUR_PASSES: usize = 2;
+// unless the device can't handle even that many
+const MAX_LIGHTS: usize = 256;
+// fog's buffers come after the camera's and the lights' ones, its binding is FOG_UBO_INDEX though
+const FOG_UNIFORM_BUFFERS: usize = 2;
+const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+// normals aren't colors, they mustn't get gamma corrected
+const NORMAL_MAP_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+// cgmath's projections map depth to [-1; 1] like OpenGL, Vulkan expects [0; 1]
+#[rustfmt::skip]
+const OPENGL_TO_VULKAN_DEPTH: Matrix4<f32> = Matrix4::new(
+    1., 0., 0., 0.,
+    0., 1., 0., 0.,
+    0., 0., 0.5, 0.,
+    0., 0., 0.5, 1.,
+);
 
 struct UniformBuffer {
-    buffers: Vec<vk::Buffer>,              // one per swapchain_image_count
-    buffers_memory: Vec<vk::DeviceMemory>, // one per swapchain_image_count
+    buffers: Vec<vk::Buffer>,        // one per swapchain_image_count
+    buffers_memory: Vec<Allocation>, // one per swapchain_image_count
 }
 
 #[derive(Clone, Copy)]
 struct VulkanColorMesh {
     vertex_buffer: vk::Buffer,
-    vertex_buffer_memory: vk::DeviceMemory,
+    vertex_buffer_memory: Allocation,
     index_buffer: vk::Buffer,
-    index_buffer_memory: vk::DeviceMemory,
+    index_buffer_memory: Allocation,
     indices_no: u32,
     instance_buffer: vk::Buffer,
-    instance_buffer_memory: vk::DeviceMemory,
+    instance_buffer_memory: Allocation,
     instances_no: u32,
+    // when set, how many instances to draw is decided on the GPU
+    indirect_buffer: Option<(vk::Buffer, Allocation)>,
+    upload: u64, // batch of the uploader its buffers are in
 }
 
 impl VulkanColorMesh {
-    fn drop(&self, device: &ash::Device) {
+    fn drop(&self, core: &VulkanCore) {
+        let device = &core.device;
         unsafe {
+            if let Some((buffer, buffer_memory)) = self.indirect_buffer {
+                device.destroy_buffer(buffer, None);
+                core.free_memory(buffer_memory);
+            }
             device.destroy_buffer(self.instance_buffer, None);
-            device.free_memory(self.instance_buffer_memory, None);
+            core.free_memory(self.instance_buffer_memory);
             device.destroy_buffer(self.index_buffer, None);
-            device.free_memory(self.index_buffer_memory, None);
+            core.free_memory(self.index_buffer_memory);
             device.destroy_buffer(self.vertex_buffer, None);
-            device.free_memory(self.vertex_buffer_memory, None);
+            core.free_memory(self.vertex_buffer_memory);
         }
     }
 
     fn from_color_mesh(
         mesh: &ColorMesh,
-        graphics_setup: &VulkanGraphicsSetup,
+        name: &str,
         graphics_execution: &VulkanGraphicsExecution,
-    ) -> Self {
-        let (vertex_buffer, vertex_buffer_memory) = VulkanGraphicsExecution::create_vertex_buffer(
-            &graphics_execution.core,
-            graphics_setup.command_pool,
+    ) -> VulkanResult<Self> {
+        VulkanColorMesh::new(
             &mesh.vertices,
-        );
-        let (index_buffer, index_buffer_memory) = VulkanGraphicsExecution::create_index_buffer(
-            &graphics_execution.core,
-            graphics_setup.command_pool,
             &mesh.indices,
-        );
-        let indices_no = mesh.indices.len() as u32;
+            &mesh.instances,
+            name,
+            graphics_execution,
+        )
+    }
+
+    /// Same buffers, only the instances differ.
+    fn from_pbr_mesh(
+        mesh: &PbrMesh,
+        graphics_execution: &VulkanGraphicsExecution,
+    ) -> VulkanResult<Self> {
+        VulkanColorMesh::new(
+            &mesh.vertices,
+            &mesh.indices,
+            &mesh.instances,
+            "pbr mesh",
+            graphics_execution,
+        )
+    }
+
+    fn new<T>(
+        vertices: &[Vertex],
+        indices: &[u32],
+        instances: &[T],
+        name: &str,
+        graphics_execution: &VulkanGraphicsExecution,
+    ) -> VulkanResult<Self> {
+        let mut uploader = graphics_execution.uploader.borrow_mut();
+        let (vertex_buffer, vertex_buffer_memory) = VulkanGraphicsExecution::create_vertex_buffer(
+            &mut uploader,
+            vertices,
+            &format!("{} vertices", name),
+        )?;
+        let (index_buffer, index_buffer_memory) = VulkanGraphicsExecution::create_index_buffer(
+            &mut uploader,
+            indices,
+            &format!("{} indices", name),
+        )?;
+        let indices_no = indices.len() as u32;
         let (instance_buffer, instance_buffer_memory) =
             VulkanGraphicsExecution::create_vertex_buffer(
-                &graphics_execution.core,
-                graphics_setup.command_pool,
-                &mesh.instances,
-            );
-        let instances_no = mesh.instances.len() as u32;
-        Self {
+                &mut uploader,
+                instances,
+                &format!("{} instances", name),
+            )?;
+        let instances_no = instances.len() as u32;
+        Ok(Self {
             vertex_buffer,
             vertex_buffer_memory,
             index_buffer,
@@ -74,40 +147,74 @@ impl VulkanColorMesh {
             instance_buffer,
             instance_buffer_memory,
             instances_no,
-        }
+            indirect_buffer: None,
+            upload: uploader.current_batch(),
+        })
+    }
+
+    /// Indirect draw command for all the instances, so that compute shaders can draw fewer.
+    fn with_indirect_draw(
+        mut self,
+        graphics_execution: &VulkanGraphicsExecution,
+    ) -> VulkanResult<Self> {
+        let command = [vk::DrawIndexedIndirectCommand {
+            index_count: self.indices_no,
+            instance_count: self.instances_no,
+            first_index: 0,
+            vertex_offset: 0,
+            first_instance: 0,
+        }];
+        let mut uploader = graphics_execution.uploader.borrow_mut();
+        self.indirect_buffer = Some(uploader.upload_buffer(
+            vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+            &command,
+            "indirect draw command",
+        )?);
+        self.upload = uploader.current_batch();
+        Ok(self)
     }
 }
 
 #[derive(Clone)]
 struct VulkanTexturedMesh {
     vertex_buffer: vk::Buffer,
-    vertex_buffer_memory: vk::DeviceMemory,
+    vertex_buffer_memory: Allocation,
     index_buffer: vk::Buffer,
-    index_buffer_memory: vk::DeviceMemory,
+    index_buffer_memory: Allocation,
     indices_no: u32,
     instance_buffer: vk::Buffer,
-    instance_buffer_memory: vk::DeviceMemory,
+    instance_buffer_memory: Allocation,
     instances_no: u32,
     texture_buffer: vk::Image,
-    texture_buffer_memory: vk::DeviceMemory,
+    texture_buffer_memory: Allocation,
     texture_image_view: vk::ImageView,
     texture_sampler: vk::Sampler,
+    normal_map_buffer: vk::Image,
+    normal_map_buffer_memory: Allocation,
+    normal_map_image_view: vk::ImageView,
+    normal_map_sampler: vk::Sampler,
     textured_descriptor_sets: Vec<vk::DescriptorSet>,
+    upload: u64,
 }
 
 impl VulkanTexturedMesh {
-    fn drop(&self, device: &ash::Device) {
+    fn drop(&self, core: &VulkanCore) {
+        let device = &core.device;
         unsafe {
+            device.destroy_sampler(self.normal_map_sampler, None);
+            device.destroy_image_view(self.normal_map_image_view, None);
+            device.destroy_image(self.normal_map_buffer, None);
+            core.free_memory(self.normal_map_buffer_memory);
             device.destroy_sampler(self.texture_sampler, None);
             device.destroy_image_view(self.texture_image_view, None);
             device.destroy_image(self.texture_buffer, None);
-            device.free_memory(self.texture_buffer_memory, None);
+            core.free_memory(self.texture_buffer_memory);
             device.destroy_buffer(self.instance_buffer, None);
-            device.free_memory(self.instance_buffer_memory, None);
+            core.free_memory(self.instance_buffer_memory);
             device.destroy_buffer(self.index_buffer, None);
-            device.free_memory(self.index_buffer_memory, None);
+            core.free_memory(self.index_buffer_memory);
             device.destroy_buffer(self.vertex_buffer, None);
-            device.free_memory(self.vertex_buffer_memory, None);
+            core.free_memory(self.vertex_buffer_memory);
         }
     }
 
@@ -115,39 +222,70 @@ impl VulkanTexturedMesh {
         mesh: &TexturedMesh,
         graphics_setup: &VulkanGraphicsSetup,
         graphics_execution: &VulkanGraphicsExecution,
-    ) -> Self {
+    ) -> VulkanResult<Self> {
+        let mut uploader = graphics_execution.uploader.borrow_mut();
         let (vertex_buffer, vertex_buffer_memory) = VulkanGraphicsExecution::create_vertex_buffer(
-            &graphics_execution.core,
-            graphics_setup.command_pool,
-            &mesh.vertices,
-        );
+            &mut uploader,
+            &mesh.vertices_with_tangents(),
+            "textured mesh vertices",
+        )?;
         let (index_buffer, index_buffer_memory) = VulkanGraphicsExecution::create_index_buffer(
-            &graphics_execution.core,
-            graphics_setup.command_pool,
+            &mut uploader,
             &mesh.indices,
-        );
+            "textured mesh indices",
+        )?;
         let indices_no = mesh.indices.len() as u32;
         let (instance_buffer, instance_buffer_memory) =
             VulkanGraphicsExecution::create_vertex_buffer(
-                &graphics_execution.core,
-                graphics_setup.command_pool,
+                &mut uploader,
                 &mesh.instances,
-            );
+                "textured mesh instances",
+            )?;
         let instances_no = mesh.instances.len() as u32;
-        let (texture_buffer, texture_buffer_memory) =
-            graphics_execution.create_texture(graphics_setup.command_pool, mesh.texture.clone());
-        let texture_image_view = graphics_execution.create_texture_image_view(texture_buffer);
-        let texture_sampler = graphics_execution.create_texture_sampler();
+        let (texture_buffer, texture_buffer_memory, mip_levels) = graphics_execution
+            .create_texture(
+                &mut uploader,
+                mesh.texture.clone(),
+                TEXTURE_FORMAT,
+                "textured mesh texture",
+            )?;
+        let texture_image_view = graphics_execution.create_texture_image_view(
+            texture_buffer,
+            mip_levels,
+            TEXTURE_FORMAT,
+        )?;
+        let texture_sampler = graphics_execution.create_texture_sampler(mip_levels)?;
+        let normal_map = mesh.normal_map.clone().unwrap_or_else(flat_normal_map);
+        let (normal_map_buffer, normal_map_buffer_memory, mip_levels) = graphics_execution
+            .create_texture(
+                &mut uploader,
+                normal_map,
+                NORMAL_MAP_FORMAT,
+                "textured mesh normal map",
+            )?;
+        let normal_map_image_view = graphics_execution.create_texture_image_view(
+            normal_map_buffer,
+            mip_levels,
+            NORMAL_MAP_FORMAT,
+        )?;
+        let normal_map_sampler = graphics_execution.create_texture_sampler(mip_levels)?;
         let textured_descriptor_sets = VulkanGraphicsExecution::create_textured_descriptor_sets(
             &graphics_execution.core.device,
-            graphics_setup.textured_descriptor_pool,
-            graphics_setup.textured_descriptor_set_layout,
             &graphics_execution.uniform_buffers,
             graphics_setup.swapchain_composite.images.len(),
-            texture_image_view,
-            texture_sampler,
-        );
-        Self {
+            vk::DescriptorImageInfo {
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                image_view: texture_image_view,
+                sampler: texture_sampler,
+            },
+            vk::DescriptorImageInfo {
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                image_view: normal_map_image_view,
+                sampler: normal_map_sampler,
+            },
+            graphics_setup,
+        )?;
+        Ok(Self {
             vertex_buffer,
             vertex_buffer_memory,
             index_buffer,
@@ -160,8 +298,249 @@ impl VulkanTexturedMesh {
             texture_buffer_memory,
             texture_image_view,
             texture_sampler,
+            normal_map_buffer,
+            normal_map_buffer_memory,
+            normal_map_image_view,
+            normal_map_sampler,
             textured_descriptor_sets,
+            upload: uploader.current_batch(),
+        })
+    }
+}
+
+/// Instances of the fairy lights change every frame, hence one host-visible instance buffer per
+/// swapchain image.
+struct VulkanBulbMesh {
+    vertex_buffer: vk::Buffer,
+    vertex_buffer_memory: Allocation,
+    index_buffer: vk::Buffer,
+    index_buffer_memory: Allocation,
+    indices_no: u32,
+    instance_buffers: Vec<vk::Buffer>,
+    instance_buffers_memory: Vec<Allocation>,
+    instances_no: u32,
+    upload: u64,
+}
+
+impl VulkanBulbMesh {
+    fn drop(&self, core: &VulkanCore) {
+        let device = &core.device;
+        unsafe {
+            for i in 0..self.instance_buffers.len() {
+                device.destroy_buffer(self.instance_buffers[i], None);
+                core.free_memory(self.instance_buffers_memory[i]);
+            }
+            device.destroy_buffer(self.index_buffer, None);
+            core.free_memory(self.index_buffer_memory);
+            device.destroy_buffer(self.vertex_buffer, None);
+            core.free_memory(self.vertex_buffer_memory);
+        }
+    }
+
+    fn from_bulb_mesh(
+        mesh: &BulbMesh,
+        graphics_setup: &VulkanGraphicsSetup,
+        graphics_execution: &VulkanGraphicsExecution,
+    ) -> VulkanResult<Self> {
+        let mut uploader = graphics_execution.uploader.borrow_mut();
+        let (vertex_buffer, vertex_buffer_memory) = VulkanGraphicsExecution::create_vertex_buffer(
+            &mut uploader,
+            &mesh.vertices,
+            "bulb mesh vertices",
+        )?;
+        let (index_buffer, index_buffer_memory) = VulkanGraphicsExecution::create_index_buffer(
+            &mut uploader,
+            &mesh.indices,
+            "bulb mesh indices",
+        )?;
+        let indices_no = mesh.indices.len() as u32;
+        let mut instance_buffers = vec![];
+        let mut instance_buffers_memory = vec![];
+        for i in 0..graphics_setup.swapchain_composite.images.len() {
+            let (instance_buffer, instance_buffer_memory) = graphics_execution.core.create_buffer(
+                std::mem::size_of_val(mesh.instances.as_slice()) as vk::DeviceSize,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                &format!("fairy light instances {}", i),
+            )?;
+            instance_buffers.push(instance_buffer);
+            instance_buffers_memory.push(instance_buffer_memory);
+        }
+        let instances_no = mesh.instances.len() as u32;
+        let bulb_mesh = Self {
+            vertex_buffer,
+            vertex_buffer_memory,
+            index_buffer,
+            index_buffer_memory,
+            indices_no,
+            instance_buffers,
+            instance_buffers_memory,
+            instances_no,
+            upload: uploader.current_batch(),
+        };
+        for image_index in 0..bulb_mesh.instance_buffers.len() {
+            bulb_mesh.write_instances(image_index, &mesh.instances);
+        }
+        Ok(bulb_mesh)
+    }
+
+    fn write_instances(&self, image_index: usize, instances: &[BulbInstanceData]) {
+        assert_eq!(instances.len(), self.instances_no as usize);
+        unsafe {
+            let data_ptr =
+                self.instance_buffers_memory[image_index].mapped_ptr::<BulbInstanceData>();
+            data_ptr.copy_from_nonoverlapping(instances.as_ptr(), instances.len());
+        }
+    }
+}
+
+struct VulkanSkybox {
+    image: vk::Image,
+    image_memory: Allocation,
+    image_view: vk::ImageView,
+    sampler: vk::Sampler,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    upload: u64,
+}
+
+impl VulkanSkybox {
+    fn drop(&self, core: &VulkanCore) {
+        let device = &core.device;
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_image_view(self.image_view, None);
+            device.destroy_image(self.image, None);
+            core.free_memory(self.image_memory);
+        }
+    }
+
+    fn from_skybox(
+        skybox: &Skybox,
+        graphics_setup: &VulkanGraphicsSetup,
+        graphics_execution: &VulkanGraphicsExecution,
+    ) -> VulkanResult<Self> {
+        let mut uploader = graphics_execution.uploader.borrow_mut();
+        let (image, image_memory) =
+            graphics_execution.create_cubemap(&mut uploader, &skybox.faces)?;
+        let image_view = graphics_execution.core.create_layered_image_view(
+            image,
+            vk::ImageViewType::CUBE,
+            TEXTURE_FORMAT,
+            vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: skybox.faces.len() as u32,
+            },
+        )?;
+        let sampler = VulkanSkybox::create_sampler(&graphics_execution.core.device)?;
+        let descriptor_sets = VulkanSkybox::create_descriptor_sets(
+            &graphics_execution.core.device,
+            graphics_setup.skybox_descriptor_pool,
+            graphics_setup.skybox_descriptor_set_layout,
+            &graphics_execution.uniform_buffers,
+            graphics_setup.swapchain_composite.images.len(),
+            image_view,
+            sampler,
+        )?;
+        Ok(Self {
+            image,
+            image_memory,
+            image_view,
+            sampler,
+            descriptor_sets,
+            upload: uploader.current_batch(),
+        })
+    }
+
+    /// Clamped, so that the faces' edges don't bleed into each other.
+    fn create_sampler(device: &ash::Device) -> VulkanResult<vk::Sampler> {
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            anisotropy_enable: vk::FALSE,
+            unnormalized_coordinates: vk::FALSE,
+            compare_enable: vk::FALSE,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            min_lod: 0.,
+            max_lod: 0.,
+            ..Default::default()
+        };
+
+        unsafe {
+            device
+                .create_sampler(&sampler_info, None)
+                .context("Failed to create skybox sampler")
+        }
+    }
+
+    fn create_descriptor_sets(
+        device: &ash::Device,
+        descriptor_pool: vk::DescriptorPool,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        uniforms_buffers: &[UniformBuffer],
+        swapchain_images_size: usize,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) -> VulkanResult<Vec<vk::DescriptorSet>> {
+        let layouts = vec![descriptor_set_layout; swapchain_images_size];
+
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: swapchain_images_size as u32,
+            p_set_layouts: layouts.as_ptr(),
+            ..Default::default()
+        };
+
+        let descriptor_sets = unsafe {
+            device
+                .allocate_descriptor_sets(&descriptor_set_allocate_info)
+                .context("Failed to allocate descriptor sets")?
+        };
+
+        for (i, &descriptor_set) in descriptor_sets.iter().enumerate() {
+            let camera_descriptor_buffer_info = [vk::DescriptorBufferInfo {
+                buffer: uniforms_buffers[CAMERA_UBO_INDEX].buffers[i],
+                offset: 0,
+                range: std::mem::size_of::<CameraUBO>() as u64,
+            }];
+            let descriptor_image_info = [vk::DescriptorImageInfo {
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                image_view,
+                sampler,
+            }];
+
+            let descriptor_write_sets = [
+                vk::WriteDescriptorSet {
+                    dst_set: descriptor_set,
+                    dst_binding: CAMERA_UBO_INDEX as u32,
+                    dst_array_element: 0,
+                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                    descriptor_count: camera_descriptor_buffer_info.len() as u32,
+                    p_buffer_info: camera_descriptor_buffer_info.as_ptr(),
+                    ..Default::default()
+                },
+                vk::WriteDescriptorSet {
+                    dst_set: descriptor_set,
+                    dst_binding: COMBINED_IMAGE_SAMPLER_INDEX as u32,
+                    dst_array_element: 0,
+                    descriptor_count: descriptor_image_info.len() as u32,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    p_image_info: descriptor_image_info.as_ptr(),
+                    ..Default::default()
+                },
+            ];
+
+            unsafe {
+                device.update_descriptor_sets(&descriptor_write_sets, &[]);
+            }
         }
+
+        Ok(descriptor_sets)
     }
 }
 
@@ -186,53 +565,146 @@ impl From<&Camera> for CameraUBO {
     }
 }
 
+// must match the constants in the fragment shaders
+const DIRECTIONAL_LIGHT: u32 = 0;
+const POINT_LIGHT: u32 = 1;
+const SPOT_LIGHT: u32 = 2;
+
 // TODO - how to handle layout 140 better?
 #[repr(C)]
 struct LightUBO {
     position: [f32; 3],
-    alignment_fix_1: f32,
+    light_type: u32,
+    direction: [f32; 3],
+    inner_cone_cos: f32,
     ambient: [f32; 3],
-    alignment_fix_2: f32,
+    outer_cone_cos: f32,
     diffuse: [f32; 3],
-    alignment_fix_3: f32,
+    alignment_fix_1: f32,
     specular: [f32; 3],
-    alignment_fix_4: f32,
+    alignment_fix_2: f32,
+    attenuation: [f32; 3],
+    alignment_fix_3: f32,
+    shadow_view_projection: Matrix4<f32>,
 }
 impl From<Light> for LightUBO {
     fn from(light: Light) -> Self {
+        // cosines of the cone angles, so that the shader can compare them with dot products
+        let (light_type, direction, inner_cone_cos, outer_cone_cos) = match light.light_type {
+            LightType::Directional { direction } => (DIRECTIONAL_LIGHT, direction, 0., 0.),
+            LightType::Point => (POINT_LIGHT, Vector3::zero(), 0., 0.),
+            LightType::Spot {
+                direction,
+                inner_cone,
+                outer_cone,
+            } => (SPOT_LIGHT, direction, inner_cone.cos(), outer_cone.cos()),
+        };
         LightUBO {
             position: light.position,
+            light_type,
+            direction: direction.into(),
+            inner_cone_cos,
             ambient: light.ambient,
+            outer_cone_cos,
             diffuse: light.diffuse,
-            specular: light.specular,
             alignment_fix_1: 0.0,
+            specular: light.specular,
             alignment_fix_2: 0.0,
+            attenuation: [
+                light.attenuation.constant,
+                light.attenuation.linear,
+                light.attenuation.quadratic,
+            ],
             alignment_fix_3: 0.0,
-            alignment_fix_4: 0.0,
+            shadow_view_projection: OPENGL_TO_VULKAN_DEPTH * light.shadow_view_projection(),
         }
     }
 }
 
 #[repr(C)]
-struct LightsUBO {
-    count: u32,
-    alignment_fix_1: [f32; 3],
-    lights: [LightUBO; 2], // hardcoded "2"
+struct FogUBO {
+    color: [f32; 3],
+    density: f32,
+    height_density: f32,
+    height_falloff: f32,
+    base_height: f32,
+    alignment_fix: f32,
 }
 
-impl From<&Lights> for LightsUBO {
-    fn from(lights: &Lights) -> Self {
-        LightsUBO {
-            count: lights.lights.len() as u32,
-            alignment_fix_1: [0., 0., 0.],
-            lights: [
-                LightUBO::from(lights.lights[0]),
-                LightUBO::from(lights.lights[1]),
-            ],
+impl From<&Fog> for FogUBO {
+    fn from(fog: &Fog) -> Self {
+        FogUBO {
+            color: fog.color,
+            density: fog.density,
+            height_density: fog.height_density,
+            height_falloff: fog.height_falloff,
+            base_height: fog.base_height,
+            alignment_fix: 0.0,
         }
     }
 }
 
+/// Beginning of the lights storage buffer, followed by `count` lights.
+#[repr(C)]
+struct LightsHeader {
+    count: u32,
+    shadow_casters: u32,
+    alignment_fix_1: [u32; 2],
+}
+
+fn lights_buffer_size(lights_count: usize) -> vk::DeviceSize {
+    (std::mem::size_of::<LightsHeader>() + lights_count * std::mem::size_of::<LightUBO>())
+        as vk::DeviceSize
+}
+
+/// Number of levels in a full mip chain, down to 1x1.
+fn mip_levels(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Same sizes the blits would produce, every level halves the previous one.
+fn cpu_mip_chain(data: RgbaImage, mip_levels: u32) -> Vec<RgbaImage> {
+    let mut mips = vec![data];
+    for _ in 1..mip_levels {
+        let previous = mips.last().unwrap();
+        let width = (previous.width() / 2).max(1);
+        let height = (previous.height() / 2).max(1);
+        let mip = image::imageops::resize(previous, width, height, FilterType::Triangle);
+        mips.push(mip);
+    }
+    mips
+}
+
+/// Whether captured pixels of that format need their red and blue swapped to become RGBA, `None`
+/// if they aren't 4 bytes of 8 bit channels.
+fn capture_swizzle(format: vk::Format) -> Option<bool> {
+    match format {
+        vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SNORM
+        | vk::Format::B8G8R8A8_USCALED
+        | vk::Format::B8G8R8A8_SSCALED
+        | vk::Format::B8G8R8A8_UINT
+        | vk::Format::B8G8R8A8_SINT
+        | vk::Format::B8G8R8A8_SRGB => Some(true),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SNORM
+        | vk::Format::R8G8B8A8_USCALED
+        | vk::Format::R8G8B8A8_SSCALED
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::R8G8B8A8_SINT
+        | vk::Format::R8G8B8A8_SRGB => Some(false),
+        _ => None,
+    }
+}
+
+struct PendingCapture {
+    command_buffers: Vec<vk::CommandBuffer>,
+    readback_buffer: vk::Buffer,
+    readback_buffer_memory: Allocation,
+    extent: vk::Extent2D,
+    format: vk::Format,
+}
+
 struct SyncObjects {
     image_available_semaphores: Vec<vk::Semaphore>,
     render_finished_semaphores: Vec<vk::Semaphore>,
@@ -246,60 +718,141 @@ pub(crate) struct VulkanGraphicsExecution {
     clear_value: [f32; 4],
 
     uniform_buffers: Vec<UniformBuffer>,
-    color_meshes: Vec<VulkanColorMesh>,
+    pbr_meshes: Vec<VulkanColorMesh>,
     textured_meshes: Vec<VulkanTexturedMesh>,
-    snow_mesh: Vec<VulkanColorMesh>,
+    snow_meshes: Vec<Vec<VulkanColorMesh>>, // one set per frame in flight, computed separately
+    bulb_meshes: Vec<VulkanBulbMesh>,
+    fairy_lights_mesh: Option<VulkanBulbMesh>,
+    fairy_lights_instances: Vec<BulbInstanceData>, // written into the next drawn image
+    skybox: Option<VulkanSkybox>,
+    uploader: RefCell<Uploader>, // meshes borrow it while being created
+    uploaded_batches: u64,       // the command buffers draw what's in them only
     color_descriptor_sets: Vec<vk::DescriptorSet>,
-    command_buffers: Vec<vk::CommandBuffer>,
+    shadow_descriptor_sets: Vec<vk::DescriptorSet>,
+    shadow_casters: usize,
+    max_lights: usize,
+    command_buffers: Vec<vk::CommandBuffer>, // per image and frame in flight, see `command_buffer`
 
     image_available_semaphores: Vec<vk::Semaphore>,
     render_finished_semaphores: Vec<vk::Semaphore>,
     in_flight_fences: Vec<vk::Fence>,
+    images_in_flight: Vec<vk::Fence>, // the fence of the last frame drawn into each image
     current_frame: usize,
 
+    capture_requested: bool,
+    captured_frame: Option<RgbaImage>,
+
     is_framebuffer_resized: bool,
 }
 
 impl VulkanGraphicsExecution {
-    pub(crate) fn new(core: VulkanCore, graphics_setup: &VulkanGraphicsSetup) -> Self {
+    pub(crate) fn new(
+        core: VulkanCore,
+        graphics_setup: &VulkanGraphicsSetup,
+    ) -> VulkanResult<Self> {
+        let mut cleanup = Cleanup::new(&core);
+        let max_lights = VulkanGraphicsExecution::find_max_lights(&core);
         let uniform_buffers = VulkanGraphicsExecution::create_uniform_buffers(
             &core,
             graphics_setup.swapchain_composite.images.len(),
-        );
+            max_lights,
+        )?;
+        for uniform_buffer in uniform_buffers.iter() {
+            for (&buffer, &buffer_memory) in uniform_buffer
+                .buffers
+                .iter()
+                .zip(uniform_buffer.buffers_memory.iter())
+            {
+                cleanup.push(move |core| unsafe {
+                    core.device.destroy_buffer(buffer, None);
+                    core.free_memory(buffer_memory);
+                });
+            }
+        }
         let color_descriptor_sets = VulkanGraphicsExecution::create_color_descriptor_sets(
             &core.device,
             graphics_setup.color_descriptor_pool,
             graphics_setup.color_descriptor_set_layout,
             &uniform_buffers,
             graphics_setup.swapchain_composite.images.len(),
-        );
-        let sync_objects = VulkanGraphicsExecution::create_sync_objects(&core);
+            graphics_setup,
+        )?;
+        let shadow_descriptor_sets = VulkanGraphicsExecution::create_shadow_descriptor_sets(
+            &core.device,
+            graphics_setup.shadow_descriptor_pool,
+            graphics_setup.shadow_descriptor_set_layout,
+            &uniform_buffers,
+            graphics_setup.swapchain_composite.images.len(),
+        )?;
+        let sync_objects = VulkanGraphicsExecution::create_sync_objects(&core)?;
+        for i in 0..MAX_FRAMES_IN_FLIGHT {
+            let image_available_semaphore = sync_objects.image_available_semaphores[i];
+            let render_finished_semaphore = sync_objects.render_finished_semaphores[i];
+            let inflight_fence = sync_objects.inflight_fences[i];
+            cleanup.push(move |core| unsafe {
+                core.device
+                    .destroy_semaphore(image_available_semaphore, None);
+                core.device
+                    .destroy_semaphore(render_finished_semaphore, None);
+                core.device.destroy_fence(inflight_fence, None);
+            });
+        }
+        let uploader = Uploader::new(core.clone())?;
+        cleanup.disarm();
 
-        VulkanGraphicsExecution {
+        Ok(VulkanGraphicsExecution {
             core,
 
             clear_value: [0.0, 0.0, 0.0, 0.0],
 
             uniform_buffers,
-            color_meshes: vec![],
+            pbr_meshes: vec![],
             textured_meshes: vec![],
-            snow_mesh: vec![],
+            snow_meshes: vec![],
+            bulb_meshes: vec![],
+            fairy_lights_mesh: None,
+            fairy_lights_instances: vec![],
+            skybox: None,
+            uploader: RefCell::new(uploader),
+            uploaded_batches: 0,
             color_descriptor_sets,
+            shadow_descriptor_sets,
+            shadow_casters: 0,
+            max_lights,
             command_buffers: vec![],
 
             image_available_semaphores: sync_objects.image_available_semaphores,
             render_finished_semaphores: sync_objects.render_finished_semaphores,
             in_flight_fences: sync_objects.inflight_fences,
+            images_in_flight: vec![
+                vk::Fence::null();
+                graphics_setup.swapchain_composite.images.len()
+            ],
             current_frame: 0,
 
+            capture_requested: false,
+            captured_frame: None,
+
             is_framebuffer_resized: false,
-        }
+        })
+    }
+
+    fn find_max_lights(core: &VulkanCore) -> usize {
+        let physical_device_properties = unsafe {
+            core.instance
+                .get_physical_device_properties(core.physical_device)
+        };
+        let max_buffer_size = physical_device_properties.limits.max_storage_buffer_range as usize;
+        let device_max_lights = (max_buffer_size - std::mem::size_of::<LightsHeader>())
+            / std::mem::size_of::<LightUBO>();
+        MAX_LIGHTS.min(device_max_lights)
     }
 
     fn create_uniform_buffers(
         core: &VulkanCore,
         swapchain_image_count: usize,
-    ) -> Vec<UniformBuffer> {
+        max_lights: usize,
+    ) -> VulkanResult<Vec<UniformBuffer>> {
         let mut uniform_buffers = vec![];
 
         {
@@ -308,12 +861,13 @@ impl VulkanGraphicsExecution {
             let mut buffers = vec![];
             let mut buffers_memory = vec![];
 
-            for _ in 0..swapchain_image_count {
+            for i in 0..swapchain_image_count {
                 let (uniform_buffer, uniform_buffer_memory) = core.create_buffer(
                     buffer_size as u64,
                     vk::BufferUsageFlags::UNIFORM_BUFFER,
                     vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-                );
+                    &format!("camera uniforms {}", i),
+                )?;
                 buffers.push(uniform_buffer);
                 buffers_memory.push(uniform_buffer_memory);
             }
@@ -324,17 +878,41 @@ impl VulkanGraphicsExecution {
             });
         }
         {
-            let buffer_size = std::mem::size_of::<LightsUBO>();
+            // big enough for the most lights there can be, so it never needs to grow
+            let buffer_size = lights_buffer_size(max_lights);
 
             let mut buffers = vec![];
             let mut buffers_memory = vec![];
 
-            for _ in 0..swapchain_image_count {
+            for i in 0..swapchain_image_count {
+                let (uniform_buffer, uniform_buffer_memory) = core.create_buffer(
+                    buffer_size,
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    &format!("lights uniforms {}", i),
+                )?;
+                buffers.push(uniform_buffer);
+                buffers_memory.push(uniform_buffer_memory);
+            }
+
+            uniform_buffers.push(UniformBuffer {
+                buffers,
+                buffers_memory,
+            });
+        }
+        {
+            let buffer_size = std::mem::size_of::<FogUBO>();
+
+            let mut buffers = vec![];
+            let mut buffers_memory = vec![];
+
+            for i in 0..swapchain_image_count {
                 let (uniform_buffer, uniform_buffer_memory) = core.create_buffer(
                     buffer_size as u64,
                     vk::BufferUsageFlags::UNIFORM_BUFFER,
                     vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-                );
+                    &format!("fog uniforms {}", i),
+                )?;
                 buffers.push(uniform_buffer);
                 buffers_memory.push(uniform_buffer_memory);
             }
@@ -344,16 +922,17 @@ impl VulkanGraphicsExecution {
                 buffers_memory,
             });
         }
-        uniform_buffers
+        Ok(uniform_buffers)
     }
 
     fn create_color_descriptor_sets(
         device: &ash::Device,
         descriptor_pool: vk::DescriptorPool,
         descriptor_set_layout: vk::DescriptorSetLayout,
-        uniforms_buffers: &Vec<UniformBuffer>,
+        uniforms_buffers: &[UniformBuffer],
         swapchain_images_size: usize,
-    ) -> Vec<vk::DescriptorSet> {
+        graphics_setup: &VulkanGraphicsSetup,
+    ) -> VulkanResult<Vec<vk::DescriptorSet>> {
         let mut layouts: Vec<vk::DescriptorSetLayout> = vec![];
         for _ in 0..swapchain_images_size {
             layouts.push(descriptor_set_layout);
@@ -369,7 +948,7 @@ impl VulkanGraphicsExecution {
         let descriptor_sets = unsafe {
             device
                 .allocate_descriptor_sets(&descriptor_set_allocate_info)
-                .expect("Failed to allocate descriptor sets!")
+                .context("Failed to allocate descriptor sets")?
         };
 
         for (i, &descriptor_set) in descriptor_sets.iter().enumerate() {
@@ -380,10 +959,19 @@ impl VulkanGraphicsExecution {
                 range: std::mem::size_of::<CameraUBO>() as u64,
             }];
             let lights_descriptor_buffer_info = [vk::DescriptorBufferInfo {
-                buffer: uniforms_buffers[LIGHTS_UBO_INDEX].buffers[i],
+                buffer: uniforms_buffers[LIGHTS_SSBO_INDEX].buffers[i],
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }];
+            let fog_descriptor_buffer_info = [vk::DescriptorBufferInfo {
+                buffer: uniforms_buffers[FOG_UNIFORM_BUFFERS].buffers[i],
                 offset: 0,
-                range: std::mem::size_of::<LightsUBO>() as u64,
+                range: std::mem::size_of::<FogUBO>() as u64,
             }];
+            let shadow_map_descriptor_image_info =
+                [VulkanGraphicsExecution::shadow_map_descriptor_image_info(
+                    graphics_setup,
+                )];
 
             let descriptor_write_sets = [
                 vk::WriteDescriptorSet {
@@ -399,37 +987,54 @@ impl VulkanGraphicsExecution {
                     dst_set: descriptor_set,
                     dst_binding: 1,
                     dst_array_element: 0,
-                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                    descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
                     descriptor_count: lights_descriptor_buffer_info.len() as u32,
                     p_buffer_info: lights_descriptor_buffer_info.as_ptr(),
                     ..Default::default()
                 },
-            ];
-
-            unsafe {
-                device.update_descriptor_sets(&descriptor_write_sets, &[]);
-            }
-        }
-
-        descriptor_sets
-    }
-
-    fn create_textured_descriptor_sets(
+                vk::WriteDescriptorSet {
+                    dst_set: descriptor_set,
+                    dst_binding: FOG_UBO_INDEX as u32,
+                    dst_array_element: 0,
+                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                    descriptor_count: fog_descriptor_buffer_info.len() as u32,
+                    p_buffer_info: fog_descriptor_buffer_info.as_ptr(),
+                    ..Default::default()
+                },
+                vk::WriteDescriptorSet {
+                    dst_set: descriptor_set,
+                    dst_binding: SHADOW_MAP_INDEX as u32,
+                    dst_array_element: 0,
+                    descriptor_count: shadow_map_descriptor_image_info.len() as u32,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    p_image_info: shadow_map_descriptor_image_info.as_ptr(),
+                    ..Default::default()
+                },
+            ];
+
+            unsafe {
+                device.update_descriptor_sets(&descriptor_write_sets, &[]);
+            }
+        }
+
+        Ok(descriptor_sets)
+    }
+
+    fn create_textured_descriptor_sets(
         device: &ash::Device,
-        descriptor_pool: vk::DescriptorPool,
-        descriptor_set_layout: vk::DescriptorSetLayout,
-        uniforms_buffers: &Vec<UniformBuffer>,
+        uniforms_buffers: &[UniformBuffer],
         swapchain_images_size: usize,
-        image_view: vk::ImageView,
-        sampler: vk::Sampler,
-    ) -> Vec<vk::DescriptorSet> {
+        texture: vk::DescriptorImageInfo,
+        normal_map: vk::DescriptorImageInfo,
+        graphics_setup: &VulkanGraphicsSetup,
+    ) -> VulkanResult<Vec<vk::DescriptorSet>> {
         let mut layouts: Vec<vk::DescriptorSetLayout> = vec![];
         for _ in 0..swapchain_images_size {
-            layouts.push(descriptor_set_layout);
+            layouts.push(graphics_setup.textured_descriptor_set_layout);
         }
 
         let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo {
-            descriptor_pool,
+            descriptor_pool: graphics_setup.textured_descriptor_pool,
             descriptor_set_count: swapchain_images_size as u32,
             p_set_layouts: layouts.as_ptr(),
             ..Default::default()
@@ -438,7 +1043,7 @@ impl VulkanGraphicsExecution {
         let descriptor_sets = unsafe {
             device
                 .allocate_descriptor_sets(&descriptor_set_allocate_info)
-                .expect("Failed to allocate descriptor sets!")
+                .context("Failed to allocate descriptor sets")?
         };
 
         for (i, &descriptor_set) in descriptor_sets.iter().enumerate() {
@@ -448,17 +1053,23 @@ impl VulkanGraphicsExecution {
                 range: std::mem::size_of::<CameraUBO>() as u64,
             }];
             let lights_descriptor_buffer_info = [vk::DescriptorBufferInfo {
-                buffer: uniforms_buffers[LIGHTS_UBO_INDEX].buffers[i],
+                buffer: uniforms_buffers[LIGHTS_SSBO_INDEX].buffers[i],
                 offset: 0,
-                range: std::mem::size_of::<LightsUBO>() as u64,
+                range: vk::WHOLE_SIZE,
             }];
-
-            let descriptor_image_info = [vk::DescriptorImageInfo {
-                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                image_view,
-                sampler,
+            let fog_descriptor_buffer_info = [vk::DescriptorBufferInfo {
+                buffer: uniforms_buffers[FOG_UNIFORM_BUFFERS].buffers[i],
+                offset: 0,
+                range: std::mem::size_of::<FogUBO>() as u64,
             }];
 
+            let descriptor_image_info = [texture];
+            let normal_map_descriptor_image_info = [normal_map];
+            let shadow_map_descriptor_image_info =
+                [VulkanGraphicsExecution::shadow_map_descriptor_image_info(
+                    graphics_setup,
+                )];
+
             let descriptor_write_sets = [
                 vk::WriteDescriptorSet {
                     dst_set: descriptor_set,
@@ -473,11 +1084,20 @@ impl VulkanGraphicsExecution {
                     dst_set: descriptor_set,
                     dst_binding: 1,
                     dst_array_element: 0,
-                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                    descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
                     descriptor_count: lights_descriptor_buffer_info.len() as u32,
                     p_buffer_info: lights_descriptor_buffer_info.as_ptr(),
                     ..Default::default()
                 },
+                vk::WriteDescriptorSet {
+                    dst_set: descriptor_set,
+                    dst_binding: FOG_UBO_INDEX as u32,
+                    dst_array_element: 0,
+                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                    descriptor_count: fog_descriptor_buffer_info.len() as u32,
+                    p_buffer_info: fog_descriptor_buffer_info.as_ptr(),
+                    ..Default::default()
+                },
                 vk::WriteDescriptorSet {
                     dst_set: descriptor_set,
                     dst_binding: 2,
@@ -487,6 +1107,24 @@ impl VulkanGraphicsExecution {
                     p_image_info: descriptor_image_info.as_ptr(),
                     ..Default::default()
                 },
+                vk::WriteDescriptorSet {
+                    dst_set: descriptor_set,
+                    dst_binding: SHADOW_MAP_INDEX as u32,
+                    dst_array_element: 0,
+                    descriptor_count: shadow_map_descriptor_image_info.len() as u32,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    p_image_info: shadow_map_descriptor_image_info.as_ptr(),
+                    ..Default::default()
+                },
+                vk::WriteDescriptorSet {
+                    dst_set: descriptor_set,
+                    dst_binding: NORMAL_MAP_INDEX as u32,
+                    dst_array_element: 0,
+                    descriptor_count: normal_map_descriptor_image_info.len() as u32,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    p_image_info: normal_map_descriptor_image_info.as_ptr(),
+                    ..Default::default()
+                },
             ];
 
             unsafe {
@@ -494,10 +1132,67 @@ impl VulkanGraphicsExecution {
             }
         }
 
-        descriptor_sets
+        Ok(descriptor_sets)
+    }
+
+    fn create_shadow_descriptor_sets(
+        device: &ash::Device,
+        descriptor_pool: vk::DescriptorPool,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        uniforms_buffers: &[UniformBuffer],
+        swapchain_images_size: usize,
+    ) -> VulkanResult<Vec<vk::DescriptorSet>> {
+        let layouts = vec![descriptor_set_layout; swapchain_images_size];
+
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: swapchain_images_size as u32,
+            p_set_layouts: layouts.as_ptr(),
+            ..Default::default()
+        };
+
+        let descriptor_sets = unsafe {
+            device
+                .allocate_descriptor_sets(&descriptor_set_allocate_info)
+                .context("Failed to allocate descriptor sets")?
+        };
+
+        for (i, &descriptor_set) in descriptor_sets.iter().enumerate() {
+            let lights_descriptor_buffer_info = [vk::DescriptorBufferInfo {
+                buffer: uniforms_buffers[LIGHTS_SSBO_INDEX].buffers[i],
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }];
+
+            let descriptor_write_sets = [vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: LIGHTS_SSBO_INDEX as u32,
+                dst_array_element: 0,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: lights_descriptor_buffer_info.len() as u32,
+                p_buffer_info: lights_descriptor_buffer_info.as_ptr(),
+                ..Default::default()
+            }];
+
+            unsafe {
+                device.update_descriptor_sets(&descriptor_write_sets, &[]);
+            }
+        }
+
+        Ok(descriptor_sets)
     }
 
-    fn create_sync_objects(core: &VulkanCore) -> SyncObjects {
+    fn shadow_map_descriptor_image_info(
+        graphics_setup: &VulkanGraphicsSetup,
+    ) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            image_view: graphics_setup.shadow_map_composite.image_view,
+            sampler: graphics_setup.shadow_map_composite.sampler,
+        }
+    }
+
+    fn create_sync_objects(core: &VulkanCore) -> VulkanResult<SyncObjects> {
         let mut sync_objects = SyncObjects {
             image_available_semaphores: vec![],
             render_finished_semaphores: vec![],
@@ -505,9 +1200,9 @@ impl VulkanGraphicsExecution {
         };
 
         for _ in 0..MAX_FRAMES_IN_FLIGHT {
-            let image_available_semaphore = core.create_semaphore();
-            let render_finished_semaphore = core.create_semaphore();
-            let inflight_fence = core.create_fence();
+            let image_available_semaphore = core.create_semaphore()?;
+            let render_finished_semaphore = core.create_semaphore()?;
+            let inflight_fence = core.create_fence()?;
 
             sync_objects
                 .image_available_semaphores
@@ -518,61 +1213,78 @@ impl VulkanGraphicsExecution {
             sync_objects.inflight_fences.push(inflight_fence);
         }
 
-        sync_objects
+        Ok(sync_objects)
     }
 
     pub(crate) fn update_camera(&mut self, camera: &Camera, graphics_setup: &VulkanGraphicsSetup) {
         let ubo: CameraUBO = CameraUBO::from(camera);
         let ubos = [ubo];
 
-        let buffer_size = (std::mem::size_of::<CameraUBO>() * ubos.len()) as u64;
-
         for current_image in 0..graphics_setup.swapchain_composite.images.len() {
             unsafe {
-                let data_ptr =
-                    self.core
-                        .device
-                        .map_memory(
-                            self.uniform_buffers[CAMERA_UBO_INDEX].buffers_memory[current_image],
-                            0,
-                            buffer_size,
-                            vk::MemoryMapFlags::empty(),
-                        )
-                        .expect("Failed to Map Memory") as *mut CameraUBO;
-
+                let data_ptr = self.uniform_buffers[CAMERA_UBO_INDEX].buffers_memory[current_image]
+                    .mapped_ptr::<CameraUBO>();
                 data_ptr.copy_from_nonoverlapping(ubos.as_ptr(), ubos.len());
-
-                self.core.device.unmap_memory(
-                    self.uniform_buffers[CAMERA_UBO_INDEX].buffers_memory[current_image],
-                );
             }
         }
     }
 
-    pub(crate) fn update_lights(&mut self, lights: &Lights, graphics_setup: &VulkanGraphicsSetup) {
-        let ubo: LightsUBO = LightsUBO::from(lights);
-        let ubos = [ubo];
-
-        let buffer_size = (std::mem::size_of::<LightsUBO>() * ubos.len()) as u64;
+    pub(crate) fn update_lights(
+        &mut self,
+        lights: &Lights,
+        graphics_setup: &VulkanGraphicsSetup,
+    ) -> VulkanResult<()> {
+        if lights.lights.len() > self.max_lights {
+            return Err(VulkanError::TooManyLights {
+                requested: lights.lights.len(),
+                supported: self.max_lights,
+            });
+        }
+        let shadow_casters = lights.lights.len().min(SHADOW_MAP_LAYERS);
+        let header = [LightsHeader {
+            count: lights.lights.len() as u32,
+            shadow_casters: shadow_casters as u32,
+            alignment_fix_1: [0, 0],
+        }];
+        let ubos: Vec<LightUBO> = lights.lights.iter().map(|&l| LightUBO::from(l)).collect();
 
         for current_image in 0..graphics_setup.swapchain_composite.images.len() {
             unsafe {
-                let data_ptr =
+                let data_ptr = self.uniform_buffers[LIGHTS_SSBO_INDEX].buffers_memory
+                    [current_image]
+                    .mapped_ptr::<LightsHeader>();
+                data_ptr.copy_from_nonoverlapping(header.as_ptr(), header.len());
+                let lights_ptr = data_ptr.add(1) as *mut LightUBO;
+                lights_ptr.copy_from_nonoverlapping(ubos.as_ptr(), ubos.len());
+            }
+        }
+
+        // shadow passes are recorded only for existing lights
+        if shadow_casters != self.shadow_casters {
+            self.shadow_casters = shadow_casters;
+            if !self.command_buffers.is_empty() {
+                unsafe {
                     self.core
                         .device
-                        .map_memory(
-                            self.uniform_buffers[LIGHTS_UBO_INDEX].buffers_memory[current_image],
-                            0,
-                            buffer_size,
-                            vk::MemoryMapFlags::empty(),
-                        )
-                        .expect("Failed to Map Memory") as *mut LightsUBO;
+                        .device_wait_idle()
+                        .context("Failed to wait device idle")?
+                };
+                self.cleanup_swapchain(graphics_setup.command_pool);
+                self.create_command_buffers(graphics_setup)?;
+            }
+        }
+        Ok(())
+    }
 
-                data_ptr.copy_from_nonoverlapping(ubos.as_ptr(), ubos.len());
+    pub(crate) fn update_fog(&mut self, fog: &Fog, graphics_setup: &VulkanGraphicsSetup) {
+        let ubos = [FogUBO::from(fog)];
 
-                self.core.device.unmap_memory(
-                    self.uniform_buffers[LIGHTS_UBO_INDEX].buffers_memory[current_image],
-                );
+        for current_image in 0..graphics_setup.swapchain_composite.images.len() {
+            unsafe {
+                let data_ptr = self.uniform_buffers[FOG_UNIFORM_BUFFERS].buffers_memory
+                    [current_image]
+                    .mapped_ptr::<FogUBO>();
+                data_ptr.copy_from_nonoverlapping(ubos.as_ptr(), ubos.len());
             }
         }
     }
@@ -583,41 +1295,97 @@ impl VulkanGraphicsExecution {
 
     pub(crate) fn set_static_meshes(
         &mut self,
-        color_meshes: &Vec<ColorMesh>,
-        textured_meshes: &Vec<TexturedMesh>,
+        pbr_meshes: &[PbrMesh],
+        textured_meshes: &[TexturedMesh],
+        bulb_meshes: &[BulbMesh],
         graphics_setup: &VulkanGraphicsSetup,
-    ) {
-        self.color_meshes = color_meshes
+    ) -> VulkanResult<()> {
+        self.pbr_meshes = pbr_meshes
             .iter()
-            .map(|m| VulkanColorMesh::from_color_mesh(m, graphics_setup, self))
-            .collect();
+            .map(|m| VulkanColorMesh::from_pbr_mesh(m, self))
+            .collect::<VulkanResult<_>>()?;
         self.textured_meshes = textured_meshes
             .iter()
             .map(|m| VulkanTexturedMesh::from_textured_mesh(m, graphics_setup, self))
-            .collect();
+            .collect::<VulkanResult<_>>()?;
+        self.bulb_meshes = bulb_meshes
+            .iter()
+            .map(|m| VulkanBulbMesh::from_bulb_mesh(m, graphics_setup, self))
+            .collect::<VulkanResult<_>>()?;
+        self.uploader.get_mut().flush()?;
+        Ok(())
     }
 
+    /// Returns the instance and the draw command buffer snow.comp writes into, for every frame in
+    /// flight. Unlike the swapchain images, there's always the same number of them.
     pub(crate) fn set_snow_mesh(
         &mut self,
-        meshes: &Vec<ColorMesh>,
-        graphics_setup: &VulkanGraphicsSetup,
-    ) -> (vk::Buffer, vk::DeviceMemory) {
-        self.snow_mesh = meshes
+        meshes: &[ColorMesh],
+    ) -> VulkanResult<Vec<(vk::Buffer, vk::Buffer)>> {
+        // snow.comp culls snowflakes the camera doesn't see
+        self.snow_meshes = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                meshes
+                    .iter()
+                    .map(|m| {
+                        VulkanColorMesh::from_color_mesh(m, "snow mesh", self)?
+                            .with_indirect_draw(self)
+                    })
+                    .collect()
+            })
+            .collect::<VulkanResult<_>>()?;
+        // snow.comp writes into them right away, they can't stream in
+        let uploader = self.uploader.get_mut();
+        uploader.flush()?;
+        uploader.wait_for_flushed()?;
+
+        Ok(self
+            .snow_meshes
             .iter()
-            .map(|m| VulkanColorMesh::from_color_mesh(m, graphics_setup, self))
-            .collect();
+            .map(|meshes| {
+                let last_mesh = meshes.last().unwrap();
+                (
+                    last_mesh.instance_buffer,
+                    last_mesh.indirect_buffer.unwrap().0,
+                )
+            })
+            .collect())
+    }
 
-        let last_mesh = self.snow_mesh.last().unwrap();
-        (
-            last_mesh.instance_buffer.clone(),
-            last_mesh.instance_buffer_memory.clone(),
-        )
+    pub(crate) fn set_fairy_lights_mesh(
+        &mut self,
+        mesh: &BulbMesh,
+        graphics_setup: &VulkanGraphicsSetup,
+    ) -> VulkanResult<()> {
+        self.fairy_lights_mesh = Some(VulkanBulbMesh::from_bulb_mesh(mesh, graphics_setup, self)?);
+        self.uploader.get_mut().flush()?;
+        Ok(())
+    }
+
+    pub(crate) fn update_fairy_lights(&mut self, instances: &[BulbInstanceData]) {
+        self.fairy_lights_instances = instances.to_vec();
+    }
+
+    pub(crate) fn set_skybox(
+        &mut self,
+        skybox: &Skybox,
+        graphics_setup: &VulkanGraphicsSetup,
+    ) -> VulkanResult<()> {
+        self.skybox = Some(VulkanSkybox::from_skybox(skybox, graphics_setup, self)?);
+        self.uploader.get_mut().flush()?;
+        Ok(())
     }
 
-    pub(crate) fn create_command_buffers(&mut self, graphics_setup: &VulkanGraphicsSetup) {
+    /// Meshes still being uploaded are left out, see `begin_frame`.
+    pub(crate) fn create_command_buffers(
+        &mut self,
+        graphics_setup: &VulkanGraphicsSetup,
+    ) -> VulkanResult<()> {
+        self.uploaded_batches = self.uploader.get_mut().uploaded_batches()?;
         let device = &self.core.device;
         let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
-            command_buffer_count: graphics_setup.swapchain_composite.framebuffers.len() as u32,
+            command_buffer_count: (graphics_setup.swapchain_composite.framebuffers.len()
+                * MAX_FRAMES_IN_FLIGHT) as u32,
             command_pool: graphics_setup.command_pool,
             level: vk::CommandBufferLevel::PRIMARY,
             ..Default::default()
@@ -626,10 +1394,16 @@ impl VulkanGraphicsExecution {
         let command_buffers = unsafe {
             device
                 .allocate_command_buffers(&command_buffer_allocate_info)
-                .expect("Failed to allocate Command Buffers!")
+                .context("Failed to allocate Command Buffers")?
         };
 
-        for (i, &command_buffer) in command_buffers.iter().enumerate() {
+        for (index, &command_buffer) in command_buffers.iter().enumerate() {
+            // image first, every frame in flight draws its own snow
+            let (i, frame) = (index / MAX_FRAMES_IN_FLIGHT, index % MAX_FRAMES_IN_FLIGHT);
+            self.core.debug_utils.name(
+                command_buffer,
+                &format!("commands for image {}, frame {}", i, frame),
+            );
             let command_buffer_begin_info = vk::CommandBufferBeginInfo {
                 p_inheritance_info: ptr::null(),
                 flags: vk::CommandBufferUsageFlags::SIMULTANEOUS_USE,
@@ -639,7 +1413,7 @@ impl VulkanGraphicsExecution {
             unsafe {
                 device
                     .begin_command_buffer(command_buffer, &command_buffer_begin_info)
-                    .expect("Failed to begin recording Command Buffer at beginning!");
+                    .context("Failed to begin recording Command Buffer at beginning")?;
             }
 
             let clear_values = [
@@ -656,79 +1430,242 @@ impl VulkanGraphicsExecution {
                 },
             ];
 
-            let render_pass_begin_info = vk::RenderPassBeginInfo {
-                render_pass: graphics_setup.render_pass,
-                framebuffer: graphics_setup.swapchain_composite.framebuffers[i],
-                render_area: vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: graphics_setup.swapchain_composite.extent,
-                },
-                clear_value_count: clear_values.len() as u32,
-                p_clear_values: clear_values.as_ptr(),
-                ..Default::default()
-            };
+            let scene_pass = graphics_setup.scene_pass()?;
 
-            unsafe {
-                device.cmd_begin_render_pass(
-                    command_buffer,
-                    &render_pass_begin_info,
-                    vk::SubpassContents::INLINE,
-                );
-                self.execute_color_pipeline(
-                    graphics_setup,
-                    i,
-                    command_buffer,
-                    self.color_meshes.clone(),
-                );
-                self.execute_textured_pipeline(
-                    graphics_setup,
-                    i,
-                    command_buffer,
-                    self.textured_meshes.clone(),
-                );
-                self.execute_color_pipeline(
-                    graphics_setup,
-                    i,
-                    command_buffer,
-                    self.snow_mesh.clone(),
-                );
-                device.cmd_end_render_pass(command_buffer);
+            self.execute_shadow_passes(graphics_setup, i, command_buffer);
+
+            let debug_utils = &self.core.debug_utils;
+            rendering::begin_pass(&self.core, command_buffer, &scene_pass, &clear_values);
+            debug_utils.begin_label(command_buffer, "color");
+            self.execute_color_pipeline(
+                i,
+                command_buffer,
+                graphics_setup.pbr_pipeline,
+                graphics_setup.pbr_pipeline_layout,
+                self.pbr_meshes.clone(),
+            );
+            debug_utils.end_label(command_buffer);
+            debug_utils.begin_label(command_buffer, "textured");
+            self.execute_textured_pipeline(
+                graphics_setup,
+                i,
+                command_buffer,
+                self.textured_meshes.clone(),
+            );
+            debug_utils.end_label(command_buffer);
+            debug_utils.begin_label(command_buffer, "snow");
+            self.execute_color_pipeline(
+                i,
+                command_buffer,
+                graphics_setup.color_pipeline,
+                graphics_setup.color_pipeline_layout,
+                self.snow_meshes.get(frame).cloned().unwrap_or_default(),
+            );
+            debug_utils.end_label(command_buffer);
+            self.execute_bulb_pipeline(graphics_setup, i, command_buffer);
+            // last, so that only pixels not covered by anything else get shaded
+            self.execute_skybox_pipeline(graphics_setup, i, command_buffer);
+            rendering::end_pass(&self.core, command_buffer, &scene_pass);
+
+            self.execute_post_passes(graphics_setup, i, command_buffer);
 
+            unsafe {
                 device
                     .end_command_buffer(command_buffer)
-                    .expect("Failed to record Command Buffer at Ending!");
+                    .context("Failed to record Command Buffer at Ending")?;
             }
         }
 
         self.command_buffers = command_buffers;
+        Ok(())
     }
 
-    fn execute_color_pipeline(
+    /// Renders depth of the shadow casting meshes into one shadow map layer per light. Layers
+    /// without a light get cleared only, so that all of them are in a layout ready to be sampled.
+    fn execute_shadow_passes(
         &self,
         graphics_setup: &VulkanGraphicsSetup,
         frame_index: usize,
         command_buffer: vk::CommandBuffer,
-        meshes: Vec<VulkanColorMesh>,
     ) {
         let device = &self.core.device;
+        let clear_values = [vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        }];
+
+        for light_index in 0..SHADOW_MAP_LAYERS {
+            let shadow_pass = graphics_setup.shadow_pass(light_index);
+            rendering::begin_pass(&self.core, command_buffer, &shadow_pass, &clear_values);
+            unsafe {
+                if light_index < self.shadow_casters {
+                    device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        graphics_setup.shadow_pipeline,
+                    );
+                    let descriptor_sets_to_bind = [self.shadow_descriptor_sets[frame_index]];
+                    device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        graphics_setup.shadow_pipeline_layout,
+                        0,
+                        &descriptor_sets_to_bind,
+                        &[],
+                    );
+                    let constants = (light_index as i32).to_le_bytes();
+                    device.cmd_push_constants(
+                        command_buffer,
+                        graphics_setup.shadow_pipeline_layout,
+                        vk::ShaderStageFlags::VERTEX,
+                        0,
+                        &constants,
+                    );
+                    // snowflakes are too small to cast any meaningful shadow
+                    for mesh in self
+                        .pbr_meshes
+                        .iter()
+                        .filter(|m| self.is_uploaded(m.upload))
+                    {
+                        let vertex_buffers = [mesh.vertex_buffer, mesh.instance_buffer];
+                        let offsets = [0_u64, 0_u64];
+
+                        device.cmd_bind_vertex_buffers(
+                            command_buffer,
+                            0,
+                            &vertex_buffers,
+                            &offsets,
+                        );
+                        device.cmd_bind_index_buffer(
+                            command_buffer,
+                            mesh.index_buffer,
+                            0,
+                            vk::IndexType::UINT32,
+                        );
+                        device.cmd_draw_indexed(
+                            command_buffer,
+                            mesh.indices_no,
+                            mesh.instances_no,
+                            0,
+                            0,
+                            0,
+                        );
+                    }
+                }
+            }
+            rendering::end_pass(&self.core, command_buffer, &shadow_pass);
+        }
+    }
+
+    /// Extracts the bright parts of the HDR image, blurs them and adds them back while tone mapping
+    /// into the swapchain image.
+    fn execute_post_passes(
+        &self,
+        graphics_setup: &VulkanGraphicsSetup,
+        image_index: usize,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let composite = &graphics_setup.post_processing_composite;
+        let bloom_extent = composite.bloom_extent;
+        let texel_width = 1. / bloom_extent.width as f32;
+        let texel_height = 1. / bloom_extent.height as f32;
+
+        self.execute_post_pass(
+            graphics_setup,
+            command_buffer,
+            &graphics_setup.bloom_pass(0),
+            composite.bright_pass_pipeline,
+            composite.bright_pass_descriptor_set,
+            [0., 0.],
+        );
+        for _ in 0..BLOOM_BLUR_PASSES {
+            self.execute_post_pass(
+                graphics_setup,
+                command_buffer,
+                &graphics_setup.bloom_pass(1),
+                composite.blur_pipeline,
+                composite.blur_descriptor_sets[0],
+                [texel_width, 0.],
+            );
+            self.execute_post_pass(
+                graphics_setup,
+                command_buffer,
+                &graphics_setup.bloom_pass(0),
+                composite.blur_pipeline,
+                composite.blur_descriptor_sets[1],
+                [0., texel_height],
+            );
+        }
+        self.execute_post_pass(
+            graphics_setup,
+            command_buffer,
+            &graphics_setup.tonemap_pass(image_index),
+            composite.tonemap_pipeline,
+            composite.tonemap_descriptor_set,
+            [0., 0.],
+        );
+    }
+
+    fn execute_post_pass(
+        &self,
+        graphics_setup: &VulkanGraphicsSetup,
+        command_buffer: vk::CommandBuffer,
+        pass: &rendering::Pass,
+        pipeline: vk::Pipeline,
+        descriptor_set: vk::DescriptorSet,
+        direction: [f32; 2],
+    ) {
+        let device = &self.core.device;
+        rendering::begin_pass(&self.core, command_buffer, pass, &[]);
         unsafe {
-            device.cmd_bind_pipeline(
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_bind_descriptor_sets(
                 command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
-                graphics_setup.color_pipeline,
+                graphics_setup.post_pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            let constants = [direction[0].to_le_bytes(), direction[1].to_le_bytes()].concat();
+            device.cmd_push_constants(
+                command_buffer,
+                graphics_setup.post_pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                &constants,
             );
+            // a single triangle covering the whole screen, see fullscreen.vert
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+        rendering::end_pass(&self.core, command_buffer, pass);
+    }
+
+    fn execute_color_pipeline(
+        &self,
+        frame_index: usize,
+        command_buffer: vk::CommandBuffer,
+        pipeline: vk::Pipeline, // either color or PBR one, they share descriptor sets
+        pipeline_layout: vk::PipelineLayout,
+        meshes: Vec<VulkanColorMesh>,
+    ) {
+        let device = &self.core.device;
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
 
             let descriptor_sets_to_bind = [self.color_descriptor_sets[frame_index]];
             device.cmd_bind_descriptor_sets(
                 command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
-                graphics_setup.color_pipeline_layout,
+                pipeline_layout,
                 0,
                 &descriptor_sets_to_bind,
                 &[],
             );
 
-            for mesh in meshes.iter() {
+            for mesh in meshes.iter().filter(|m| self.is_uploaded(m.upload)) {
                 let vertex_buffers = [mesh.vertex_buffer, mesh.instance_buffer];
                 let offsets = [0_u64, 0_u64];
 
@@ -739,14 +1676,23 @@ impl VulkanGraphicsExecution {
                     0,
                     vk::IndexType::UINT32,
                 );
-                device.cmd_draw_indexed(
-                    command_buffer,
-                    mesh.indices_no,
-                    mesh.instances_no,
-                    0,
-                    0,
-                    0,
-                );
+                match mesh.indirect_buffer {
+                    Some((indirect_buffer, _)) => device.cmd_draw_indexed_indirect(
+                        command_buffer,
+                        indirect_buffer,
+                        0,
+                        1,
+                        std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+                    ),
+                    None => device.cmd_draw_indexed(
+                        command_buffer,
+                        mesh.indices_no,
+                        mesh.instances_no,
+                        0,
+                        0,
+                        0,
+                    ),
+                }
             }
         }
     }
@@ -766,7 +1712,7 @@ impl VulkanGraphicsExecution {
                 graphics_setup.textured_pipeline,
             );
 
-            for mesh in meshes.iter() {
+            for mesh in meshes.iter().filter(|m| self.is_uploaded(m.upload)) {
                 let descriptor_sets_to_bind = [mesh.textured_descriptor_sets[frame_index]];
                 device.cmd_bind_descriptor_sets(
                     command_buffer,
@@ -799,67 +1745,190 @@ impl VulkanGraphicsExecution {
         }
     }
 
-    pub(crate) fn draw_frame(
-        &mut self,
-        graphics_setup: &mut VulkanGraphicsSetup,
-        snow_calculated_semaphore: vk::Semaphore,
+    fn execute_bulb_pipeline(
+        &self,
+        graphics_setup: &VulkanGraphicsSetup,
+        frame_index: usize,
+        command_buffer: vk::CommandBuffer,
     ) {
         let device = &self.core.device;
-        let wait_fences = [self.in_flight_fences[self.current_frame]];
-
         unsafe {
-            device
-                .wait_for_fences(&wait_fences, true, u64::MAX)
-                .expect("Failed to wait for Fence!");
-        }
-
-        let (image_index, _is_sub_optimal) = unsafe {
-            let result = graphics_setup
-                .swapchain_composite
-                .loader
-                .acquire_next_image(
-                    graphics_setup.swapchain_composite.swapchain,
-                    u64::MAX,
-                    self.image_available_semaphores[self.current_frame],
-                    vk::Fence::null(),
-                );
-            match result {
-                Ok(image_index) => image_index,
-                Err(vk_result) => match vk_result {
-                    vk::Result::ERROR_OUT_OF_DATE_KHR => {
-                        self.recreate_swapchain(graphics_setup);
-                        return;
-                    }
-                    _ => panic!("Failed to acquire Swap Chain Image!"),
-                },
-            }
-        };
-
-        let wait_semaphores = [
-            self.image_available_semaphores[self.current_frame],
-            snow_calculated_semaphore,
-        ];
-        let wait_stages = [
-            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            vk::PipelineStageFlags::VERTEX_INPUT,
-        ];
-        let signal_semaphores = [self.render_finished_semaphores[self.current_frame]];
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                graphics_setup.bulb_pipeline,
+            );
 
-        let submit_infos = [vk::SubmitInfo {
-            wait_semaphore_count: wait_semaphores.len() as u32,
-            p_wait_semaphores: wait_semaphores.as_ptr(),
+            let descriptor_sets_to_bind = [self.color_descriptor_sets[frame_index]];
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                graphics_setup.bulb_pipeline_layout,
+                0,
+                &descriptor_sets_to_bind,
+                &[],
+            );
+
+            for mesh in self
+                .bulb_meshes
+                .iter()
+                .chain(self.fairy_lights_mesh.iter())
+                .filter(|m| self.is_uploaded(m.upload))
+            {
+                let vertex_buffers = [mesh.vertex_buffer, mesh.instance_buffers[frame_index]];
+                let offsets = [0_u64, 0_u64];
+
+                device.cmd_bind_vertex_buffers(command_buffer, 0, &vertex_buffers, &offsets);
+                device.cmd_bind_index_buffer(
+                    command_buffer,
+                    mesh.index_buffer,
+                    0,
+                    vk::IndexType::UINT32,
+                );
+                device.cmd_draw_indexed(
+                    command_buffer,
+                    mesh.indices_no,
+                    mesh.instances_no,
+                    0,
+                    0,
+                    0,
+                );
+            }
+        }
+    }
+
+    fn execute_skybox_pipeline(
+        &self,
+        graphics_setup: &VulkanGraphicsSetup,
+        frame_index: usize,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let skybox = match &self.skybox {
+            Some(skybox) if self.is_uploaded(skybox.upload) => skybox,
+            _ => return, // clear color is the background then
+        };
+        let device = &self.core.device;
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                graphics_setup.skybox_pipeline,
+            );
+
+            let descriptor_sets_to_bind = [skybox.descriptor_sets[frame_index]];
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                graphics_setup.skybox_pipeline_layout,
+                0,
+                &descriptor_sets_to_bind,
+                &[],
+            );
+
+            // 6 faces, 2 triangles each
+            device.cmd_draw(command_buffer, 36, 1, 0, 0);
+        }
+    }
+
+    /// Waits until the image to draw into next, and everything drawn into it last time, is free.
+    /// None if there's no such image, because the swapchain had to be recreated.
+    pub(crate) fn begin_frame(
+        &mut self,
+        graphics_setup: &mut VulkanGraphicsSetup,
+    ) -> VulkanResult<Option<u32>> {
+        let wait_fences = [self.in_flight_fences[self.current_frame]];
+
+        unsafe {
+            self.core
+                .device
+                .wait_for_fences(&wait_fences, true, u64::MAX)
+                .context("Failed to wait for Fence")?;
+        }
+
+        let uploaded_batches = self.uploader.get_mut().uploaded_batches()?;
+        if uploaded_batches != self.uploaded_batches && !self.command_buffers.is_empty() {
+            // more meshes to draw, rarely enough to just wait for all the frames in flight
+            unsafe {
+                self.core
+                    .device
+                    .device_wait_idle()
+                    .context("Failed to wait device idle")?;
+            }
+            self.cleanup_swapchain(graphics_setup.command_pool);
+            self.create_command_buffers(graphics_setup)?;
+        }
+
+        let image_index = match self.acquire_next_image(graphics_setup)? {
+            Some(image_index) => image_index,
+            None => return Ok(None),
+        };
+        self.wait_for_image(image_index as usize)?;
+        if let Some(mesh) = &self.fairy_lights_mesh {
+            if !self.fairy_lights_instances.is_empty() {
+                mesh.write_instances(image_index as usize, &self.fairy_lights_instances);
+            }
+        }
+        Ok(Some(image_index))
+    }
+
+    /// Draws into the image from `begin_frame`, once the timeline semaphore reaches the value
+    /// meaning snow got calculated.
+    pub(crate) fn draw_frame(
+        &mut self,
+        graphics_setup: &mut VulkanGraphicsSetup,
+        image_index: u32,
+        snow_calculated_semaphore: vk::Semaphore,
+        snow_calculated_value: u64,
+    ) -> VulkanResult<()> {
+        let wait_fences = [self.in_flight_fences[self.current_frame]];
+        let presenting = !graphics_setup.swapchain_composite.is_offscreen();
+
+        let mut wait_semaphores = vec![snow_calculated_semaphore];
+        let mut wait_values = vec![snow_calculated_value];
+        // snow's draw command is read even before its instances
+        let mut wait_stages =
+            vec![vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_INPUT];
+        let mut signal_semaphores = vec![];
+        if presenting {
+            wait_semaphores.push(self.image_available_semaphores[self.current_frame]);
+            wait_values.push(0); // binary semaphore, ignored
+            wait_stages.push(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+            signal_semaphores.push(self.render_finished_semaphores[self.current_frame]);
+        }
+
+        let mut command_buffers = vec![self.command_buffer(image_index)];
+        let pending_capture = if self.capture_requested {
+            self.capture_requested = false;
+            let capture = self.record_capture(graphics_setup, image_index)?;
+            command_buffers.extend(&capture.command_buffers);
+            Some(capture)
+        } else {
+            None
+        };
+
+        let timeline_submit_info = vk::TimelineSemaphoreSubmitInfo {
+            wait_semaphore_value_count: wait_values.len() as u32,
+            p_wait_semaphore_values: wait_values.as_ptr(),
+            ..Default::default()
+        };
+        let submit_infos = [vk::SubmitInfo {
+            p_next: &timeline_submit_info as *const vk::TimelineSemaphoreSubmitInfo
+                as *const c_void,
+            wait_semaphore_count: wait_semaphores.len() as u32,
+            p_wait_semaphores: wait_semaphores.as_ptr(),
             p_wait_dst_stage_mask: wait_stages.as_ptr(),
-            command_buffer_count: 1,
-            p_command_buffers: &self.command_buffers[image_index as usize],
+            command_buffer_count: command_buffers.len() as u32,
+            p_command_buffers: command_buffers.as_ptr(),
             signal_semaphore_count: signal_semaphores.len() as u32,
             p_signal_semaphores: signal_semaphores.as_ptr(),
             ..Default::default()
         }];
 
+        let device = &self.core.device;
         unsafe {
             device
                 .reset_fences(&wait_fences)
-                .expect("Failed to reset Fence!");
+                .context("Failed to reset Fence")?;
 
             device
                 .queue_submit(
@@ -867,14 +1936,82 @@ impl VulkanGraphicsExecution {
                     &submit_infos,
                     self.in_flight_fences[self.current_frame],
                 )
-                .expect("Failed to execute queue submit.");
+                .context("Failed to execute queue submit")?;
+        }
+
+        if presenting {
+            self.present(graphics_setup, image_index, &signal_semaphores)?;
+        }
+        // there's only one offscreen image, the next frame can't start before this one is done
+        if !presenting || pending_capture.is_some() {
+            unsafe {
+                self.core
+                    .device
+                    .wait_for_fences(&wait_fences, true, u64::MAX)
+                    .context("Failed to wait for Fence")?;
+            }
+        }
+        if let Some(capture) = pending_capture {
+            self.captured_frame = Some(self.finish_capture(graphics_setup, capture));
         }
 
+        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        Ok(())
+    }
+
+    /// Swapchain images can be acquired out of order, the frame drawn into the image last time might
+    /// still be in flight. Only then its per-image buffers can be written into.
+    fn wait_for_image(&mut self, image_index: usize) -> VulkanResult<()> {
+        let image_fence = self.images_in_flight[image_index];
+        if image_fence != vk::Fence::null() {
+            unsafe {
+                self.core
+                    .device
+                    .wait_for_fences(&[image_fence], true, u64::MAX)
+                    .context("Failed to wait for Fence")?;
+            }
+        }
+        self.images_in_flight[image_index] = self.in_flight_fences[self.current_frame];
+        Ok(())
+    }
+
+    fn acquire_next_image(
+        &mut self,
+        graphics_setup: &mut VulkanGraphicsSetup,
+    ) -> VulkanResult<Option<u32>> {
+        let loader = match &graphics_setup.swapchain_composite.loader {
+            Some(loader) => loader,
+            None => return Ok(Some(0)),
+        };
+        let result = unsafe {
+            loader.acquire_next_image(
+                graphics_setup.swapchain_composite.swapchain,
+                u64::MAX,
+                self.image_available_semaphores[self.current_frame],
+                vk::Fence::null(),
+            )
+        };
+        match result {
+            Ok((image_index, _is_sub_optimal)) => Ok(Some(image_index)),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.recreate_swapchain(graphics_setup)?;
+                Ok(None)
+            }
+            Err(vk_result) => Err(vk_result).context("Failed to acquire Swap Chain Image"),
+        }
+    }
+
+    fn present(
+        &mut self,
+        graphics_setup: &mut VulkanGraphicsSetup,
+        image_index: u32,
+        wait_semaphores: &[vk::Semaphore],
+    ) -> VulkanResult<()> {
         let swapchains = [graphics_setup.swapchain_composite.swapchain];
 
         let present_info = vk::PresentInfoKHR {
-            wait_semaphore_count: 1,
-            p_wait_semaphores: signal_semaphores.as_ptr(),
+            wait_semaphore_count: wait_semaphores.len() as u32,
+            p_wait_semaphores: wait_semaphores.as_ptr(),
             swapchain_count: 1,
             p_swapchains: swapchains.as_ptr(),
             p_image_indices: &image_index,
@@ -886,21 +2023,182 @@ impl VulkanGraphicsExecution {
             graphics_setup
                 .swapchain_composite
                 .loader
+                .as_ref()
+                .unwrap()
                 .queue_present(self.core.present_queue, &present_info)
         };
         let is_resized = match result {
             Ok(_) => self.is_framebuffer_resized,
-            Err(vk_result) => match vk_result {
-                vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR => true,
-                _ => panic!("Failed to execute queue present."),
-            },
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR) => true,
+            Err(vk_result) => return Err(vk_result).context("Failed to execute queue present"),
         };
         if is_resized {
             self.is_framebuffer_resized = false;
-            self.recreate_swapchain(graphics_setup);
+            self.recreate_swapchain(graphics_setup)?;
         }
+        Ok(())
+    }
 
-        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+    /// Waits for all uploads, so that the whole scene is in the frame.
+    pub(crate) fn capture_next_frame(
+        &mut self,
+        graphics_setup: &VulkanGraphicsSetup,
+    ) -> VulkanResult<()> {
+        let swapchain_composite = &graphics_setup.swapchain_composite;
+        if !swapchain_composite.supports_capture {
+            return Err(VulkanError::Unsupported("copyable swapchain images"));
+        }
+        if capture_swizzle(swapchain_composite.format).is_none() {
+            return Err(VulkanError::Unsupported("swapchain format to capture"));
+        }
+        self.uploader.get_mut().wait_for_flushed()?;
+        self.capture_requested = true;
+        Ok(())
+    }
+
+    pub(crate) fn take_captured_frame(&mut self) -> Option<RgbaImage> {
+        self.captured_frame.take()
+    }
+
+    /// Records copying the rendered image into a host-visible buffer, to be submitted right after
+    /// the frame's own commands, before the image gets presented.
+    fn record_capture(
+        &self,
+        graphics_setup: &VulkanGraphicsSetup,
+        image_index: u32,
+    ) -> VulkanResult<PendingCapture> {
+        let swapchain_composite = &graphics_setup.swapchain_composite;
+        let extent = swapchain_composite.extent;
+        let image = swapchain_composite.images[image_index as usize];
+        let final_layout = swapchain_composite.final_layout();
+        let image_size = (extent.width * extent.height * 4) as vk::DeviceSize;
+
+        let (readback_buffer, readback_buffer_memory) = self.core.create_buffer(
+            image_size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            "frame capture readback",
+        )?;
+
+        let (command_buffers, command_buffer) = self
+            .core
+            .begin_one_time_commands(graphics_setup.command_pool)?;
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let to_transfer_barrier = vk::ImageMemoryBarrier {
+            image,
+            old_layout: final_layout,
+            new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            subresource_range,
+            src_access_mask: vk::AccessFlags::empty(), // made available by the render pass
+            dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            ..Default::default()
+        };
+        let region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+        };
+        let back_to_final_barrier = vk::ImageMemoryBarrier {
+            image,
+            old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            new_layout: final_layout,
+            subresource_range,
+            src_access_mask: vk::AccessFlags::TRANSFER_READ,
+            dst_access_mask: vk::AccessFlags::empty(),
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            ..Default::default()
+        };
+
+        unsafe {
+            let device = &self.core.device;
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer_barrier],
+            );
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback_buffer,
+                &[region],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[back_to_final_barrier],
+            );
+            device
+                .end_command_buffer(command_buffer)
+                .context("Failed to end Command Buffer")?;
+        }
+
+        Ok(PendingCapture {
+            command_buffers,
+            readback_buffer,
+            readback_buffer_memory,
+            extent,
+            format: swapchain_composite.format,
+        })
+    }
+
+    /// Reads the captured frame back, must be called only after the frame finished rendering.
+    fn finish_capture(
+        &self,
+        graphics_setup: &VulkanGraphicsSetup,
+        capture: PendingCapture,
+    ) -> RgbaImage {
+        let image_size = (capture.extent.width * capture.extent.height * 4) as vk::DeviceSize;
+        let mut pixels = vec![0_u8; image_size as usize];
+        unsafe {
+            let device = &self.core.device;
+            let data_ptr = capture.readback_buffer_memory.mapped_ptr::<u8>();
+            data_ptr.copy_to_nonoverlapping(pixels.as_mut_ptr(), pixels.len());
+
+            device.free_command_buffers(graphics_setup.command_pool, &capture.command_buffers);
+            device.destroy_buffer(capture.readback_buffer, None);
+        }
+        self.core.free_memory(capture.readback_buffer_memory);
+
+        // capturing other formats doesn't even start
+        if capture_swizzle(capture.format) == Some(true) {
+            // BGRA -> RGBA
+            pixels
+                .chunks_exact_mut(4)
+                .for_each(|pixel| pixel.swap(0, 2));
+        }
+
+        RgbaImage::from_raw(capture.extent.width, capture.extent.height, pixels)
+            .expect("Failed to create image from frame data")
     }
 
     pub(crate) fn cleanup_swapchain(&self, command_pool: vk::CommandPool) {
@@ -911,243 +2209,174 @@ impl VulkanGraphicsExecution {
         }
     }
 
-    fn recreate_swapchain(&mut self, graphics_setup: &mut VulkanGraphicsSetup) {
-        graphics_setup.recreate_swapchain();
-        self.create_command_buffers(graphics_setup);
+    fn recreate_swapchain(&mut self, graphics_setup: &mut VulkanGraphicsSetup) -> VulkanResult<()> {
+        graphics_setup.recreate_swapchain()?;
+        self.images_in_flight =
+            vec![vk::Fence::null(); graphics_setup.swapchain_composite.images.len()];
+        self.create_command_buffers(graphics_setup)
+    }
+
+    /// Swapchain, render pass, framebuffers and pipelines get rebuilt with new settings. The
+    /// device has to be idle.
+    pub(crate) fn settings_changed(
+        &mut self,
+        graphics_setup: &mut VulkanGraphicsSetup,
+    ) -> VulkanResult<()> {
+        self.cleanup_swapchain(graphics_setup.command_pool);
+        self.recreate_swapchain(graphics_setup)
+    }
+
+    /// Shaders changed, all graphics pipelines get rebuilt with them. The device has to be idle.
+    #[cfg(feature = "hot-reload")]
+    pub(crate) fn recreate_pipelines(
+        &mut self,
+        graphics_setup: &mut VulkanGraphicsSetup,
+    ) -> VulkanResult<()> {
+        self.cleanup_swapchain(graphics_setup.command_pool);
+        graphics_setup.recreate_shadow_pipeline()?;
+        self.recreate_swapchain(graphics_setup)
+    }
+
+    /// Frame in flight drawn next, see `set_snow_mesh`. Until `draw_frame` is done with it,
+    /// nothing else is using its resources, `begin_frame` waits for that.
+    pub(crate) fn current_frame(&self) -> usize {
+        self.current_frame
+    }
+
+    /// Draws into that image using the current frame's snow.
+    fn command_buffer(&self, image_index: u32) -> vk::CommandBuffer {
+        self.command_buffers[image_index as usize * MAX_FRAMES_IN_FLIGHT + self.current_frame]
     }
 
     pub(crate) fn framebuffer_resized(&mut self) {
         self.is_framebuffer_resized = true;
     }
 
+    fn is_uploaded(&self, upload: u64) -> bool {
+        upload <= self.uploaded_batches
+    }
+
     fn create_vertex_buffer<T>(
-        core: &VulkanCore,
-        command_pool: vk::CommandPool,
+        uploader: &mut Uploader,
         data: &[T],
-    ) -> (vk::Buffer, vk::DeviceMemory) {
-        core.create_data_buffer(
-            command_pool,
+        name: &str,
+    ) -> VulkanResult<(vk::Buffer, Allocation)> {
+        uploader.upload_buffer(
             vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
             data,
+            name,
         )
     }
 
     fn create_index_buffer(
-        core: &VulkanCore,
-        command_pool: vk::CommandPool,
+        uploader: &mut Uploader,
         data: &[u32],
-    ) -> (vk::Buffer, vk::DeviceMemory) {
-        core.create_data_buffer(command_pool, vk::BufferUsageFlags::INDEX_BUFFER, data)
+        name: &str,
+    ) -> VulkanResult<(vk::Buffer, Allocation)> {
+        uploader.upload_buffer(vk::BufferUsageFlags::INDEX_BUFFER, data, name)
     }
 
+    /// Uploads the texture together with its full mip chain. Mips are blitted on the GPU if the
+    /// format can be filtered linearly, otherwise they're scaled down on the CPU and uploaded too.
     fn create_texture(
         &self,
-        command_pool: vk::CommandPool,
+        uploader: &mut Uploader,
         data: RgbaImage,
-    ) -> (vk::Image, vk::DeviceMemory) {
-        let image_size = (data.width() * data.height() * 4) as vk::DeviceSize;
-        let (staging_buffer, staging_buffer_memory) = self.core.create_buffer(
-            image_size,
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-        );
-
-        unsafe {
-            let data_ptr = self
-                .core
-                .device
-                .map_memory(
-                    staging_buffer_memory,
-                    0,
-                    image_size,
-                    vk::MemoryMapFlags::empty(),
-                )
-                .expect("Failed to Map Memory") as *mut u8;
-
-            data_ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
-
-            self.core.device.unmap_memory(staging_buffer_memory);
-        }
-
-        let (image, image_memory) = self.core.create_image(
-            data.width(),
-            data.height(),
-            1,
-            vk::SampleCountFlags::TYPE_1,
-            vk::Format::R8G8B8A8_SRGB,
-            vk::ImageTiling::OPTIMAL,
-            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        );
-
-        self.transition_image_layout(
-            command_pool,
-            image,
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-        );
-
-        self.copy_buffer_to_image(
-            command_pool,
-            staging_buffer,
-            image,
-            data.width(),
-            data.height(),
-        );
-
-        self.transition_image_layout(
-            command_pool,
-            image,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-        );
-
-        unsafe {
-            self.core.device.destroy_buffer(staging_buffer, None);
-            self.core.device.free_memory(staging_buffer_memory, None);
-        }
-
-        (image, image_memory)
-    }
-
-    fn transition_image_layout(
-        &self,
-        command_pool: vk::CommandPool,
-        image: vk::Image,
-        old_layout: vk::ImageLayout,
-        new_layout: vk::ImageLayout,
-    ) {
-        let (command_buffers, command_buffer) = self.core.begin_one_time_commands(command_pool);
-
-        let src_access_mask: vk::AccessFlags;
-        let dst_access_mask: vk::AccessFlags;
-        let src_stage_mask: vk::PipelineStageFlags;
-        let dst_stage_mask: vk::PipelineStageFlags;
-
-        if old_layout == vk::ImageLayout::UNDEFINED
-            && new_layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL
-        {
-            src_access_mask = vk::AccessFlags::empty();
-            dst_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-            src_stage_mask = vk::PipelineStageFlags::TOP_OF_PIPE;
-            dst_stage_mask = vk::PipelineStageFlags::TRANSFER;
-        } else if old_layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL
-            && new_layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
-        {
-            src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-            dst_access_mask = vk::AccessFlags::SHADER_READ;
-            src_stage_mask = vk::PipelineStageFlags::TRANSFER;
-            dst_stage_mask = vk::PipelineStageFlags::FRAGMENT_SHADER;
+        format: vk::Format,
+        name: &str,
+    ) -> VulkanResult<(vk::Image, Allocation, u32)> {
+        let mip_levels = mip_levels(data.width(), data.height());
+        let mips = if self.supports_linear_blit(format) {
+            vec![data]
         } else {
-            panic!("Unsupported layout transition");
-        }
+            cpu_mip_chain(data, mip_levels)
+        };
 
-        let barrier = vk::ImageMemoryBarrier {
-            image,
-            old_layout,
-            new_layout,
-            subresource_range: vk::ImageSubresourceRange {
-                aspect_mask: vk::ImageAspectFlags::COLOR,
-                base_mip_level: 0,
-                level_count: 1,
-                base_array_layer: 0,
-                layer_count: 1,
+        let (image, image_memory) = self.core.create_image(
+            &ImageDescription {
+                extent: vk::Extent2D {
+                    width: mips[0].width(),
+                    height: mips[0].height(),
+                },
+                mip_levels,
+                format,
+                usage: vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST
+                    | vk::ImageUsageFlags::SAMPLED,
                 ..Default::default()
             },
-            src_access_mask,
-            dst_access_mask,
-            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-            ..Default::default()
-        };
+            name,
+        )?;
+        uploader.upload_image(image, &mips, false, mip_levels)?;
 
-        unsafe {
-            self.core.device.cmd_pipeline_barrier(
-                command_buffer,
-                src_stage_mask,
-                dst_stage_mask,
-                vk::DependencyFlags::BY_REGION,
-                &[],
-                &[],
-                &[barrier],
-            );
-        }
-
-        self.core
-            .end_one_time_commands(command_pool, &command_buffers, command_buffer);
+        Ok((image, image_memory, mip_levels))
     }
 
-    fn copy_buffer_to_image(
+    /// Six square faces in the order of Vulkan's cubemap layers, no mips.
+    fn create_cubemap(
         &self,
-        command_pool: vk::CommandPool,
-        buffer: vk::Buffer,
-        image: vk::Image,
-        width: u32,
-        height: u32,
-    ) {
-        let (command_buffers, command_buffer) = self.core.begin_one_time_commands(command_pool);
-
-        let region = vk::BufferImageCopy {
-            buffer_offset: 0,
-            buffer_row_length: 0,
-            buffer_image_height: 0,
-            image_subresource: vk::ImageSubresourceLayers {
-                aspect_mask: vk::ImageAspectFlags::COLOR,
-                mip_level: 0,
-                base_array_layer: 0,
-                layer_count: 1,
+        uploader: &mut Uploader,
+        faces: &[RgbaImage],
+    ) -> VulkanResult<(vk::Image, Allocation)> {
+        let (image, image_memory) = self.core.create_image(
+            &ImageDescription {
+                extent: vk::Extent2D {
+                    width: faces[0].width(),
+                    height: faces[0].height(),
+                },
+                array_layers: faces.len() as u32,
+                flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+                format: TEXTURE_FORMAT,
+                usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
                 ..Default::default()
             },
-            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
-            image_extent: vk::Extent3D {
-                width,
-                height,
-                depth: 1,
-            },
-            ..Default::default()
-        };
+            "skybox cubemap",
+        )?;
+        uploader.upload_image(image, faces, true, 1)?;
 
-        unsafe {
-            self.core.device.cmd_copy_buffer_to_image(
-                command_buffer,
-                buffer,
-                image,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                &[region],
-            );
-        }
+        Ok((image, image_memory))
+    }
 
-        self.core
-            .end_one_time_commands(command_pool, &command_buffers, command_buffer);
+    fn supports_linear_blit(&self, format: vk::Format) -> bool {
+        let format_properties = unsafe {
+            self.core
+                .instance
+                .get_physical_device_format_properties(self.core.physical_device, format)
+        };
+        format_properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
     }
 
-    pub(crate) fn create_texture_image_view(&self, texture_image: vk::Image) -> vk::ImageView {
+    pub(crate) fn create_texture_image_view(
+        &self,
+        texture_image: vk::Image,
+        mip_levels: u32,
+        format: vk::Format,
+    ) -> VulkanResult<vk::ImageView> {
         let view_info = vk::ImageViewCreateInfo {
             image: texture_image,
             view_type: vk::ImageViewType::TYPE_2D,
-            format: vk::Format::R8G8B8A8_SRGB,
+            format,
             subresource_range: vk::ImageSubresourceRange {
                 aspect_mask: vk::ImageAspectFlags::COLOR,
                 base_mip_level: 0,
-                level_count: 1,
+                level_count: mip_levels,
                 base_array_layer: 0,
                 layer_count: 1,
-                ..Default::default()
             },
             ..Default::default()
         };
 
-        let image_view = unsafe {
+        unsafe {
             self.core
                 .device
                 .create_image_view(&view_info, None)
-                .expect("Failed to create texture image view")
-        };
-
-        image_view
+                .context("Failed to create texture image view")
+        }
     }
 
-    fn create_texture_sampler(&self) -> vk::Sampler {
+    fn create_texture_sampler(&self, mip_levels: u32) -> VulkanResult<vk::Sampler> {
         let physical_device_properties = unsafe {
             self.core
                 .instance
@@ -1168,7 +2397,7 @@ impl VulkanGraphicsExecution {
             mipmap_mode: vk::SamplerMipmapMode::LINEAR,
             mip_lod_bias: 0.,
             min_lod: 0.,
-            max_lod: 0.,
+            max_lod: mip_levels as f32,
             ..Default::default()
         };
 
@@ -1176,7 +2405,7 @@ impl VulkanGraphicsExecution {
             self.core
                 .device
                 .create_sampler(&sampler_info, None)
-                .expect("Failed to create texture sampler")
+                .context("Failed to create texture sampler")
         }
     }
 
@@ -1189,15 +2418,51 @@ impl VulkanGraphicsExecution {
                 device.destroy_fence(self.in_flight_fences[i], None);
             }
 
-            self.color_meshes.iter().for_each(|m| m.drop(&device));
-            self.textured_meshes.iter().for_each(|m| m.drop(&device));
-            self.snow_mesh.iter().for_each(|m| m.drop(&device));
+            self.pbr_meshes.iter().for_each(|m| m.drop(&self.core));
+            self.textured_meshes.iter().for_each(|m| m.drop(&self.core));
+            self.snow_meshes
+                .iter()
+                .flatten()
+                .for_each(|m| m.drop(&self.core));
+            self.bulb_meshes.iter().for_each(|m| m.drop(&self.core));
+            if let Some(mesh) = &self.fairy_lights_mesh {
+                mesh.drop(&self.core);
+            }
+            if let Some(skybox) = &self.skybox {
+                skybox.drop(&self.core);
+            }
+            self.uploader.get_mut().drop();
             for j in 0..self.uniform_buffers.len() {
                 for i in 0..self.uniform_buffers[j].buffers.len() {
                     device.destroy_buffer(self.uniform_buffers[j].buffers[i], None);
-                    device.free_memory(self.uniform_buffers[j].buffers_memory[i], None);
+                    self.core
+                        .free_memory(self.uniform_buffers[j].buffers_memory[i]);
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use rstest::*;
+
+    use crate::vulkan::graphics_execution::capture_swizzle;
+
+    #[rstest(
+        format,
+        expected,
+        case(vk::Format::B8G8R8A8_UNORM, Some(true)),
+        case(vk::Format::B8G8R8A8_SRGB, Some(true)),
+        case(vk::Format::R8G8B8A8_SRGB, Some(false)),
+        case(vk::Format::A2B10G10R10_UNORM_PACK32, None),
+        case(vk::Format::R16G16B16A16_SFLOAT, None)
+    )]
+    fn swizzles_bgra_and_rejects_other_than_8_bit_channels(
+        format: vk::Format,
+        expected: Option<bool>,
+    ) {
+        assert_eq!(capture_swizzle(format), expected);
+    }
+}
@@ -3,33 +3,167 @@ use std::ptr;
 
 use ash::{khr, vk};
 
+use crate::bulb_mesh::BulbInstanceData;
 use crate::color_mesh;
+use crate::pbr_mesh::PbrInstanceData;
 use crate::textured_mesh;
 use crate::textured_mesh::TexturedVertex;
-use crate::vulkan::core::VulkanCore;
+use crate::vulkan::cleanup::Cleanup;
+use crate::vulkan::core::{ImageDescription, VulkanCore};
+use crate::vulkan::error::{Context, VulkanError, VulkanResult};
+use crate::vulkan::memory::Allocation;
+use crate::vulkan::msaa;
+use crate::vulkan::present_mode::PresentMode;
+use crate::vulkan::rendering;
 use crate::vulkan::{SurfaceComposite, Vertex};
 
 pub const CAMERA_UBO_INDEX: usize = 0;
-pub const LIGHTS_UBO_INDEX: usize = 1;
+pub const LIGHTS_SSBO_INDEX: usize = 1;
 pub const COMBINED_IMAGE_SAMPLER_INDEX: usize = 2;
+pub const SHADOW_MAP_INDEX: usize = 3;
+pub const NORMAL_MAP_INDEX: usize = 4; // textured meshes only
+pub const FOG_UBO_INDEX: usize = 5;
 
-const COLOR_VERTEX_SHADER_SPV: &[u8] = include_bytes!("../../target/shaders/color.vert.spv");
-const COLOR_FRAGMENT_SHADER_SPV: &[u8] = include_bytes!("../../target/shaders/color.frag.spv");
-const TEXTURED_VERTEX_SHADER_SPV: &[u8] = include_bytes!("../../target/shaders/textured.vert.spv");
-const TEXTURED_FRAGMENT_SHADER_SPV: &[u8] =
-    include_bytes!("../../target/shaders/textured.frag.spv");
+// one layer per shadow casting light, only the first lights cast shadows
+pub const SHADOW_MAP_LAYERS: usize = 4;
+const SHADOW_MAP_SIZE: u32 = 2048;
 
 const COLOR_FORMAT: vk::Format = vk::Format::B8G8R8A8_UNORM;
+// the scene gets rendered into it, every device supports it as a color attachment
+const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const BLOOM_DOWNSCALE: u32 = 2;
 
 #[derive(Clone)]
 pub struct SwapChainComposite {
-    pub loader: khr::swapchain::Device,
+    pub loader: Option<khr::swapchain::Device>, // None when rendering offscreen
     pub swapchain: vk::SwapchainKHR,
     pub images: Vec<vk::Image>,
-    format: vk::Format,
+    images_memory: Vec<Allocation>, // only offscreen images are owned by me
+    pub format: vk::Format,
     pub extent: vk::Extent2D,
     image_views: Vec<vk::ImageView>,
     pub framebuffers: Vec<vk::Framebuffer>,
+    pub supports_capture: bool, // images can be copied out
+}
+
+impl SwapChainComposite {
+    pub fn is_offscreen(&self) -> bool {
+        self.loader.is_none()
+    }
+
+    /// Layout the rendered images end up in, ready to be presented or copied out.
+    pub fn final_layout(&self) -> vk::ImageLayout {
+        if self.is_offscreen() {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        } else {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        }
+    }
+
+    fn drop(&self, core: &VulkanCore) {
+        let device = &core.device;
+        unsafe {
+            for &framebuffer in self.framebuffers.iter() {
+                device.destroy_framebuffer(framebuffer, None);
+            }
+            for &image_view in self.image_views.iter() {
+                device.destroy_image_view(image_view, None);
+            }
+            match &self.loader {
+                Some(loader) => loader.destroy_swapchain(self.swapchain, None),
+                None => {
+                    for i in 0..self.images.len() {
+                        device.destroy_image(self.images[i], None);
+                        core.free_memory(self.images_memory[i]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ShadowMapComposite {
+    image: vk::Image,
+    image_memory: Allocation,
+    pub image_view: vk::ImageView,         // all layers, for sampling
+    layer_image_views: Vec<vk::ImageView>, // one per layer, for rendering into
+    pub framebuffers: Vec<vk::Framebuffer>,
+    pub sampler: vk::Sampler,
+    pub extent: vk::Extent2D,
+}
+
+impl ShadowMapComposite {
+    fn drop(&self, core: &VulkanCore) {
+        let device = &core.device;
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            for &framebuffer in self.framebuffers.iter() {
+                device.destroy_framebuffer(framebuffer, None);
+            }
+            for &image_view in self.layer_image_views.iter() {
+                device.destroy_image_view(image_view, None);
+            }
+            device.destroy_image_view(self.image_view, None);
+            device.destroy_image(self.image, None);
+            core.free_memory(self.image_memory);
+        }
+    }
+}
+
+/// Render passes and attachments post-processing builds on, the HDR image joins the scene's ones.
+#[derive(Clone, Copy)]
+struct PostProcessingTargets {
+    render_pass: vk::RenderPass, // the scene's
+    bloom_render_pass: vk::RenderPass,
+    tonemap_render_pass: vk::RenderPass,
+    tonemap_format: vk::Format,
+    color_image_view: vk::ImageView, // multisampled, unused without MSAA
+    depth_image_view: vk::ImageView,
+    msaa_samples: vk::SampleCountFlags,
+}
+
+/// The scene is rendered into an HDR image first. Its brightest parts get blurred into a smaller bloom
+/// image, which is added back while tone mapping the HDR image into a swapchain image.
+pub struct PostProcessingComposite {
+    hdr_image: vk::Image,
+    hdr_image_memory: Allocation,
+    hdr_image_view: vk::ImageView,
+    pub hdr_framebuffer: vk::Framebuffer,
+    bloom_images: Vec<vk::Image>, // two, blurring goes back and forth between them
+    bloom_images_memory: Vec<Allocation>,
+    bloom_image_views: Vec<vk::ImageView>,
+    pub bloom_framebuffers: Vec<vk::Framebuffer>,
+    pub bloom_extent: vk::Extent2D,
+    sampler: vk::Sampler,
+    pub bright_pass_descriptor_set: vk::DescriptorSet, // reads the HDR image
+    pub blur_descriptor_sets: Vec<vk::DescriptorSet>,  // [i] reads bloom image i
+    pub tonemap_descriptor_set: vk::DescriptorSet,     // reads the HDR and bloom image 0
+    pub bright_pass_pipeline: vk::Pipeline,
+    pub blur_pipeline: vk::Pipeline,
+    pub tonemap_pipeline: vk::Pipeline,
+}
+
+impl PostProcessingComposite {
+    fn drop(&self, core: &VulkanCore) {
+        let device = &core.device;
+        unsafe {
+            device.destroy_pipeline(self.tonemap_pipeline, None);
+            device.destroy_pipeline(self.blur_pipeline, None);
+            device.destroy_pipeline(self.bright_pass_pipeline, None);
+            device.destroy_sampler(self.sampler, None);
+            for i in 0..self.bloom_images.len() {
+                device.destroy_framebuffer(self.bloom_framebuffers[i], None);
+                device.destroy_image_view(self.bloom_image_views[i], None);
+                device.destroy_image(self.bloom_images[i], None);
+                core.free_memory(self.bloom_images_memory[i]);
+            }
+            device.destroy_framebuffer(self.hdr_framebuffer, None);
+            device.destroy_image_view(self.hdr_image_view, None);
+            device.destroy_image(self.hdr_image, None);
+            core.free_memory(self.hdr_image_memory);
+        }
+    }
 }
 
 pub struct SwapChainSupportDetails {
@@ -41,30 +175,54 @@ pub struct SwapChainSupportDetails {
 pub struct VulkanGraphicsSetup {
     core: VulkanCore,
 
-    surface_composite: SurfaceComposite,
-    pub swapchain_composite: SwapChainComposite,
+    surface_composite: Option<SurfaceComposite>,
+    pub swapchain_composite: SwapChainComposite, // framebuffers are for tone mapping
+    present_mode: PresentMode,
 
-    pub render_pass: vk::RenderPass,
+    pub render_pass: vk::RenderPass, // draws the scene in HDR
     pub color_descriptor_set_layout: vk::DescriptorSetLayout,
     pub color_pipeline_layout: vk::PipelineLayout,
     pub color_pipeline: vk::Pipeline,
+    pub pbr_pipeline_layout: vk::PipelineLayout, // shares color descriptor sets
+    pub pbr_pipeline: vk::Pipeline,
     pub textured_descriptor_set_layout: vk::DescriptorSetLayout,
     pub textured_pipeline_layout: vk::PipelineLayout,
     pub textured_pipeline: vk::Pipeline,
+    pub bulb_pipeline_layout: vk::PipelineLayout, // shares color descriptor sets
+    pub bulb_pipeline: vk::Pipeline,
+    pub skybox_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub skybox_pipeline_layout: vk::PipelineLayout,
+    pub skybox_pipeline: vk::Pipeline,
+
+    pub bloom_render_pass: vk::RenderPass,
+    pub tonemap_render_pass: vk::RenderPass,
+    post_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub post_pipeline_layout: vk::PipelineLayout,
+    pub post_processing_composite: PostProcessingComposite,
+
+    pub shadow_render_pass: vk::RenderPass,
+    pub shadow_map_composite: ShadowMapComposite,
+    pub shadow_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub shadow_pipeline_layout: vk::PipelineLayout,
+    pub shadow_pipeline: vk::Pipeline,
 
     msaa_samples: vk::SampleCountFlags,
 
+    // multisampled, all null without MSAA, the scene is then rendered straight into the HDR image
     color_image: vk::Image,
     color_image_view: vk::ImageView,
-    color_image_memory: vk::DeviceMemory,
+    color_image_memory: Allocation,
 
     depth_image: vk::Image,
     depth_image_view: vk::ImageView,
-    depth_image_memory: vk::DeviceMemory,
+    depth_image_memory: Allocation,
 
     pub command_pool: vk::CommandPool,
     pub color_descriptor_pool: vk::DescriptorPool,
     pub textured_descriptor_pool: vk::DescriptorPool,
+    pub skybox_descriptor_pool: vk::DescriptorPool,
+    pub shadow_descriptor_pool: vk::DescriptorPool,
+    post_descriptor_pool: vk::DescriptorPool,
 
     window_width: u32,
     window_height: u32,
@@ -74,30 +232,90 @@ impl VulkanGraphicsSetup {
     pub fn new(
         core: VulkanCore,
         surface_composite: SurfaceComposite,
-        window: &winit::window::Window,
-    ) -> Self {
-        let window_width = window.inner_size().width;
-        let window_height = window.inner_size().height;
-        let mut swapchain_composite = VulkanGraphicsSetup::create_swapchain(
+        window_width: u32,
+        window_height: u32,
+    ) -> VulkanResult<Self> {
+        let swapchain_composite = VulkanGraphicsSetup::create_swapchain(
             &core,
             &surface_composite,
             window_width,
             window_height,
+            PresentMode::default(),
+        )
+        .inspect_err(|_| unsafe {
+            surface_composite
+                .loader
+                .destroy_surface(surface_composite.surface, None)
+        })?;
+        VulkanGraphicsSetup::init(
+            core,
+            Some(surface_composite),
+            swapchain_composite,
+            window_width,
+            window_height,
+        )
+    }
+
+    /// Renders into an offscreen color image standing in for the swapchain images.
+    pub fn new_headless(core: VulkanCore, width: u32, height: u32) -> VulkanResult<Self> {
+        let swapchain_composite =
+            VulkanGraphicsSetup::create_offscreen_targets(&core, width, height)?;
+        VulkanGraphicsSetup::init(core, None, swapchain_composite, width, height)
+    }
+
+    fn init(
+        core: VulkanCore,
+        surface_composite: Option<SurfaceComposite>,
+        mut swapchain_composite: SwapChainComposite,
+        window_width: u32,
+        window_height: u32,
+    ) -> VulkanResult<Self> {
+        let mut cleanup = Cleanup::new(&core);
+        if let Some(surface_composite) = &surface_composite {
+            cleanup.push(|_| unsafe {
+                surface_composite
+                    .loader
+                    .destroy_surface(surface_composite.surface, None)
+            });
+        }
+        cleanup.push(|core| swapchain_composite.drop(core));
+        let image_views =
+            VulkanGraphicsSetup::create_image_views(&core.device, &swapchain_composite)?;
+        for &image_view in image_views.iter() {
+            cleanup.push(move |core| unsafe { core.device.destroy_image_view(image_view, None) });
+        }
+        let msaa_samples = msaa::highest_supported(
+            VulkanGraphicsSetup::supported_msaa_samples(&core),
+            msaa::DEFAULT_MAX_SAMPLES,
         );
-        swapchain_composite.image_views =
-            VulkanGraphicsSetup::create_image_views(&core.device, &swapchain_composite);
-        let msaa_samples = VulkanGraphicsSetup::choose_msaa_samples(&core);
-        let render_pass = VulkanGraphicsSetup::create_render_pass(
+        let render_pass = VulkanGraphicsSetup::create_render_pass(&core, msaa_samples)?;
+        cleanup.push(move |core| unsafe { core.device.destroy_render_pass(render_pass, None) });
+        let bloom_render_pass = VulkanGraphicsSetup::create_post_render_pass(
+            &core,
+            HDR_FORMAT,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_render_pass(bloom_render_pass, None);
+        });
+        let tonemap_render_pass = VulkanGraphicsSetup::create_post_render_pass(
             &core,
             swapchain_composite.format,
-            msaa_samples,
-        );
+            swapchain_composite.final_layout(),
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_render_pass(tonemap_render_pass, None)
+        });
         let color_descriptor_set_layout =
-            VulkanGraphicsSetup::create_color_descriptor_set_layout(&core.device);
+            VulkanGraphicsSetup::create_color_descriptor_set_layout(&core.device)?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_descriptor_set_layout(color_descriptor_set_layout, None)
+        });
         let (color_pipeline, color_pipeline_layout) = VulkanGraphicsSetup::create_pipeline(
             &core,
-            COLOR_VERTEX_SHADER_SPV,
-            COLOR_FRAGMENT_SHADER_SPV,
+            &core.shaders.get("color.vert"),
+            &core.shaders.get("color.frag"),
             Vertex::get_binding_descriptions(),
             Vertex::get_attribute_descriptions(),
             color_mesh::InstanceData::get_binding_descriptions(),
@@ -106,13 +324,42 @@ impl VulkanGraphicsSetup {
             swapchain_composite.extent,
             color_descriptor_set_layout,
             msaa_samples,
-        );
+            "color pipeline",
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_pipeline(color_pipeline, None);
+            core.device
+                .destroy_pipeline_layout(color_pipeline_layout, None);
+        });
+        let (pbr_pipeline, pbr_pipeline_layout) = VulkanGraphicsSetup::create_pipeline(
+            &core,
+            &core.shaders.get("pbr.vert"),
+            &core.shaders.get("pbr.frag"),
+            Vertex::get_binding_descriptions(),
+            Vertex::get_attribute_descriptions(),
+            PbrInstanceData::get_binding_descriptions(),
+            PbrInstanceData::get_attribute_descriptions(),
+            render_pass,
+            swapchain_composite.extent,
+            color_descriptor_set_layout,
+            msaa_samples,
+            "pbr pipeline",
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_pipeline(pbr_pipeline, None);
+            core.device
+                .destroy_pipeline_layout(pbr_pipeline_layout, None);
+        });
         let textured_descriptor_set_layout =
-            VulkanGraphicsSetup::create_textured_descriptor_set_layout(&core.device);
+            VulkanGraphicsSetup::create_textured_descriptor_set_layout(&core.device)?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_descriptor_set_layout(textured_descriptor_set_layout, None)
+        });
         let (textured_pipeline, textured_pipeline_layout) = VulkanGraphicsSetup::create_pipeline(
             &core,
-            TEXTURED_VERTEX_SHADER_SPV,
-            TEXTURED_FRAGMENT_SHADER_SPV,
+            &core.shaders.get("textured.vert"),
+            &core.shaders.get("textured.frag"),
             TexturedVertex::get_binding_descriptions(),
             TexturedVertex::get_attribute_descriptions(),
             textured_mesh::InstanceData::get_binding_descriptions(),
@@ -121,50 +368,219 @@ impl VulkanGraphicsSetup {
             swapchain_composite.extent,
             textured_descriptor_set_layout,
             msaa_samples,
-        );
+            "textured pipeline",
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_pipeline(textured_pipeline, None);
+            core.device
+                .destroy_pipeline_layout(textured_pipeline_layout, None);
+        });
+        let (bulb_pipeline, bulb_pipeline_layout) = VulkanGraphicsSetup::create_pipeline(
+            &core,
+            &core.shaders.get("bulb.vert"),
+            &core.shaders.get("bulb.frag"),
+            Vertex::get_binding_descriptions(),
+            Vertex::get_attribute_descriptions(),
+            BulbInstanceData::get_binding_descriptions(),
+            BulbInstanceData::get_attribute_descriptions(),
+            render_pass,
+            swapchain_composite.extent,
+            color_descriptor_set_layout,
+            msaa_samples,
+            "bulb pipeline",
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_pipeline(bulb_pipeline, None);
+            core.device
+                .destroy_pipeline_layout(bulb_pipeline_layout, None);
+        });
+        let skybox_descriptor_set_layout =
+            VulkanGraphicsSetup::create_skybox_descriptor_set_layout(&core.device)?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_descriptor_set_layout(skybox_descriptor_set_layout, None)
+        });
+        let (skybox_pipeline, skybox_pipeline_layout) =
+            VulkanGraphicsSetup::create_skybox_pipeline(
+                &core,
+                render_pass,
+                swapchain_composite.extent,
+                skybox_descriptor_set_layout,
+                msaa_samples,
+            )?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_pipeline(skybox_pipeline, None);
+            core.device
+                .destroy_pipeline_layout(skybox_pipeline_layout, None);
+        });
+        let shadow_map_format = VulkanGraphicsSetup::find_shadow_map_format(&core)?;
+        let shadow_render_pass =
+            VulkanGraphicsSetup::create_shadow_render_pass(&core, shadow_map_format)?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_render_pass(shadow_render_pass, None);
+        });
+        let shadow_map_composite =
+            VulkanGraphicsSetup::create_shadow_map(&core, shadow_render_pass, shadow_map_format)?;
+        let shadow_map = shadow_map_composite.clone();
+        cleanup.push(move |core| shadow_map.drop(core));
+        let shadow_descriptor_set_layout =
+            VulkanGraphicsSetup::create_shadow_descriptor_set_layout(&core.device)?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_descriptor_set_layout(shadow_descriptor_set_layout, None)
+        });
+        let (shadow_pipeline, shadow_pipeline_layout) =
+            VulkanGraphicsSetup::create_shadow_pipeline(
+                &core,
+                shadow_render_pass,
+                shadow_map_composite.extent,
+                shadow_descriptor_set_layout,
+            )?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_pipeline(shadow_pipeline, None);
+            core.device
+                .destroy_pipeline_layout(shadow_pipeline_layout, None);
+        });
         let (color_image, color_image_view, color_image_memory) =
             VulkanGraphicsSetup::create_color_resources(
                 &core,
                 swapchain_composite.extent,
                 msaa_samples,
-            );
+            )?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_image_view(color_image_view, None);
+            core.device.destroy_image(color_image, None);
+            core.free_memory(color_image_memory);
+        });
         let (depth_image, depth_image_view, depth_image_memory) =
             VulkanGraphicsSetup::create_depth_resources(
                 &core,
                 swapchain_composite.extent,
                 msaa_samples,
-            );
-        swapchain_composite.framebuffers = VulkanGraphicsSetup::create_framebuffers(
+            )?;
+        cleanup.push(move |core| unsafe {
+            core.device.destroy_image_view(depth_image_view, None);
+            core.device.destroy_image(depth_image, None);
+            core.free_memory(depth_image_memory);
+        });
+        let framebuffers = VulkanGraphicsSetup::create_framebuffers(
             &core.device,
-            render_pass,
-            &swapchain_composite.image_views,
-            color_image_view,
-            depth_image_view,
-            &swapchain_composite.extent,
-        );
-        let command_pool = core.create_command_pool(core.queue_family.graphics_family.unwrap());
+            tonemap_render_pass,
+            &image_views,
+            swapchain_composite.extent,
+        )?;
+        for &framebuffer in framebuffers.iter() {
+            cleanup.push(move |core| unsafe { core.device.destroy_framebuffer(framebuffer, None) });
+        }
+        let command_pool = core.create_command_pool(
+            core.queue_family.graphics_family.unwrap(),
+            vk::CommandPoolCreateFlags::empty(),
+        )?;
+        cleanup.push(move |core| unsafe { core.device.destroy_command_pool(command_pool, None) });
         let color_descriptor_pool = VulkanGraphicsSetup::create_color_descriptor_pool(
             &core.device,
             swapchain_composite.images.len(),
-        );
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_descriptor_pool(color_descriptor_pool, None)
+        });
         let textured_descriptor_pool = VulkanGraphicsSetup::create_textured_descriptor_pool(
             &core.device,
             swapchain_composite.images.len(),
-        );
-
-        VulkanGraphicsSetup {
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_descriptor_pool(textured_descriptor_pool, None)
+        });
+        let shadow_descriptor_pool = VulkanGraphicsSetup::create_shadow_descriptor_pool(
+            &core.device,
+            swapchain_composite.images.len(),
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_descriptor_pool(shadow_descriptor_pool, None)
+        });
+        let skybox_descriptor_pool = VulkanGraphicsSetup::create_skybox_descriptor_pool(
+            &core.device,
+            swapchain_composite.images.len(),
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_descriptor_pool(skybox_descriptor_pool, None)
+        });
+        let post_descriptor_set_layout =
+            VulkanGraphicsSetup::create_post_descriptor_set_layout(&core.device)?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_descriptor_set_layout(post_descriptor_set_layout, None)
+        });
+        let post_pipeline_layout = VulkanGraphicsSetup::create_post_pipeline_layout(
+            &core.device,
+            post_descriptor_set_layout,
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_pipeline_layout(post_pipeline_layout, None)
+        });
+        let post_descriptor_pool = VulkanGraphicsSetup::create_post_descriptor_pool(&core.device)?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_descriptor_pool(post_descriptor_pool, None)
+        });
+        let post_processing_composite = VulkanGraphicsSetup::create_post_processing(
+            &core,
+            swapchain_composite.extent,
+            PostProcessingTargets {
+                render_pass,
+                bloom_render_pass,
+                tonemap_render_pass,
+                tonemap_format: swapchain_composite.format,
+                color_image_view,
+                depth_image_view,
+                msaa_samples,
+            },
+            post_descriptor_pool,
+            post_descriptor_set_layout,
+            post_pipeline_layout,
+        )?;
+        cleanup.disarm();
+        swapchain_composite.image_views = image_views;
+        swapchain_composite.framebuffers = framebuffers;
+
+        Ok(VulkanGraphicsSetup {
             core,
 
             surface_composite,
             swapchain_composite,
+            present_mode: PresentMode::default(),
 
             render_pass,
             color_descriptor_set_layout,
             color_pipeline_layout,
             color_pipeline,
+            pbr_pipeline_layout,
+            pbr_pipeline,
             textured_descriptor_set_layout,
             textured_pipeline_layout,
             textured_pipeline,
+            bulb_pipeline_layout,
+            bulb_pipeline,
+            skybox_descriptor_set_layout,
+            skybox_pipeline_layout,
+            skybox_pipeline,
+
+            bloom_render_pass,
+            tonemap_render_pass,
+            post_descriptor_set_layout,
+            post_pipeline_layout,
+            post_processing_composite,
+
+            shadow_render_pass,
+            shadow_map_composite,
+            shadow_descriptor_set_layout,
+            shadow_pipeline_layout,
+            shadow_pipeline,
 
             msaa_samples,
 
@@ -179,10 +595,13 @@ impl VulkanGraphicsSetup {
             command_pool,
             color_descriptor_pool,
             textured_descriptor_pool,
+            shadow_descriptor_pool,
+            skybox_descriptor_pool,
+            post_descriptor_pool,
 
             window_width,
             window_height,
-        }
+        })
     }
 
     fn create_swapchain(
@@ -190,14 +609,16 @@ impl VulkanGraphicsSetup {
         surface_composite: &SurfaceComposite,
         window_width: u32,
         window_height: u32,
-    ) -> SwapChainComposite {
+        present_mode: PresentMode,
+    ) -> VulkanResult<SwapChainComposite> {
         let swapchain_support =
-            VulkanGraphicsSetup::find_swapchain_support(core.physical_device, surface_composite);
+            VulkanGraphicsSetup::find_swapchain_support(core.physical_device, surface_composite)?;
 
         let surface_format =
             VulkanGraphicsSetup::choose_swapchain_format(&swapchain_support.formats);
-        let present_mode =
-            VulkanGraphicsSetup::choose_swapchain_present_mode(&swapchain_support.present_modes);
+        let present_mode = present_mode
+            .or_available(&swapchain_support.present_modes)
+            .to_vk();
         let extent = VulkanGraphicsSetup::choose_swapchain_extent(
             &swapchain_support.capabilities,
             window_width,
@@ -211,6 +632,17 @@ impl VulkanGraphicsSetup {
             image_count
         };
 
+        // needed for screenshots
+        let supports_capture = swapchain_support
+            .capabilities
+            .supported_usage_flags
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC);
+        let image_usage = if supports_capture {
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC
+        } else {
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+        };
+
         let (image_sharing_mode, queue_family_indices) =
             if core.queue_family.graphics_family != core.queue_family.present_family {
                 (
@@ -230,7 +662,7 @@ impl VulkanGraphicsSetup {
             image_color_space: surface_format.color_space,
             image_format: surface_format.format,
             image_extent: extent,
-            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            image_usage,
             image_sharing_mode,
             queue_family_index_count: queue_family_indices.len() as u32,
             p_queue_family_indices: queue_family_indices.as_ptr(),
@@ -246,30 +678,65 @@ impl VulkanGraphicsSetup {
         let swapchain = unsafe {
             loader
                 .create_swapchain(&swapchain_create_info, None)
-                .expect("Failed to create Swapchain!")
+                .context("Failed to create Swapchain")?
         };
 
         let images = unsafe {
             loader
                 .get_swapchain_images(swapchain)
-                .expect("Failed to get Swapchain Images.")
+                .inspect_err(|_| loader.destroy_swapchain(swapchain, None))
+                .context("Failed to get Swapchain Images")?
         };
+        for (i, &image) in images.iter().enumerate() {
+            core.debug_utils
+                .name(image, &format!("swapchain image {}", i));
+        }
 
-        SwapChainComposite {
-            loader,
+        Ok(SwapChainComposite {
+            loader: Some(loader),
             swapchain,
             format: surface_format.format,
             extent,
             images,
+            images_memory: vec![],
             image_views: vec![],
             framebuffers: vec![],
-        }
+            supports_capture,
+        })
+    }
+
+    fn create_offscreen_targets(
+        core: &VulkanCore,
+        width: u32,
+        height: u32,
+    ) -> VulkanResult<SwapChainComposite> {
+        let (image, image_memory) = core.create_image(
+            &ImageDescription {
+                extent: vk::Extent2D { width, height },
+                format: COLOR_FORMAT,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                ..Default::default()
+            },
+            "offscreen image",
+        )?;
+
+        Ok(SwapChainComposite {
+            loader: None,
+            swapchain: vk::SwapchainKHR::null(),
+            format: COLOR_FORMAT,
+            extent: vk::Extent2D { width, height },
+            images: vec![image],
+            images_memory: vec![image_memory],
+            image_views: vec![],
+            framebuffers: vec![],
+            supports_capture: true,
+        })
     }
 
     pub fn find_swapchain_support(
         physical_device: vk::PhysicalDevice,
         surface_composite: &SurfaceComposite,
-    ) -> SwapChainSupportDetails {
+    ) -> VulkanResult<SwapChainSupportDetails> {
         unsafe {
             // TODO - validation layers are complaining on the notebook
             let capabilities = surface_composite
@@ -278,30 +745,28 @@ impl VulkanGraphicsSetup {
                     physical_device,
                     surface_composite.surface,
                 )
-                .expect("Failed to query for surface capabilities.");
+                .context("Failed to query for surface capabilities")?;
             let formats = surface_composite
                 .loader
                 .get_physical_device_surface_formats(physical_device, surface_composite.surface)
-                .expect("Failed to query for surface formats.");
+                .context("Failed to query for surface formats")?;
             let present_modes = surface_composite
                 .loader
                 .get_physical_device_surface_present_modes(
                     physical_device,
                     surface_composite.surface,
                 )
-                .expect("Failed to query for surface present mode.");
+                .context("Failed to query for surface present mode")?;
 
-            SwapChainSupportDetails {
+            Ok(SwapChainSupportDetails {
                 capabilities,
                 formats,
                 present_modes,
-            }
+            })
         }
     }
 
-    fn choose_swapchain_format(
-        available_formats: &Vec<vk::SurfaceFormatKHR>,
-    ) -> vk::SurfaceFormatKHR {
+    fn choose_swapchain_format(available_formats: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
         // check if list contains most widely used R8G8B8A8 format with nonlinear color space
         let selected_format = available_formats.iter().find(|format| {
             format.format == COLOR_FORMAT && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
@@ -309,23 +774,8 @@ impl VulkanGraphicsSetup {
 
         // return the first format from the list
         match selected_format {
-            Some(f) => f.clone(),
-            None => available_formats.first().unwrap().clone(),
-        }
-    }
-
-    fn choose_swapchain_present_mode(
-        available_present_modes: &Vec<vk::PresentModeKHR>,
-    ) -> vk::PresentModeKHR {
-        // prefer MAILBOX
-        let selected_present_mode = available_present_modes
-            .iter()
-            .find(|present_mode| **present_mode == vk::PresentModeKHR::MAILBOX);
-
-        // if not, use FIFO
-        match selected_present_mode {
-            Some(m) => *m,
-            None => vk::PresentModeKHR::FIFO,
+            Some(f) => *f,
+            None => available_formats[0],
         }
     }
 
@@ -347,7 +797,7 @@ impl VulkanGraphicsSetup {
     fn create_image_views(
         device: &ash::Device,
         swapchain_composite: &SwapChainComposite,
-    ) -> Vec<vk::ImageView> {
+    ) -> VulkanResult<Vec<vk::ImageView>> {
         let mut swapchain_imageviews = vec![];
 
         for &image in swapchain_composite.images.iter() {
@@ -374,34 +824,42 @@ impl VulkanGraphicsSetup {
             let imageview = unsafe {
                 device
                     .create_image_view(&imageview_create_info, None)
-                    .expect("Failed to create Image View!")
+                    .context("Failed to create Image View")?
             };
             swapchain_imageviews.push(imageview);
         }
 
-        swapchain_imageviews
+        Ok(swapchain_imageviews)
     }
 
     fn create_render_pass(
         core: &VulkanCore,
-        surface_format: vk::Format,
         msaa_samples: vk::SampleCountFlags,
-    ) -> vk::RenderPass {
+    ) -> VulkanResult<vk::RenderPass> {
+        if core.dynamic_rendering.is_some() {
+            // attachments get described when rendering begins instead, see scene_pass
+            return Ok(vk::RenderPass::null());
+        }
+        let multisampled = msaa_samples != vk::SampleCountFlags::TYPE_1;
         let color_attachment = vk::AttachmentDescription {
             flags: vk::AttachmentDescriptionFlags::empty(),
-            format: surface_format,
+            format: HDR_FORMAT,
             samples: msaa_samples,
             load_op: vk::AttachmentLoadOp::CLEAR,
             store_op: vk::AttachmentStoreOp::STORE,
             stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
             stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
             initial_layout: vk::ImageLayout::UNDEFINED,
-            final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            final_layout: if multisampled {
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            } else {
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL // for post-processing
+            },
         };
 
         let depth_attachment = vk::AttachmentDescription {
             flags: vk::AttachmentDescriptionFlags::empty(),
-            format: VulkanGraphicsSetup::find_depth_format(&core.instance, core.physical_device),
+            format: VulkanGraphicsSetup::find_depth_format(&core.instance, core.physical_device)?,
             samples: msaa_samples,
             load_op: vk::AttachmentLoadOp::CLEAR,
             store_op: vk::AttachmentStoreOp::DONT_CARE,
@@ -413,14 +871,14 @@ impl VulkanGraphicsSetup {
 
         let color_attachment_resolve = vk::AttachmentDescription {
             flags: vk::AttachmentDescriptionFlags::empty(),
-            format: surface_format, // swapchain_format
+            format: HDR_FORMAT,
             samples: vk::SampleCountFlags::TYPE_1,
             load_op: vk::AttachmentLoadOp::DONT_CARE,
             store_op: vk::AttachmentStoreOp::STORE,
             stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
             stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
             initial_layout: vk::ImageLayout::UNDEFINED,
-            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, // for post-processing
         };
 
         let color_attachment_ref = vk::AttachmentReference {
@@ -446,23 +904,43 @@ impl VulkanGraphicsSetup {
             p_color_attachments: &color_attachment_ref,
             p_depth_stencil_attachment: &depth_attachment_ref,
             preserve_attachment_count: 0,
-            p_resolve_attachments: &color_attachment_resolve_ref,
+            p_resolve_attachments: if multisampled {
+                &color_attachment_resolve_ref
+            } else {
+                ptr::null()
+            },
             ..Default::default()
         }];
 
-        let render_pass_attachments =
-            [color_attachment, depth_attachment, color_attachment_resolve];
-
-        let subpass_dependencies = [vk::SubpassDependency {
-            src_subpass: vk::SUBPASS_EXTERNAL,
-            dst_subpass: 0,
-            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            src_access_mask: vk::AccessFlags::empty(),
-            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
-                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-            dependency_flags: vk::DependencyFlags::empty(),
-        }];
+        let render_pass_attachments = if multisampled {
+            vec![color_attachment, depth_attachment, color_attachment_resolve]
+        } else {
+            vec![color_attachment, depth_attachment]
+        };
+
+        let subpass_dependencies = [
+            // previous frame's post-processing might still be reading the resolved image
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::empty(),
+                dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dependency_flags: vk::DependencyFlags::empty(),
+            },
+            vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                dependency_flags: vk::DependencyFlags::empty(),
+            },
+        ];
 
         let renderpass_create_info = vk::RenderPassCreateInfo {
             flags: vk::RenderPassCreateFlags::empty(),
@@ -478,11 +956,13 @@ impl VulkanGraphicsSetup {
         unsafe {
             core.device
                 .create_render_pass(&renderpass_create_info, None)
-                .expect("Failed to create render pass!")
+                .context("Failed to create render pass")
         }
     }
 
-    fn create_color_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
+    fn create_color_descriptor_set_layout(
+        device: &ash::Device,
+    ) -> VulkanResult<vk::DescriptorSetLayout> {
         let descriptor_set_layout_bindings = [
             vk::DescriptorSetLayoutBinding {
                 binding: CAMERA_UBO_INDEX as u32,
@@ -492,12 +972,26 @@ impl VulkanGraphicsSetup {
                 ..Default::default()
             },
             vk::DescriptorSetLayoutBinding {
-                binding: LIGHTS_UBO_INDEX as u32,
-                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                binding: LIGHTS_SSBO_INDEX as u32,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
                 descriptor_count: 1,
                 stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
                 ..Default::default()
             },
+            vk::DescriptorSetLayoutBinding {
+                binding: SHADOW_MAP_INDEX as u32,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: FOG_UBO_INDEX as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
         ];
 
         let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
@@ -509,11 +1003,13 @@ impl VulkanGraphicsSetup {
         unsafe {
             device
                 .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
-                .expect("Failed to create Descriptor Set Layout!")
+                .context("Failed to create Descriptor Set Layout")
         }
     }
 
-    fn create_textured_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
+    fn create_textured_descriptor_set_layout(
+        device: &ash::Device,
+    ) -> VulkanResult<vk::DescriptorSetLayout> {
         let descriptor_set_layout_bindings = [
             vk::DescriptorSetLayoutBinding {
                 binding: CAMERA_UBO_INDEX as u32,
@@ -523,8 +1019,8 @@ impl VulkanGraphicsSetup {
                 ..Default::default()
             },
             vk::DescriptorSetLayoutBinding {
-                binding: LIGHTS_UBO_INDEX as u32,
-                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                binding: LIGHTS_SSBO_INDEX as u32,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
                 descriptor_count: 1,
                 stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
                 ..Default::default()
@@ -536,6 +1032,27 @@ impl VulkanGraphicsSetup {
                 stage_flags: vk::ShaderStageFlags::FRAGMENT,
                 ..Default::default()
             },
+            vk::DescriptorSetLayoutBinding {
+                binding: SHADOW_MAP_INDEX as u32,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: NORMAL_MAP_INDEX as u32,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: FOG_UBO_INDEX as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
         ];
 
         let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
@@ -547,51 +1064,109 @@ impl VulkanGraphicsSetup {
         unsafe {
             device
                 .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
-                .expect("Failed to create Descriptor Set Layout!")
+                .context("Failed to create Descriptor Set Layout")
         }
     }
 
-    fn create_pipeline(
-        core: &VulkanCore,
-        vertex_shader_spv: &[u8],
-        fragment_shader_spv: &[u8],
-        vertex_binding_descriptions: Vec<vk::VertexInputBindingDescription>,
-        vertex_attribute_descriptions: Vec<vk::VertexInputAttributeDescription>,
-        instance_binding_descriptions: Vec<vk::VertexInputBindingDescription>,
-        instance_attribute_descriptions: Vec<vk::VertexInputAttributeDescription>,
-        render_pass: vk::RenderPass,
-        swapchain_extent: vk::Extent2D,
-        descriptor_set_layout: vk::DescriptorSetLayout,
-        msaa_samples: vk::SampleCountFlags,
-    ) -> (vk::Pipeline, vk::PipelineLayout) {
-        let device = &core.device;
-        let vert_shader_module = core.create_shader_module(vertex_shader_spv);
-        let frag_shader_module = core.create_shader_module(fragment_shader_spv);
-
-        let main_function_name = CString::new("main").unwrap(); // the beginning function name in shader code.
-
-        let shader_stages = [
-            vk::PipelineShaderStageCreateInfo {
-                module: vert_shader_module,
-                p_name: main_function_name.as_ptr(),
-                stage: vk::ShaderStageFlags::VERTEX,
+    fn create_skybox_descriptor_set_layout(
+        device: &ash::Device,
+    ) -> VulkanResult<vk::DescriptorSetLayout> {
+        let descriptor_set_layout_bindings = [
+            vk::DescriptorSetLayoutBinding {
+                binding: CAMERA_UBO_INDEX as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
                 ..Default::default()
             },
-            vk::PipelineShaderStageCreateInfo {
-                module: frag_shader_module,
-                p_name: main_function_name.as_ptr(),
-                stage: vk::ShaderStageFlags::FRAGMENT,
+            vk::DescriptorSetLayoutBinding {
+                binding: COMBINED_IMAGE_SAMPLER_INDEX as u32,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
                 ..Default::default()
             },
         ];
 
-        let mut binding_descriptions: Vec<vk::VertexInputBindingDescription> = vec![];
-        binding_descriptions.extend(vertex_binding_descriptions);
-        binding_descriptions.extend(instance_binding_descriptions);
-
-        let mut attribute_descriptions: Vec<vk::VertexInputAttributeDescription> = vec![];
-        attribute_descriptions.extend(vertex_attribute_descriptions);
-        attribute_descriptions.extend(instance_attribute_descriptions);
+        let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: descriptor_set_layout_bindings.len() as u32,
+            p_bindings: descriptor_set_layout_bindings.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe {
+            device
+                .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
+                .context("Failed to create Descriptor Set Layout")
+        }
+    }
+
+    fn create_shadow_descriptor_set_layout(
+        device: &ash::Device,
+    ) -> VulkanResult<vk::DescriptorSetLayout> {
+        let descriptor_set_layout_bindings = [vk::DescriptorSetLayoutBinding {
+            binding: LIGHTS_SSBO_INDEX as u32,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            ..Default::default()
+        }];
+
+        let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: descriptor_set_layout_bindings.len() as u32,
+            p_bindings: descriptor_set_layout_bindings.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe {
+            device
+                .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
+                .context("Failed to create Descriptor Set Layout")
+        }
+    }
+
+    fn create_pipeline(
+        core: &VulkanCore,
+        vertex_shader_spv: &[u8],
+        fragment_shader_spv: &[u8],
+        vertex_binding_descriptions: Vec<vk::VertexInputBindingDescription>,
+        vertex_attribute_descriptions: Vec<vk::VertexInputAttributeDescription>,
+        instance_binding_descriptions: Vec<vk::VertexInputBindingDescription>,
+        instance_attribute_descriptions: Vec<vk::VertexInputAttributeDescription>,
+        render_pass: vk::RenderPass,
+        swapchain_extent: vk::Extent2D,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        msaa_samples: vk::SampleCountFlags,
+        name: &str,
+    ) -> VulkanResult<(vk::Pipeline, vk::PipelineLayout)> {
+        let device = &core.device;
+        let vert_shader_module = core.create_shader_module(vertex_shader_spv)?;
+        let frag_shader_module = core.create_shader_module(fragment_shader_spv)?;
+
+        let main_function_name = CString::new("main").unwrap(); // the beginning function name in shader code.
+
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo {
+                module: vert_shader_module,
+                p_name: main_function_name.as_ptr(),
+                stage: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+            vk::PipelineShaderStageCreateInfo {
+                module: frag_shader_module,
+                p_name: main_function_name.as_ptr(),
+                stage: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+
+        let mut binding_descriptions: Vec<vk::VertexInputBindingDescription> = vec![];
+        binding_descriptions.extend(vertex_binding_descriptions);
+        binding_descriptions.extend(instance_binding_descriptions);
+
+        let mut attribute_descriptions: Vec<vk::VertexInputAttributeDescription> = vec![];
+        attribute_descriptions.extend(vertex_attribute_descriptions);
+        attribute_descriptions.extend(instance_attribute_descriptions);
 
         let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo {
             flags: vk::PipelineVertexInputStateCreateFlags::empty(),
@@ -715,10 +1290,19 @@ impl VulkanGraphicsSetup {
         let pipeline_layout = unsafe {
             device
                 .create_pipeline_layout(&pipeline_layout_create_info, None)
-                .expect("Failed to create pipeline layout!")
+                .context("Failed to create pipeline layout")?
         };
 
+        let color_formats = [HDR_FORMAT];
+        let pipeline_rendering_create_info = rendering::pipeline_rendering_create_info(
+            &color_formats,
+            VulkanGraphicsSetup::find_depth_format(&core.instance, core.physical_device)?,
+        );
         let graphic_pipeline_create_infos = [vk::GraphicsPipelineCreateInfo {
+            p_next: rendering::pipeline_create_info_next(
+                render_pass,
+                &pipeline_rendering_create_info,
+            ),
             flags: vk::PipelineCreateFlags::empty(),
             stage_count: shader_stages.len() as u32,
             p_stages: shader_stages.as_ptr(),
@@ -733,116 +1317,1156 @@ impl VulkanGraphicsSetup {
             p_dynamic_state: ptr::null(),
             layout: pipeline_layout,
             render_pass,
-            subpass: 0,
-            base_pipeline_handle: vk::Pipeline::null(),
-            base_pipeline_index: -1,
+            subpass: 0,
+            base_pipeline_handle: vk::Pipeline::null(),
+            base_pipeline_index: -1,
+            ..Default::default()
+        }];
+
+        let graphics_pipelines = unsafe {
+            device
+                .create_graphics_pipelines(
+                    core.pipeline_cache.cache,
+                    &graphic_pipeline_create_infos,
+                    None,
+                )
+                .map_err(|(_, result)| result)
+                .context("Failed to create Graphics Pipeline")?
+        };
+        core.debug_utils.name(graphics_pipelines[0], name);
+
+        unsafe {
+            device.destroy_shader_module(vert_shader_module, None);
+            device.destroy_shader_module(frag_shader_module, None);
+        }
+
+        Ok((graphics_pipelines[0], pipeline_layout))
+    }
+
+    /// Depth-only pass rendering the scene as seen by a single light into one shadow map layer.
+    fn create_shadow_render_pass(
+        core: &VulkanCore,
+        format: vk::Format,
+    ) -> VulkanResult<vk::RenderPass> {
+        if core.dynamic_rendering.is_some() {
+            return Ok(vk::RenderPass::null());
+        }
+        let depth_attachment = vk::AttachmentDescription {
+            flags: vk::AttachmentDescriptionFlags::empty(),
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        };
+
+        let depth_attachment_ref = vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+
+        let subpasses = [vk::SubpassDescription {
+            flags: vk::SubpassDescriptionFlags::empty(),
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: 0,
+            p_depth_stencil_attachment: &depth_attachment_ref,
+            ..Default::default()
+        }];
+
+        let render_pass_attachments = [depth_attachment];
+
+        let subpass_dependencies = [
+            // previous frame might still be sampling the shadow map
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                src_access_mask: vk::AccessFlags::SHADER_READ,
+                dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dependency_flags: vk::DependencyFlags::empty(),
+            },
+            vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                dependency_flags: vk::DependencyFlags::empty(),
+            },
+        ];
+
+        let renderpass_create_info = vk::RenderPassCreateInfo {
+            flags: vk::RenderPassCreateFlags::empty(),
+            attachment_count: render_pass_attachments.len() as u32,
+            p_attachments: render_pass_attachments.as_ptr(),
+            subpass_count: subpasses.len() as u32,
+            p_subpasses: subpasses.as_ptr(),
+            dependency_count: subpass_dependencies.len() as u32,
+            p_dependencies: subpass_dependencies.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe {
+            core.device
+                .create_render_pass(&renderpass_create_info, None)
+                .context("Failed to create shadow render pass")
+        }
+    }
+
+    fn create_shadow_map(
+        core: &VulkanCore,
+        render_pass: vk::RenderPass,
+        format: vk::Format,
+    ) -> VulkanResult<ShadowMapComposite> {
+        let extent = vk::Extent2D {
+            width: SHADOW_MAP_SIZE,
+            height: SHADOW_MAP_SIZE,
+        };
+        let (image, image_memory) = core.create_image(
+            &ImageDescription {
+                extent,
+                array_layers: SHADOW_MAP_LAYERS as u32,
+                format,
+                usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                ..Default::default()
+            },
+            "shadow map",
+        )?;
+        let layers = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: SHADOW_MAP_LAYERS as u32,
+        };
+        let image_view = core.create_layered_image_view(
+            image,
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            format,
+            layers,
+        )?;
+        let layer_image_views = (0..SHADOW_MAP_LAYERS as u32)
+            .map(|layer| {
+                core.create_layered_image_view(
+                    image,
+                    vk::ImageViewType::TYPE_2D,
+                    format,
+                    vk::ImageSubresourceRange {
+                        base_array_layer: layer,
+                        layer_count: 1,
+                        ..layers
+                    },
+                )
+            })
+            .collect::<VulkanResult<Vec<vk::ImageView>>>()?;
+        let framebuffers = layer_image_views
+            .iter()
+            .map(|&layer_image_view| {
+                VulkanGraphicsSetup::create_framebuffer(
+                    &core.device,
+                    render_pass,
+                    &[layer_image_view],
+                    extent,
+                )
+            })
+            .collect::<VulkanResult<Vec<vk::Framebuffer>>>()?;
+        let sampler = VulkanGraphicsSetup::create_shadow_sampler(core, format)?;
+
+        Ok(ShadowMapComposite {
+            image,
+            image_memory,
+            image_view,
+            layer_image_views,
+            framebuffers,
+            sampler,
+            extent,
+        })
+    }
+
+    fn create_shadow_sampler(core: &VulkanCore, format: vk::Format) -> VulkanResult<vk::Sampler> {
+        let format_properties = unsafe {
+            core.instance
+                .get_physical_device_format_properties(core.physical_device, format)
+        };
+        // with comparison enabled linear filtering gives a bit of free smoothing, if supported
+        let filter = if format_properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+        {
+            vk::Filter::LINEAR
+        } else {
+            vk::Filter::NEAREST
+        };
+
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: filter,
+            min_filter: filter,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            anisotropy_enable: vk::FALSE,
+            max_anisotropy: 1.,
+            // everything outside of the light's view is lit
+            border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
+            unnormalized_coordinates: vk::FALSE,
+            compare_enable: vk::TRUE,
+            compare_op: vk::CompareOp::LESS_OR_EQUAL,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            mip_lod_bias: 0.,
+            min_lod: 0.,
+            max_lod: 0.,
+            ..Default::default()
+        };
+
+        unsafe {
+            core.device
+                .create_sampler(&sampler_info, None)
+                .context("Failed to create shadow map sampler")
+        }
+    }
+
+    fn create_shadow_pipeline(
+        core: &VulkanCore,
+        render_pass: vk::RenderPass,
+        shadow_map_extent: vk::Extent2D,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> VulkanResult<(vk::Pipeline, vk::PipelineLayout)> {
+        let device = &core.device;
+        let vert_shader_module = core.create_shader_module(&core.shaders.get("shadow.vert"))?;
+
+        let main_function_name = CString::new("main").unwrap();
+
+        // depth only, no fragment shader needed
+        let shader_stages = [vk::PipelineShaderStageCreateInfo {
+            module: vert_shader_module,
+            p_name: main_function_name.as_ptr(),
+            stage: vk::ShaderStageFlags::VERTEX,
+            ..Default::default()
+        }];
+
+        // only PBR meshes cast shadows, the ground just receives them
+        let mut binding_descriptions: Vec<vk::VertexInputBindingDescription> = vec![];
+        binding_descriptions.extend(Vertex::get_binding_descriptions());
+        binding_descriptions.extend(PbrInstanceData::get_binding_descriptions());
+
+        let mut attribute_descriptions: Vec<vk::VertexInputAttributeDescription> = vec![];
+        attribute_descriptions.extend(Vertex::get_attribute_descriptions());
+        attribute_descriptions.extend(PbrInstanceData::get_attribute_descriptions());
+
+        let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo {
+            flags: vk::PipelineVertexInputStateCreateFlags::empty(),
+            vertex_attribute_description_count: attribute_descriptions.len() as u32,
+            p_vertex_attribute_descriptions: attribute_descriptions.as_ptr(),
+            vertex_binding_description_count: binding_descriptions.len() as u32,
+            p_vertex_binding_descriptions: binding_descriptions.as_ptr(),
+            ..Default::default()
+        };
+        let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
+            flags: vk::PipelineInputAssemblyStateCreateFlags::empty(),
+            primitive_restart_enable: vk::FALSE,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            ..Default::default()
+        };
+
+        let viewports = [vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: shadow_map_extent.width as f32,
+            height: shadow_map_extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+
+        let scissors = [vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: shadow_map_extent,
+        }];
+
+        let viewport_state_create_info = vk::PipelineViewportStateCreateInfo {
+            scissor_count: scissors.len() as u32,
+            p_scissors: scissors.as_ptr(),
+            viewport_count: viewports.len() as u32,
+            p_viewports: viewports.as_ptr(),
+            ..Default::default()
+        };
+
+        // no culling, some tree parts are single-sided; depth bias against shadow acne
+        let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo {
+            flags: vk::PipelineRasterizationStateCreateFlags::empty(),
+            depth_clamp_enable: vk::FALSE,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::CLOCKWISE,
+            line_width: 1.0,
+            polygon_mode: vk::PolygonMode::FILL,
+            rasterizer_discard_enable: vk::FALSE,
+            depth_bias_clamp: 0.0,
+            depth_bias_constant_factor: 1.25,
+            depth_bias_enable: vk::TRUE,
+            depth_bias_slope_factor: 1.75,
+            ..Default::default()
+        };
+        let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo {
+            flags: vk::PipelineMultisampleStateCreateFlags::empty(),
+            rasterization_samples: vk::SampleCountFlags::TYPE_1,
+            sample_shading_enable: vk::FALSE,
+            min_sample_shading: 0.0,
+            alpha_to_one_enable: vk::FALSE,
+            alpha_to_coverage_enable: vk::FALSE,
+            ..Default::default()
+        };
+
+        let stencil_state = vk::StencilOpState {
+            fail_op: vk::StencilOp::KEEP,
+            pass_op: vk::StencilOp::KEEP,
+            depth_fail_op: vk::StencilOp::KEEP,
+            compare_op: vk::CompareOp::ALWAYS,
+            compare_mask: 0,
+            write_mask: 0,
+            reference: 0,
+        };
+
+        let depth_state_create_info = vk::PipelineDepthStencilStateCreateInfo {
+            flags: vk::PipelineDepthStencilStateCreateFlags::empty(),
+            depth_test_enable: vk::TRUE,
+            depth_write_enable: vk::TRUE,
+            depth_compare_op: vk::CompareOp::LESS,
+            depth_bounds_test_enable: vk::FALSE,
+            stencil_test_enable: vk::FALSE,
+            front: stencil_state,
+            back: stencil_state,
+            max_depth_bounds: 1.0,
+            min_depth_bounds: 0.0,
+            ..Default::default()
+        };
+
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
+            flags: vk::PipelineColorBlendStateCreateFlags::empty(),
+            logic_op_enable: vk::FALSE,
+            logic_op: vk::LogicOp::COPY,
+            attachment_count: 0,
+            blend_constants: [0.0, 0.0, 0.0, 0.0],
+            ..Default::default()
+        };
+
+        let set_layouts = [descriptor_set_layout];
+
+        let light_index = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            size: 4,
+            offset: 0,
+        };
+        let push_constant_ranges = [light_index];
+
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+            ..Default::default()
+        };
+
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_create_info, None)
+                .context("Failed to create pipeline layout")?
+        };
+
+        let pipeline_rendering_create_info = rendering::pipeline_rendering_create_info(
+            &[],
+            VulkanGraphicsSetup::find_shadow_map_format(core)?,
+        );
+        let graphic_pipeline_create_infos = [vk::GraphicsPipelineCreateInfo {
+            p_next: rendering::pipeline_create_info_next(
+                render_pass,
+                &pipeline_rendering_create_info,
+            ),
+            flags: vk::PipelineCreateFlags::empty(),
+            stage_count: shader_stages.len() as u32,
+            p_stages: shader_stages.as_ptr(),
+            p_vertex_input_state: &vertex_input_state_create_info,
+            p_input_assembly_state: &vertex_input_assembly_state_info,
+            p_tessellation_state: ptr::null(),
+            p_viewport_state: &viewport_state_create_info,
+            p_rasterization_state: &rasterization_state_create_info,
+            p_multisample_state: &multisample_state_create_info,
+            p_depth_stencil_state: &depth_state_create_info,
+            p_color_blend_state: &color_blend_state,
+            p_dynamic_state: ptr::null(),
+            layout: pipeline_layout,
+            render_pass,
+            subpass: 0,
+            base_pipeline_handle: vk::Pipeline::null(),
+            base_pipeline_index: -1,
+            ..Default::default()
+        }];
+
+        let graphics_pipelines = unsafe {
+            device
+                .create_graphics_pipelines(
+                    core.pipeline_cache.cache,
+                    &graphic_pipeline_create_infos,
+                    None,
+                )
+                .map_err(|(_, result)| result)
+                .context("Failed to create shadow Pipeline")?
+        };
+        core.debug_utils
+            .name(graphics_pipelines[0], "shadow pipeline");
+
+        unsafe {
+            device.destroy_shader_module(vert_shader_module, None);
+        }
+
+        Ok((graphics_pipelines[0], pipeline_layout))
+    }
+
+    /// Single color attachment pass drawing a full screen triangle, used for all post-processing.
+    fn create_post_render_pass(
+        core: &VulkanCore,
+        format: vk::Format,
+        final_layout: vk::ImageLayout,
+    ) -> VulkanResult<vk::RenderPass> {
+        if core.dynamic_rendering.is_some() {
+            return Ok(vk::RenderPass::null());
+        }
+        let color_attachment = vk::AttachmentDescription {
+            flags: vk::AttachmentDescriptionFlags::empty(),
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE, // every pixel gets overwritten anyway
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout,
+        };
+
+        let color_attachment_ref = vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        };
+
+        let subpasses = [vk::SubpassDescription {
+            flags: vk::SubpassDescriptionFlags::empty(),
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: 1,
+            p_color_attachments: &color_attachment_ref,
+            ..Default::default()
+        }];
+
+        let render_pass_attachments = [color_attachment];
+
+        let subpass_dependencies = [
+            // the image might still be read by the previous pass or frame
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::empty(),
+                dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dependency_flags: vk::DependencyFlags::empty(),
+            },
+            // next pass samples it, frame capture copies it
+            vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::TRANSFER,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_access_mask: vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ,
+                dependency_flags: vk::DependencyFlags::empty(),
+            },
+        ];
+
+        let renderpass_create_info = vk::RenderPassCreateInfo {
+            flags: vk::RenderPassCreateFlags::empty(),
+            attachment_count: render_pass_attachments.len() as u32,
+            p_attachments: render_pass_attachments.as_ptr(),
+            subpass_count: subpasses.len() as u32,
+            p_subpasses: subpasses.as_ptr(),
+            dependency_count: subpass_dependencies.len() as u32,
+            p_dependencies: subpass_dependencies.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe {
+            core.device
+                .create_render_pass(&renderpass_create_info, None)
+                .context("Failed to create post-processing render pass")
+        }
+    }
+
+    fn create_post_descriptor_set_layout(
+        device: &ash::Device,
+    ) -> VulkanResult<vk::DescriptorSetLayout> {
+        let descriptor_set_layout_bindings = [
+            vk::DescriptorSetLayoutBinding {
+                binding: 0, // image being processed
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: 1, // bloom, used by tone mapping only
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+
+        let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: descriptor_set_layout_bindings.len() as u32,
+            p_bindings: descriptor_set_layout_bindings.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe {
+            device
+                .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
+                .context("Failed to create Descriptor Set Layout")
+        }
+    }
+
+    fn create_post_pipeline_layout(
+        device: &ash::Device,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> VulkanResult<vk::PipelineLayout> {
+        let set_layouts = [descriptor_set_layout];
+        // blur direction
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<[f32; 2]>() as u32,
+        }];
+
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_create_info, None)
+                .context("Failed to create pipeline layout")
+        }
+    }
+
+    /// Full screen triangle, no vertex buffers, the fragment shader does all the work.
+    fn create_post_pipeline(
+        core: &VulkanCore,
+        fragment_shader_spv: &[u8],
+        render_pass: vk::RenderPass,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        pipeline_layout: vk::PipelineLayout,
+        name: &str,
+    ) -> VulkanResult<vk::Pipeline> {
+        let device = &core.device;
+        let vert_shader_module = core.create_shader_module(&core.shaders.get("fullscreen.vert"))?;
+        let frag_shader_module = core.create_shader_module(fragment_shader_spv)?;
+
+        let main_function_name = CString::new("main").unwrap();
+
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo {
+                module: vert_shader_module,
+                p_name: main_function_name.as_ptr(),
+                stage: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+            vk::PipelineShaderStageCreateInfo {
+                module: frag_shader_module,
+                p_name: main_function_name.as_ptr(),
+                stage: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+
+        let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::default();
+        let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            ..Default::default()
+        };
+
+        let viewports = [vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+
+        let scissors = [vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        }];
+
+        let viewport_state_create_info = vk::PipelineViewportStateCreateInfo {
+            scissor_count: scissors.len() as u32,
+            p_scissors: scissors.as_ptr(),
+            viewport_count: viewports.len() as u32,
+            p_viewports: viewports.as_ptr(),
+            ..Default::default()
+        };
+
+        let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo {
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::CLOCKWISE,
+            line_width: 1.0,
+            polygon_mode: vk::PolygonMode::FILL,
+            ..Default::default()
+        };
+        let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo {
+            rasterization_samples: vk::SampleCountFlags::TYPE_1,
+            ..Default::default()
+        };
+
+        let depth_state_create_info = vk::PipelineDepthStencilStateCreateInfo {
+            depth_test_enable: vk::FALSE,
+            depth_write_enable: vk::FALSE,
+            ..Default::default()
+        };
+
+        let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: vk::FALSE,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            ..Default::default()
+        }];
+
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
+            attachment_count: color_blend_attachment_states.len() as u32,
+            p_attachments: color_blend_attachment_states.as_ptr(),
+            ..Default::default()
+        };
+
+        let color_formats = [format];
+        let pipeline_rendering_create_info =
+            rendering::pipeline_rendering_create_info(&color_formats, vk::Format::UNDEFINED);
+        let graphic_pipeline_create_infos = [vk::GraphicsPipelineCreateInfo {
+            p_next: rendering::pipeline_create_info_next(
+                render_pass,
+                &pipeline_rendering_create_info,
+            ),
+            flags: vk::PipelineCreateFlags::empty(),
+            stage_count: shader_stages.len() as u32,
+            p_stages: shader_stages.as_ptr(),
+            p_vertex_input_state: &vertex_input_state_create_info,
+            p_input_assembly_state: &vertex_input_assembly_state_info,
+            p_tessellation_state: ptr::null(),
+            p_viewport_state: &viewport_state_create_info,
+            p_rasterization_state: &rasterization_state_create_info,
+            p_multisample_state: &multisample_state_create_info,
+            p_depth_stencil_state: &depth_state_create_info,
+            p_color_blend_state: &color_blend_state,
+            p_dynamic_state: ptr::null(),
+            layout: pipeline_layout,
+            render_pass,
+            subpass: 0,
+            base_pipeline_handle: vk::Pipeline::null(),
+            base_pipeline_index: -1,
+            ..Default::default()
+        }];
+
+        let graphics_pipelines = unsafe {
+            device
+                .create_graphics_pipelines(
+                    core.pipeline_cache.cache,
+                    &graphic_pipeline_create_infos,
+                    None,
+                )
+                .map_err(|(_, result)| result)
+                .context("Failed to create post-processing Pipeline")?
+        };
+        core.debug_utils.name(graphics_pipelines[0], name);
+
+        unsafe {
+            device.destroy_shader_module(vert_shader_module, None);
+            device.destroy_shader_module(frag_shader_module, None);
+        }
+
+        Ok(graphics_pipelines[0])
+    }
+
+    /// Sky's cube has no vertex buffers, see skybox.vert. It ends up at the far plane, so only
+    /// the pixels nothing else was drawn at get the sky.
+    fn create_skybox_pipeline(
+        core: &VulkanCore,
+        render_pass: vk::RenderPass,
+        extent: vk::Extent2D,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        msaa_samples: vk::SampleCountFlags,
+    ) -> VulkanResult<(vk::Pipeline, vk::PipelineLayout)> {
+        let device = &core.device;
+        let vert_shader_module = core.create_shader_module(&core.shaders.get("skybox.vert"))?;
+        let frag_shader_module = core.create_shader_module(&core.shaders.get("skybox.frag"))?;
+
+        let main_function_name = CString::new("main").unwrap();
+
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo {
+                module: vert_shader_module,
+                p_name: main_function_name.as_ptr(),
+                stage: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+            vk::PipelineShaderStageCreateInfo {
+                module: frag_shader_module,
+                p_name: main_function_name.as_ptr(),
+                stage: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+
+        let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::default();
+        let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            ..Default::default()
+        };
+
+        let viewports = [vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+
+        let scissors = [vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        }];
+
+        let viewport_state_create_info = vk::PipelineViewportStateCreateInfo {
+            scissor_count: scissors.len() as u32,
+            p_scissors: scissors.as_ptr(),
+            viewport_count: viewports.len() as u32,
+            p_viewports: viewports.as_ptr(),
+            ..Default::default()
+        };
+
+        // looking at the cube from the inside
+        let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo {
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::CLOCKWISE,
+            line_width: 1.0,
+            polygon_mode: vk::PolygonMode::FILL,
+            ..Default::default()
+        };
+        let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo {
+            rasterization_samples: msaa_samples,
+            ..Default::default()
+        };
+
+        // depth buffer is cleared to the far plane, which is where the sky is
+        let depth_state_create_info = vk::PipelineDepthStencilStateCreateInfo {
+            depth_test_enable: vk::TRUE,
+            depth_write_enable: vk::FALSE,
+            depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+            max_depth_bounds: 1.0,
+            min_depth_bounds: 0.0,
+            ..Default::default()
+        };
+
+        let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: vk::FALSE,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            ..Default::default()
+        }];
+
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
+            attachment_count: color_blend_attachment_states.len() as u32,
+            p_attachments: color_blend_attachment_states.as_ptr(),
+            ..Default::default()
+        };
+
+        let set_layouts = [descriptor_set_layout];
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            ..Default::default()
+        };
+
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_create_info, None)
+                .context("Failed to create skybox pipeline layout")?
+        };
+
+        let color_formats = [HDR_FORMAT];
+        let pipeline_rendering_create_info = rendering::pipeline_rendering_create_info(
+            &color_formats,
+            VulkanGraphicsSetup::find_depth_format(&core.instance, core.physical_device)?,
+        );
+        let graphic_pipeline_create_infos = [vk::GraphicsPipelineCreateInfo {
+            p_next: rendering::pipeline_create_info_next(
+                render_pass,
+                &pipeline_rendering_create_info,
+            ),
+            flags: vk::PipelineCreateFlags::empty(),
+            stage_count: shader_stages.len() as u32,
+            p_stages: shader_stages.as_ptr(),
+            p_vertex_input_state: &vertex_input_state_create_info,
+            p_input_assembly_state: &vertex_input_assembly_state_info,
+            p_tessellation_state: ptr::null(),
+            p_viewport_state: &viewport_state_create_info,
+            p_rasterization_state: &rasterization_state_create_info,
+            p_multisample_state: &multisample_state_create_info,
+            p_depth_stencil_state: &depth_state_create_info,
+            p_color_blend_state: &color_blend_state,
+            p_dynamic_state: ptr::null(),
+            layout: pipeline_layout,
+            render_pass,
+            subpass: 0,
+            base_pipeline_handle: vk::Pipeline::null(),
+            base_pipeline_index: -1,
+            ..Default::default()
+        }];
+
+        let graphics_pipelines = unsafe {
+            device
+                .create_graphics_pipelines(
+                    core.pipeline_cache.cache,
+                    &graphic_pipeline_create_infos,
+                    None,
+                )
+                .map_err(|(_, result)| result)
+                .context("Failed to create skybox Pipeline")?
+        };
+        core.debug_utils
+            .name(graphics_pipelines[0], "skybox pipeline");
+
+        unsafe {
+            device.destroy_shader_module(vert_shader_module, None);
+            device.destroy_shader_module(frag_shader_module, None);
+        }
+
+        Ok((graphics_pipelines[0], pipeline_layout))
+    }
+
+    fn create_post_processing(
+        core: &VulkanCore,
+        extent: vk::Extent2D,
+        targets: PostProcessingTargets,
+        descriptor_pool: vk::DescriptorPool,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> VulkanResult<PostProcessingComposite> {
+        let PostProcessingTargets {
+            render_pass,
+            bloom_render_pass,
+            tonemap_render_pass,
+            tonemap_format,
+            color_image_view,
+            depth_image_view,
+            msaa_samples,
+        } = targets;
+        let device = &core.device;
+        let (hdr_image, hdr_image_memory) = core.create_image(
+            &ImageDescription {
+                extent,
+                format: HDR_FORMAT,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                ..Default::default()
+            },
+            "hdr image",
+        )?;
+        let hdr_image_view =
+            core.create_image_view(hdr_image, HDR_FORMAT, vk::ImageAspectFlags::COLOR, 1)?;
+        let hdr_framebuffer = VulkanGraphicsSetup::create_framebuffer(
+            device,
+            render_pass,
+            &if msaa_samples == vk::SampleCountFlags::TYPE_1 {
+                vec![hdr_image_view, depth_image_view]
+            } else {
+                vec![color_image_view, depth_image_view, hdr_image_view]
+            },
+            extent,
+        )?;
+
+        // blurring a smaller image is cheaper and spreads the glow further
+        let bloom_extent = vk::Extent2D {
+            width: (extent.width / BLOOM_DOWNSCALE).max(1),
+            height: (extent.height / BLOOM_DOWNSCALE).max(1),
+        };
+        let mut bloom_images = vec![];
+        let mut bloom_images_memory = vec![];
+        let mut bloom_image_views = vec![];
+        let mut bloom_framebuffers = vec![];
+        for i in 0..2 {
+            let (image, image_memory) = core.create_image(
+                &ImageDescription {
+                    extent: bloom_extent,
+                    format: HDR_FORMAT,
+                    usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                    ..Default::default()
+                },
+                &format!("bloom image {}", i),
+            )?;
+            let image_view =
+                core.create_image_view(image, HDR_FORMAT, vk::ImageAspectFlags::COLOR, 1)?;
+            bloom_framebuffers.push(VulkanGraphicsSetup::create_framebuffer(
+                device,
+                bloom_render_pass,
+                &[image_view],
+                bloom_extent,
+            )?);
+            bloom_images.push(image);
+            bloom_images_memory.push(image_memory);
+            bloom_image_views.push(image_view);
+        }
+
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
             ..Default::default()
-        }];
+        };
+        let sampler = unsafe {
+            device
+                .create_sampler(&sampler_info, None)
+                .context("Failed to create post-processing sampler")?
+        };
 
-        let graphics_pipelines = unsafe {
+        // bright pass, horizontal blur, vertical blur, tone mapping
+        let layouts = [descriptor_set_layout; 4];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: layouts.len() as u32,
+            p_set_layouts: layouts.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_sets = unsafe {
             device
-                .create_graphics_pipelines(
-                    vk::PipelineCache::null(),
-                    &graphic_pipeline_create_infos,
-                    None,
-                )
-                .expect("Failed to create Graphics Pipeline!.")
+                .allocate_descriptor_sets(&descriptor_set_allocate_info)
+                .context("Failed to allocate descriptor sets")?
         };
+        let inputs = [
+            (hdr_image_view, hdr_image_view),
+            (bloom_image_views[0], bloom_image_views[0]),
+            (bloom_image_views[1], bloom_image_views[1]),
+            (hdr_image_view, bloom_image_views[0]),
+        ];
+        for (&descriptor_set, &(image_view, bloom_image_view)) in
+            descriptor_sets.iter().zip(inputs.iter())
+        {
+            let image_infos = [
+                vk::DescriptorImageInfo {
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    image_view,
+                    sampler,
+                },
+                vk::DescriptorImageInfo {
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    image_view: bloom_image_view,
+                    sampler,
+                },
+            ];
+            let descriptor_write_sets = [vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: 0,
+                dst_array_element: 0,
+                descriptor_count: image_infos.len() as u32,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                p_image_info: image_infos.as_ptr(),
+                ..Default::default()
+            }];
 
-        unsafe {
-            device.destroy_shader_module(vert_shader_module, None);
-            device.destroy_shader_module(frag_shader_module, None);
+            unsafe {
+                device.update_descriptor_sets(&descriptor_write_sets, &[]);
+            }
         }
 
-        (graphics_pipelines[0], pipeline_layout)
+        let bright_pass_pipeline = VulkanGraphicsSetup::create_post_pipeline(
+            core,
+            &core.shaders.get("bright_pass.frag"),
+            bloom_render_pass,
+            HDR_FORMAT,
+            bloom_extent,
+            pipeline_layout,
+            "bright pass pipeline",
+        )?;
+        let blur_pipeline = VulkanGraphicsSetup::create_post_pipeline(
+            core,
+            &core.shaders.get("blur.frag"),
+            bloom_render_pass,
+            HDR_FORMAT,
+            bloom_extent,
+            pipeline_layout,
+            "blur pipeline",
+        )?;
+        let tonemap_pipeline = VulkanGraphicsSetup::create_post_pipeline(
+            core,
+            &core.shaders.get("tonemap.frag"),
+            tonemap_render_pass,
+            tonemap_format,
+            extent,
+            pipeline_layout,
+            "tonemap pipeline",
+        )?;
+
+        Ok(PostProcessingComposite {
+            hdr_image,
+            hdr_image_memory,
+            hdr_image_view,
+            hdr_framebuffer,
+            bloom_images,
+            bloom_images_memory,
+            bloom_image_views,
+            bloom_framebuffers,
+            bloom_extent,
+            sampler,
+            bright_pass_descriptor_set: descriptor_sets[0],
+            blur_descriptor_sets: vec![descriptor_sets[1], descriptor_sets[2]],
+            tonemap_descriptor_set: descriptor_sets[3],
+            bright_pass_pipeline,
+            blur_pipeline,
+            tonemap_pipeline,
+        })
     }
 
-    fn choose_msaa_samples(core: &VulkanCore) -> vk::SampleCountFlags {
+    fn supported_msaa_samples(core: &VulkanCore) -> vk::SampleCountFlags {
         let physical_device_properties = unsafe {
             core.instance
                 .get_physical_device_properties(core.physical_device)
         };
 
-        let count = std::cmp::min(
-            physical_device_properties
-                .limits
-                .framebuffer_color_sample_counts,
-            physical_device_properties
+        physical_device_properties
+            .limits
+            .framebuffer_color_sample_counts
+            & physical_device_properties
                 .limits
-                .framebuffer_depth_sample_counts,
+                .framebuffer_depth_sample_counts
+    }
+
+    pub fn msaa_samples(&self) -> vk::SampleCountFlags {
+        self.msaa_samples
+    }
+
+    /// At most that many samples per pixel, as many as the device supports. Takes effect when
+    /// the swapchain gets recreated.
+    pub fn set_msaa_samples(&mut self, max_samples: u32) {
+        self.msaa_samples = msaa::highest_supported(
+            VulkanGraphicsSetup::supported_msaa_samples(&self.core),
+            max_samples,
         );
+    }
 
-        // if count.contains(vk::SampleCountFlags::TYPE_64) {
-        //     return vk::SampleCountFlags::TYPE_64;
-        // }
-        // if count.contains(vk::SampleCountFlags::TYPE_32) {
-        //     return vk::SampleCountFlags::TYPE_32;
-        // }
-        // if count.contains(vk::SampleCountFlags::TYPE_16) {
-        //     return vk::SampleCountFlags::TYPE_16;
-        // }
-        // if count.contains(vk::SampleCountFlags::TYPE_8) {
-        //     return vk::SampleCountFlags::TYPE_8;
-        // }
-        if count.contains(vk::SampleCountFlags::TYPE_4) {
-            return vk::SampleCountFlags::TYPE_4;
-        }
-        if count.contains(vk::SampleCountFlags::TYPE_2) {
-            return vk::SampleCountFlags::TYPE_2;
-        }
+    /// Takes effect when the swapchain gets recreated.
+    pub fn next_msaa_samples(&mut self) {
+        self.msaa_samples = msaa::next_supported(
+            VulkanGraphicsSetup::supported_msaa_samples(&self.core),
+            self.msaa_samples,
+        );
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
 
-        vk::SampleCountFlags::TYPE_1
+    /// Or the closest one supported, that's what it returns. Takes effect when the swapchain gets
+    /// recreated.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> VulkanResult<PresentMode> {
+        self.present_mode = match &self.surface_composite {
+            Some(surface_composite) => present_mode.or_available(
+                &VulkanGraphicsSetup::find_swapchain_support(
+                    self.core.physical_device,
+                    surface_composite,
+                )?
+                .present_modes,
+            ),
+            None => present_mode, // nothing gets presented anyway
+        };
+        Ok(self.present_mode)
+    }
+
+    /// Skips the unsupported ones. Takes effect when the swapchain gets recreated.
+    pub fn next_present_mode(&mut self) -> VulkanResult<PresentMode> {
+        let mut present_mode = self.present_mode.next();
+        // FIFO is always supported, so it ends there at the latest
+        while self.set_present_mode(present_mode)? != present_mode {
+            present_mode = present_mode.next();
+        }
+        Ok(present_mode)
     }
 
     fn create_color_resources(
         core: &VulkanCore,
         swapchain_extent: vk::Extent2D,
         msaa_samples: vk::SampleCountFlags,
-    ) -> (vk::Image, vk::ImageView, vk::DeviceMemory) {
-        let color_format = COLOR_FORMAT;
+    ) -> VulkanResult<(vk::Image, vk::ImageView, Allocation)> {
+        if msaa_samples == vk::SampleCountFlags::TYPE_1 {
+            // destroying null handles does nothing, so cleaning up doesn't need to care
+            return Ok((
+                vk::Image::null(),
+                vk::ImageView::null(),
+                Allocation::default(),
+            ));
+        }
+        let color_format = HDR_FORMAT;
         let (color_image, color_image_memory) = core.create_image(
-            swapchain_extent.width,
-            swapchain_extent.height,
-            1,
-            msaa_samples,
-            color_format,
-            vk::ImageTiling::OPTIMAL,
-            vk::ImageUsageFlags::TRANSIENT_ATTACHMENT | vk::ImageUsageFlags::COLOR_ATTACHMENT,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        );
+            &ImageDescription {
+                extent: swapchain_extent,
+                samples: msaa_samples,
+                format: color_format,
+                usage: vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
+                    | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                ..Default::default()
+            },
+            "multisampled color image",
+        )?;
         let color_image_view =
-            core.create_image_view(color_image, color_format, vk::ImageAspectFlags::COLOR, 1);
+            core.create_image_view(color_image, color_format, vk::ImageAspectFlags::COLOR, 1)?;
 
-        (color_image, color_image_view, color_image_memory)
+        Ok((color_image, color_image_view, color_image_memory))
     }
 
     fn create_depth_resources(
         core: &VulkanCore,
         swapchain_extent: vk::Extent2D,
         msaa_samples: vk::SampleCountFlags,
-    ) -> (vk::Image, vk::ImageView, vk::DeviceMemory) {
+    ) -> VulkanResult<(vk::Image, vk::ImageView, Allocation)> {
         let depth_format =
-            VulkanGraphicsSetup::find_depth_format(&core.instance, core.physical_device);
+            VulkanGraphicsSetup::find_depth_format(&core.instance, core.physical_device)?;
         let (depth_image, depth_image_memory) = core.create_image(
-            swapchain_extent.width,
-            swapchain_extent.height,
-            1,
-            msaa_samples,
-            depth_format,
-            vk::ImageTiling::OPTIMAL,
-            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        );
+            &ImageDescription {
+                extent: swapchain_extent,
+                samples: msaa_samples,
+                format: depth_format,
+                usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                ..Default::default()
+            },
+            "depth image",
+        )?;
         let depth_image_view =
-            core.create_image_view(depth_image, depth_format, vk::ImageAspectFlags::DEPTH, 1);
+            core.create_image_view(depth_image, depth_format, vk::ImageAspectFlags::DEPTH, 1)?;
 
-        (depth_image, depth_image_view, depth_image_memory)
+        Ok((depth_image, depth_image_view, depth_image_memory))
     }
 
     fn find_depth_format(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
-    ) -> vk::Format {
+    ) -> VulkanResult<vk::Format> {
         VulkanGraphicsSetup::find_supported_format(
             instance,
             physical_device,
@@ -856,79 +2480,127 @@ impl VulkanGraphicsSetup {
         )
     }
 
+    fn find_shadow_map_format(core: &VulkanCore) -> VulkanResult<vk::Format> {
+        // D16_UNORM is guaranteed to be supported for both
+        VulkanGraphicsSetup::find_supported_format(
+            &core.instance,
+            core.physical_device,
+            &[vk::Format::D32_SFLOAT, vk::Format::D16_UNORM],
+            vk::ImageTiling::OPTIMAL,
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+        )
+    }
+
     fn find_supported_format(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
         candidate_formats: &[vk::Format],
         tiling: vk::ImageTiling,
         features: vk::FormatFeatureFlags,
-    ) -> vk::Format {
+    ) -> VulkanResult<vk::Format> {
         for &format in candidate_formats.iter() {
             let format_properties =
                 unsafe { instance.get_physical_device_format_properties(physical_device, format) };
             if tiling == vk::ImageTiling::LINEAR
                 && format_properties.linear_tiling_features.contains(features)
             {
-                return format.clone();
+                return Ok(format);
             } else if tiling == vk::ImageTiling::OPTIMAL
                 && format_properties.optimal_tiling_features.contains(features)
             {
-                return format.clone();
+                return Ok(format);
             }
         }
 
-        panic!("Failed to find supported format!")
+        Err(VulkanError::Unsupported("supported format"))
     }
 
     fn create_framebuffers(
         device: &ash::Device,
         render_pass: vk::RenderPass,
-        image_views: &Vec<vk::ImageView>,
-        color_image_view: vk::ImageView,
-        depth_image_view: vk::ImageView,
-        swapchain_extent: &vk::Extent2D,
-    ) -> Vec<vk::Framebuffer> {
-        let mut framebuffers = vec![];
+        image_views: &[vk::ImageView],
+        swapchain_extent: vk::Extent2D,
+    ) -> VulkanResult<Vec<vk::Framebuffer>> {
+        image_views
+            .iter()
+            .map(|&image_view| {
+                VulkanGraphicsSetup::create_framebuffer(
+                    device,
+                    render_pass,
+                    &[image_view],
+                    swapchain_extent,
+                )
+            })
+            .collect()
+    }
 
-        for &image_view in image_views.iter() {
-            let attachments = [color_image_view, depth_image_view, image_view];
+    fn create_framebuffer(
+        device: &ash::Device,
+        render_pass: vk::RenderPass,
+        attachments: &[vk::ImageView],
+        extent: vk::Extent2D,
+    ) -> VulkanResult<vk::Framebuffer> {
+        if render_pass == vk::RenderPass::null() {
+            return Ok(vk::Framebuffer::null()); // dynamic rendering doesn't need any
+        }
+        let framebuffer_create_info = vk::FramebufferCreateInfo {
+            flags: vk::FramebufferCreateFlags::empty(),
+            render_pass,
+            attachment_count: attachments.len() as u32,
+            p_attachments: attachments.as_ptr(),
+            width: extent.width,
+            height: extent.height,
+            layers: 1,
+            ..Default::default()
+        };
 
-            let framebuffer_create_info = vk::FramebufferCreateInfo {
-                flags: vk::FramebufferCreateFlags::empty(),
-                render_pass,
-                attachment_count: attachments.len() as u32,
-                p_attachments: attachments.as_ptr(),
-                width: swapchain_extent.width,
-                height: swapchain_extent.height,
-                layers: 1,
-                ..Default::default()
-            };
+        unsafe {
+            device
+                .create_framebuffer(&framebuffer_create_info, None)
+                .context("Failed to create Framebuffer")
+        }
+    }
 
-            let framebuffer = unsafe {
-                device
-                    .create_framebuffer(&framebuffer_create_info, None)
-                    .expect("Failed to create Framebuffer!")
-            };
+    fn create_post_descriptor_pool(device: &ash::Device) -> VulkanResult<vk::DescriptorPool> {
+        let pool_sizes = [vk::DescriptorPoolSize {
+            // bright pass, two blur passes and tone mapping, two images each
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 8,
+        }];
 
-            framebuffers.push(framebuffer);
-        }
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo {
+            max_sets: 4,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
 
-        framebuffers
+        unsafe {
+            device
+                .create_descriptor_pool(&descriptor_pool_create_info, None)
+                .context("Failed to create Descriptor Pool")
+        }
     }
 
     fn create_color_descriptor_pool(
         device: &ash::Device,
         swapchain_images_size: usize,
-    ) -> vk::DescriptorPool {
+    ) -> VulkanResult<vk::DescriptorPool> {
         let pool_sizes = [
             vk::DescriptorPoolSize {
-                // CameraUBO
+                // CameraUBO and fog
                 ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 2 * swapchain_images_size as u32,
+            },
+            vk::DescriptorPoolSize {
+                // lights
+                ty: vk::DescriptorType::STORAGE_BUFFER,
                 descriptor_count: swapchain_images_size as u32,
             },
             vk::DescriptorPoolSize {
-                // LightsUBO
-                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                // shadow map
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
                 descriptor_count: swapchain_images_size as u32,
             },
         ];
@@ -943,26 +2615,58 @@ impl VulkanGraphicsSetup {
         unsafe {
             device
                 .create_descriptor_pool(&descriptor_pool_create_info, None)
-                .expect("Failed to create Descriptor Pool!")
+                .context("Failed to create Descriptor Pool")
         }
     }
 
     fn create_textured_descriptor_pool(
         device: &ash::Device,
         swapchain_images_size: usize,
-    ) -> vk::DescriptorPool {
+    ) -> VulkanResult<vk::DescriptorPool> {
         let pool_sizes = [
             vk::DescriptorPoolSize {
-                // CameraUBO
+                // CameraUBO and fog
                 ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 2 * swapchain_images_size as u32,
+            },
+            vk::DescriptorPoolSize {
+                // lights
+                ty: vk::DescriptorType::STORAGE_BUFFER,
                 descriptor_count: swapchain_images_size as u32,
             },
             vk::DescriptorPoolSize {
-                // LightsUBO
+                // texture, normal map and shadow map
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 3 * swapchain_images_size as u32,
+            },
+        ];
+
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo {
+            max_sets: swapchain_images_size as u32,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe {
+            device
+                .create_descriptor_pool(&descriptor_pool_create_info, None)
+                .context("Failed to create Descriptor Pool")
+        }
+    }
+
+    fn create_skybox_descriptor_pool(
+        device: &ash::Device,
+        swapchain_images_size: usize,
+    ) -> VulkanResult<vk::DescriptorPool> {
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                // CameraUBO
                 ty: vk::DescriptorType::UNIFORM_BUFFER,
                 descriptor_count: swapchain_images_size as u32,
             },
             vk::DescriptorPoolSize {
+                // cubemap
                 ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
                 descriptor_count: swapchain_images_size as u32,
             },
@@ -978,7 +2682,140 @@ impl VulkanGraphicsSetup {
         unsafe {
             device
                 .create_descriptor_pool(&descriptor_pool_create_info, None)
-                .expect("Failed to create Descriptor Pool!")
+                .context("Failed to create Descriptor Pool")
+        }
+    }
+
+    fn create_shadow_descriptor_pool(
+        device: &ash::Device,
+        swapchain_images_size: usize,
+    ) -> VulkanResult<vk::DescriptorPool> {
+        let pool_sizes = [vk::DescriptorPoolSize {
+            // lights
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: swapchain_images_size as u32,
+        }];
+
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo {
+            max_sets: swapchain_images_size as u32,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe {
+            device
+                .create_descriptor_pool(&descriptor_pool_create_info, None)
+                .context("Failed to create Descriptor Pool")
+        }
+    }
+
+    /// Scene in HDR, multisampled color gets resolved into the HDR image.
+    pub(crate) fn scene_pass(&self) -> VulkanResult<rendering::Pass> {
+        let composite = &self.post_processing_composite;
+        let hdr_image = (composite.hdr_image, composite.hdr_image_view);
+        let multisampled = self.msaa_samples != vk::SampleCountFlags::TYPE_1;
+        let (image, view) = if multisampled {
+            (self.color_image, self.color_image_view)
+        } else {
+            hdr_image
+        };
+        let depth_format =
+            VulkanGraphicsSetup::find_depth_format(&self.core.instance, self.core.physical_device)?;
+        Ok(rendering::Pass {
+            render_pass: self.render_pass,
+            framebuffer: composite.hdr_framebuffer,
+            extent: self.swapchain_composite.extent,
+            color: Some(rendering::Attachment {
+                image,
+                view,
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                layer: 0,
+                resolve: if multisampled { Some(hdr_image) } else { None },
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op: vk::AttachmentStoreOp::STORE,
+                final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }),
+            depth: Some(rendering::Attachment {
+                image: self.depth_image,
+                view: self.depth_image_view,
+                aspect_mask: rendering::depth_aspect_mask(depth_format),
+                layer: 0,
+                resolve: None,
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op: vk::AttachmentStoreOp::DONT_CARE,
+                final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            }),
+        })
+    }
+
+    pub(crate) fn shadow_pass(&self, layer: usize) -> rendering::Pass {
+        let composite = &self.shadow_map_composite;
+        rendering::Pass {
+            render_pass: self.shadow_render_pass,
+            framebuffer: composite.framebuffers[layer],
+            extent: composite.extent,
+            color: None,
+            depth: Some(rendering::Attachment {
+                image: composite.image,
+                view: composite.layer_image_views[layer],
+                aspect_mask: vk::ImageAspectFlags::DEPTH,
+                layer: layer as u32,
+                resolve: None,
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op: vk::AttachmentStoreOp::STORE,
+                final_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            }),
+        }
+    }
+
+    pub(crate) fn bloom_pass(&self, index: usize) -> rendering::Pass {
+        let composite = &self.post_processing_composite;
+        VulkanGraphicsSetup::post_pass(
+            self.bloom_render_pass,
+            composite.bloom_framebuffers[index],
+            composite.bloom_extent,
+            composite.bloom_images[index],
+            composite.bloom_image_views[index],
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )
+    }
+
+    pub(crate) fn tonemap_pass(&self, image_index: usize) -> rendering::Pass {
+        let composite = &self.swapchain_composite;
+        VulkanGraphicsSetup::post_pass(
+            self.tonemap_render_pass,
+            composite.framebuffers[image_index],
+            composite.extent,
+            composite.images[image_index],
+            composite.image_views[image_index],
+            composite.final_layout(),
+        )
+    }
+
+    fn post_pass(
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D,
+        image: vk::Image,
+        view: vk::ImageView,
+        final_layout: vk::ImageLayout,
+    ) -> rendering::Pass {
+        rendering::Pass {
+            render_pass,
+            framebuffer,
+            extent,
+            color: Some(rendering::Attachment {
+                image,
+                view,
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                layer: 0,
+                resolve: None,
+                load_op: vk::AttachmentLoadOp::DONT_CARE, // every pixel gets overwritten anyway
+                store_op: vk::AttachmentStoreOp::STORE,
+                final_layout,
+            }),
+            depth: None,
         }
     }
 
@@ -987,38 +2824,197 @@ impl VulkanGraphicsSetup {
         self.window_height = window_height;
     }
 
-    pub fn recreate_swapchain(&mut self) {
-        let surface_composite = SurfaceComposite {
-            loader: self.surface_composite.loader.clone(),
-            surface: self.surface_composite.surface,
-        };
+    /// The only pipeline that doesn't get recreated together with the swapchain.
+    #[cfg(feature = "hot-reload")]
+    pub fn recreate_shadow_pipeline(&mut self) -> VulkanResult<()> {
+        unsafe {
+            self.core
+                .device
+                .destroy_pipeline(self.shadow_pipeline, None);
+            self.core
+                .device
+                .destroy_pipeline_layout(self.shadow_pipeline_layout, None);
+        }
+        let (shadow_pipeline, shadow_pipeline_layout) =
+            VulkanGraphicsSetup::create_shadow_pipeline(
+                &self.core,
+                self.shadow_render_pass,
+                self.shadow_map_composite.extent,
+                self.shadow_descriptor_set_layout,
+            )?;
+        self.shadow_pipeline = shadow_pipeline;
+        self.shadow_pipeline_layout = shadow_pipeline_layout;
+        Ok(())
+    }
+
+    /// Builds all pipelines with `core`'s shaders for the current render passes and destroys them
+    /// right away, so that shaders changed at runtime get checked before anything is torn down.
+    #[cfg(feature = "hot-reload")]
+    pub fn try_pipelines(&self, core: &VulkanCore) -> VulkanResult<()> {
+        let mut pipelines = vec![];
+        let result = self.create_trial_pipelines(core, &mut pipelines);
+        unsafe {
+            for (pipeline, pipeline_layout) in pipelines {
+                core.device.destroy_pipeline(pipeline, None);
+                core.device.destroy_pipeline_layout(pipeline_layout, None);
+            }
+        }
+        result
+    }
+
+    /// Every pipeline built goes into `pipelines`, even when a later one fails.
+    #[cfg(feature = "hot-reload")]
+    fn create_trial_pipelines(
+        &self,
+        core: &VulkanCore,
+        pipelines: &mut Vec<(vk::Pipeline, vk::PipelineLayout)>,
+    ) -> VulkanResult<()> {
+        let extent = self.swapchain_composite.extent;
+        let meshes = [
+            (
+                "color",
+                Vertex::get_binding_descriptions(),
+                Vertex::get_attribute_descriptions(),
+                color_mesh::InstanceData::get_binding_descriptions(),
+                color_mesh::InstanceData::get_attribute_descriptions(),
+                self.color_descriptor_set_layout,
+            ),
+            (
+                "pbr",
+                Vertex::get_binding_descriptions(),
+                Vertex::get_attribute_descriptions(),
+                PbrInstanceData::get_binding_descriptions(),
+                PbrInstanceData::get_attribute_descriptions(),
+                self.color_descriptor_set_layout,
+            ),
+            (
+                "textured",
+                TexturedVertex::get_binding_descriptions(),
+                TexturedVertex::get_attribute_descriptions(),
+                textured_mesh::InstanceData::get_binding_descriptions(),
+                textured_mesh::InstanceData::get_attribute_descriptions(),
+                self.textured_descriptor_set_layout,
+            ),
+            (
+                "bulb",
+                Vertex::get_binding_descriptions(),
+                Vertex::get_attribute_descriptions(),
+                BulbInstanceData::get_binding_descriptions(),
+                BulbInstanceData::get_attribute_descriptions(),
+                self.color_descriptor_set_layout,
+            ),
+        ];
+        for (
+            shader,
+            vertex_binding_descriptions,
+            vertex_attribute_descriptions,
+            instance_binding_descriptions,
+            instance_attribute_descriptions,
+            descriptor_set_layout,
+        ) in meshes
+        {
+            pipelines.push(VulkanGraphicsSetup::create_pipeline(
+                core,
+                &core.shaders.get(&format!("{}.vert", shader)),
+                &core.shaders.get(&format!("{}.frag", shader)),
+                vertex_binding_descriptions,
+                vertex_attribute_descriptions,
+                instance_binding_descriptions,
+                instance_attribute_descriptions,
+                self.render_pass,
+                extent,
+                descriptor_set_layout,
+                self.msaa_samples,
+                "trial pipeline",
+            )?);
+        }
+        pipelines.push(VulkanGraphicsSetup::create_skybox_pipeline(
+            core,
+            self.render_pass,
+            extent,
+            self.skybox_descriptor_set_layout,
+            self.msaa_samples,
+        )?);
+        pipelines.push(VulkanGraphicsSetup::create_shadow_pipeline(
+            core,
+            self.shadow_render_pass,
+            self.shadow_map_composite.extent,
+            self.shadow_descriptor_set_layout,
+        )?);
+
+        let bloom_extent = self.post_processing_composite.bloom_extent;
+        let post_passes = [
+            (
+                "bright_pass.frag",
+                self.bloom_render_pass,
+                HDR_FORMAT,
+                bloom_extent,
+            ),
+            (
+                "blur.frag",
+                self.bloom_render_pass,
+                HDR_FORMAT,
+                bloom_extent,
+            ),
+            (
+                "tonemap.frag",
+                self.tonemap_render_pass,
+                self.swapchain_composite.format,
+                extent,
+            ),
+        ];
+        for (shader, render_pass, format, extent) in post_passes {
+            let pipeline = VulkanGraphicsSetup::create_post_pipeline(
+                core,
+                &core.shaders.get(shader),
+                render_pass,
+                format,
+                extent,
+                self.post_pipeline_layout,
+                "trial pipeline",
+            )?;
+            // the layout is shared, destroying null instead does nothing
+            pipelines.push((pipeline, vk::PipelineLayout::null()));
+        }
+        Ok(())
+    }
 
+    pub fn recreate_swapchain(&mut self) -> VulkanResult<()> {
         unsafe {
             self.core
                 .device
                 .device_wait_idle()
-                .expect("Failed to wait device idle!")
+                .context("Failed to wait device idle")?
         };
         self.cleanup_swapchain();
 
-        self.swapchain_composite = VulkanGraphicsSetup::create_swapchain(
-            &self.core,
-            &surface_composite,
-            self.window_width,
-            self.window_height,
-        );
+        self.swapchain_composite = match &self.surface_composite {
+            Some(surface_composite) => VulkanGraphicsSetup::create_swapchain(
+                &self.core,
+                surface_composite,
+                self.window_width,
+                self.window_height,
+                self.present_mode,
+            )?,
+            None => VulkanGraphicsSetup::create_offscreen_targets(
+                &self.core,
+                self.window_width,
+                self.window_height,
+            )?,
+        };
 
         self.swapchain_composite.image_views =
-            VulkanGraphicsSetup::create_image_views(&self.core.device, &self.swapchain_composite);
-        self.render_pass = VulkanGraphicsSetup::create_render_pass(
+            VulkanGraphicsSetup::create_image_views(&self.core.device, &self.swapchain_composite)?;
+        self.render_pass = VulkanGraphicsSetup::create_render_pass(&self.core, self.msaa_samples)?;
+        self.tonemap_render_pass = VulkanGraphicsSetup::create_post_render_pass(
             &self.core,
             self.swapchain_composite.format,
-            self.msaa_samples,
-        );
+            self.swapchain_composite.final_layout(),
+        )?;
         let (color_pipeline, color_pipeline_layout) = VulkanGraphicsSetup::create_pipeline(
             &self.core,
-            COLOR_VERTEX_SHADER_SPV,
-            COLOR_FRAGMENT_SHADER_SPV,
+            &self.core.shaders.get("color.vert"),
+            &self.core.shaders.get("color.frag"),
             Vertex::get_binding_descriptions(),
             Vertex::get_attribute_descriptions(),
             color_mesh::InstanceData::get_binding_descriptions(),
@@ -1027,13 +3023,30 @@ impl VulkanGraphicsSetup {
             self.swapchain_composite.extent,
             self.color_descriptor_set_layout,
             self.msaa_samples,
-        );
+            "color pipeline",
+        )?;
         self.color_pipeline = color_pipeline;
         self.color_pipeline_layout = color_pipeline_layout;
+        let (pbr_pipeline, pbr_pipeline_layout) = VulkanGraphicsSetup::create_pipeline(
+            &self.core,
+            &self.core.shaders.get("pbr.vert"),
+            &self.core.shaders.get("pbr.frag"),
+            Vertex::get_binding_descriptions(),
+            Vertex::get_attribute_descriptions(),
+            PbrInstanceData::get_binding_descriptions(),
+            PbrInstanceData::get_attribute_descriptions(),
+            self.render_pass,
+            self.swapchain_composite.extent,
+            self.color_descriptor_set_layout,
+            self.msaa_samples,
+            "pbr pipeline",
+        )?;
+        self.pbr_pipeline = pbr_pipeline;
+        self.pbr_pipeline_layout = pbr_pipeline_layout;
         let (textured_pipeline, textured_pipeline_layout) = VulkanGraphicsSetup::create_pipeline(
             &self.core,
-            TEXTURED_VERTEX_SHADER_SPV,
-            TEXTURED_FRAGMENT_SHADER_SPV,
+            &self.core.shaders.get("textured.vert"),
+            &self.core.shaders.get("textured.frag"),
             TexturedVertex::get_binding_descriptions(),
             TexturedVertex::get_attribute_descriptions(),
             textured_mesh::InstanceData::get_binding_descriptions(),
@@ -1042,16 +3055,43 @@ impl VulkanGraphicsSetup {
             self.swapchain_composite.extent,
             self.textured_descriptor_set_layout,
             self.msaa_samples,
-        );
+            "textured pipeline",
+        )?;
         self.textured_pipeline = textured_pipeline;
         self.textured_pipeline_layout = textured_pipeline_layout;
+        let (bulb_pipeline, bulb_pipeline_layout) = VulkanGraphicsSetup::create_pipeline(
+            &self.core,
+            &self.core.shaders.get("bulb.vert"),
+            &self.core.shaders.get("bulb.frag"),
+            Vertex::get_binding_descriptions(),
+            Vertex::get_attribute_descriptions(),
+            BulbInstanceData::get_binding_descriptions(),
+            BulbInstanceData::get_attribute_descriptions(),
+            self.render_pass,
+            self.swapchain_composite.extent,
+            self.color_descriptor_set_layout,
+            self.msaa_samples,
+            "bulb pipeline",
+        )?;
+        self.bulb_pipeline = bulb_pipeline;
+        self.bulb_pipeline_layout = bulb_pipeline_layout;
+        let (skybox_pipeline, skybox_pipeline_layout) =
+            VulkanGraphicsSetup::create_skybox_pipeline(
+                &self.core,
+                self.render_pass,
+                self.swapchain_composite.extent,
+                self.skybox_descriptor_set_layout,
+                self.msaa_samples,
+            )?;
+        self.skybox_pipeline = skybox_pipeline;
+        self.skybox_pipeline_layout = skybox_pipeline_layout;
 
         let (color_image, color_image_view, color_image_memory) =
             VulkanGraphicsSetup::create_color_resources(
                 &self.core,
                 self.swapchain_composite.extent,
                 self.msaa_samples,
-            );
+            )?;
         self.color_image = color_image;
         self.color_image_view = color_image_view;
         self.color_image_memory = color_image_memory;
@@ -1060,65 +3100,112 @@ impl VulkanGraphicsSetup {
                 &self.core,
                 self.swapchain_composite.extent,
                 self.msaa_samples,
-            );
+            )?;
         self.depth_image = depth_image;
         self.depth_image_view = depth_image_view;
         self.depth_image_memory = depth_image_memory;
 
         self.swapchain_composite.framebuffers = VulkanGraphicsSetup::create_framebuffers(
             &self.core.device,
-            self.render_pass,
+            self.tonemap_render_pass,
             &self.swapchain_composite.image_views,
-            self.color_image_view,
-            self.depth_image_view,
-            &self.swapchain_composite.extent,
-        );
+            self.swapchain_composite.extent,
+        )?;
+        self.post_processing_composite = VulkanGraphicsSetup::create_post_processing(
+            &self.core,
+            self.swapchain_composite.extent,
+            PostProcessingTargets {
+                render_pass: self.render_pass,
+                bloom_render_pass: self.bloom_render_pass,
+                tonemap_render_pass: self.tonemap_render_pass,
+                tonemap_format: self.swapchain_composite.format,
+                color_image_view: self.color_image_view,
+                depth_image_view: self.depth_image_view,
+                msaa_samples: self.msaa_samples,
+            },
+            self.post_descriptor_pool,
+            self.post_descriptor_set_layout,
+            self.post_pipeline_layout,
+        )?;
+        Ok(())
     }
 
     pub fn cleanup_swapchain(&self) {
         unsafe {
             let device = &self.core.device;
+            self.post_processing_composite.drop(&self.core);
+            // resetting can't fail, it only ever returns success
+            let _ = device.reset_descriptor_pool(
+                self.post_descriptor_pool,
+                vk::DescriptorPoolResetFlags::empty(),
+            );
             device.destroy_image_view(self.color_image_view, None);
             device.destroy_image(self.color_image, None);
-            device.free_memory(self.color_image_memory, None);
+            self.core.free_memory(self.color_image_memory);
             device.destroy_image_view(self.depth_image_view, None);
             device.destroy_image(self.depth_image, None);
-            device.free_memory(self.depth_image_memory, None);
-
-            for &framebuffer in self.swapchain_composite.framebuffers.iter() {
-                device.destroy_framebuffer(framebuffer, None);
-            }
+            self.core.free_memory(self.depth_image_memory);
+            device.destroy_pipeline(self.skybox_pipeline, None);
+            device.destroy_pipeline_layout(self.skybox_pipeline_layout, None);
+            device.destroy_pipeline(self.bulb_pipeline, None);
+            device.destroy_pipeline_layout(self.bulb_pipeline_layout, None);
             device.destroy_pipeline(self.textured_pipeline, None);
             device.destroy_pipeline_layout(self.textured_pipeline_layout, None);
+            device.destroy_pipeline(self.pbr_pipeline, None);
+            device.destroy_pipeline_layout(self.pbr_pipeline_layout, None);
             device.destroy_pipeline(self.color_pipeline, None);
             device.destroy_pipeline_layout(self.color_pipeline_layout, None);
+            device.destroy_render_pass(self.tonemap_render_pass, None);
             device.destroy_render_pass(self.render_pass, None);
-            for &image_view in self.swapchain_composite.image_views.iter() {
-                device.destroy_image_view(image_view, None);
-            }
-            self.swapchain_composite
-                .loader
-                .destroy_swapchain(self.swapchain_composite.swapchain, None);
         }
+        self.swapchain_composite.drop(&self.core);
     }
 
     pub fn drop(&self) {
         unsafe {
+            let device = &self.core.device;
+            device.destroy_pipeline(self.shadow_pipeline, None);
+            device.destroy_pipeline_layout(self.shadow_pipeline_layout, None);
+            self.shadow_map_composite.drop(&self.core);
+            device.destroy_render_pass(self.shadow_render_pass, None);
+            device.destroy_pipeline_layout(self.post_pipeline_layout, None);
+            device.destroy_render_pass(self.bloom_render_pass, None);
+
+            self.core
+                .device
+                .destroy_descriptor_pool(self.post_descriptor_pool, None);
+            self.core
+                .device
+                .destroy_descriptor_pool(self.shadow_descriptor_pool, None);
+            self.core
+                .device
+                .destroy_descriptor_pool(self.skybox_descriptor_pool, None);
             self.core
                 .device
                 .destroy_descriptor_pool(self.textured_descriptor_pool, None);
             self.core
                 .device
                 .destroy_descriptor_pool(self.color_descriptor_pool, None);
+            self.core
+                .device
+                .destroy_descriptor_set_layout(self.post_descriptor_set_layout, None);
+            self.core
+                .device
+                .destroy_descriptor_set_layout(self.shadow_descriptor_set_layout, None);
+            self.core
+                .device
+                .destroy_descriptor_set_layout(self.skybox_descriptor_set_layout, None);
             self.core
                 .device
                 .destroy_descriptor_set_layout(self.textured_descriptor_set_layout, None);
             self.core
                 .device
                 .destroy_descriptor_set_layout(self.color_descriptor_set_layout, None);
-            self.surface_composite
-                .loader
-                .destroy_surface(self.surface_composite.surface, None);
+            if let Some(surface_composite) = &self.surface_composite {
+                surface_composite
+                    .loader
+                    .destroy_surface(surface_composite.surface, None);
+            }
             self.core
                 .device
                 .destroy_command_pool(self.command_pool, None);
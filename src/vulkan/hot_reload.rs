@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+const SHADERS_DIR: &str = "src/shaders";
+// checking every frame would be a waste, nobody saves files that often
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Recompiles shaders whenever their sources change, same way build.rs does.
+pub(crate) struct ShaderWatcher {
+    compiler: shaderc::Compiler,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_check: Instant,
+}
+
+impl ShaderWatcher {
+    pub(crate) fn new() -> Result<Self, String> {
+        let compiler = shaderc::Compiler::new()
+            .ok_or_else(|| "Failed to create shader compiler".to_string())?;
+        let mut watcher = ShaderWatcher {
+            compiler,
+            modified: HashMap::new(),
+            last_check: Instant::now(),
+        };
+        // whatever is there now got embedded already
+        watcher.modified = watcher.scan();
+        Ok(watcher)
+    }
+
+    /// Names and SPIR-V of the shaders changed since the last call. Ones that don't compile get
+    /// reported and skipped, so that their last good version stays in use.
+    pub(crate) fn recompile_changed(&mut self) -> Vec<(String, Vec<u8>)> {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return vec![];
+        }
+        self.last_check = Instant::now();
+
+        let modified = self.scan();
+        let changed: Vec<PathBuf> = modified
+            .iter()
+            .filter(|(path, time)| self.modified.get(*path) != Some(time))
+            .map(|(path, _)| path.clone())
+            .collect();
+        self.modified = modified;
+
+        changed
+            .iter()
+            .filter_map(|path| {
+                let name = path.file_name()?.to_string_lossy().to_string();
+                match self.compile(path, &name) {
+                    Ok(spirv) => {
+                        log::info!("Reloaded shader {}", name);
+                        Some((name, spirv))
+                    }
+                    Err(message) => {
+                        log::error!("Failed to compile shader {}:\n{}", name, message);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn scan(&self) -> HashMap<PathBuf, SystemTime> {
+        let entries = match std::fs::read_dir(SHADERS_DIR) {
+            Ok(entries) => entries,
+            Err(_) => return HashMap::new(), // not started from the repo's root
+        };
+        entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let modified = entry.metadata().ok()?.modified().ok()?;
+                Some((entry.path(), modified))
+            })
+            .collect()
+    }
+
+    fn compile(&self, path: &PathBuf, name: &str) -> Result<Vec<u8>, String> {
+        let shader_kind = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("vert") => shaderc::ShaderKind::Vertex,
+            Some("frag") => shaderc::ShaderKind::Fragment,
+            Some("comp") => shaderc::ShaderKind::Compute,
+            _ => return Err("Unknown shader type".to_string()),
+        };
+        let source = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let mut options = shaderc::CompileOptions::new()
+            .ok_or_else(|| "Failed to create shader compile options".to_string())?;
+        options.add_macro_definition("EP", Some("main"));
+        self.compiler
+            .compile_into_spirv(&source, shader_kind, name, "main", Some(&options))
+            .map(|artifact| artifact.as_binary_u8().to_vec())
+            .map_err(|error| error.to_string())
+    }
+}
@@ -0,0 +1,511 @@
+use std::fmt;
+use std::ptr;
+
+use ash::vk;
+
+use crate::vulkan::error::{Context, VulkanResult};
+
+// plenty for a scene, yet few enough allocations to stay far from maxMemoryAllocationCount
+const BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+// small heaps shouldn't be used up by a single half-empty block
+const MAX_BLOCKS_PER_HEAP_SIZE: vk::DeviceSize = 8;
+
+/// How space in a block gets reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Strategy {
+    /// Freed space can be allocated again right away, for resources living long.
+    FreeList,
+    /// Allocations just follow each other, the block is reused once all of them got freed. For
+    /// short-lived resources, like staging buffers.
+    Linear,
+}
+
+/// Part of a memory block, host-visible ones stay mapped for as long as they live.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Allocation {
+    pub memory: vk::DeviceMemory,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    block: usize,
+    mapped: *mut u8,
+}
+
+impl Default for Allocation {
+    /// Nothing allocated, freeing it does nothing.
+    fn default() -> Self {
+        Allocation {
+            memory: vk::DeviceMemory::null(),
+            offset: 0,
+            size: 0,
+            block: 0,
+            mapped: ptr::null_mut(),
+        }
+    }
+}
+
+impl Allocation {
+    pub(crate) fn mapped_ptr<T>(&self) -> *mut T {
+        assert!(!self.mapped.is_null(), "Memory is not host-visible!");
+        self.mapped as *mut T
+    }
+}
+
+/// Used and free bytes of a memory heap, free meaning allocated from the driver but not in use.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryHeapStats {
+    pub heap_index: u32,
+    pub device_local: bool,
+    pub size: vk::DeviceSize,
+    pub blocks: usize,
+    pub allocations: usize,
+    pub used: vk::DeviceSize,
+    pub free: vk::DeviceSize,
+}
+
+impl fmt::Display for MemoryHeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mib = |bytes: vk::DeviceSize| bytes as f64 / (1024. * 1024.);
+        write!(
+            f,
+            "Heap {} ({}, {:.0} MiB): {:.1} MiB used, {:.1} MiB free, {} allocations in {} blocks",
+            self.heap_index,
+            if self.device_local {
+                "device local"
+            } else {
+                "host"
+            },
+            mib(self.size),
+            mib(self.used),
+            mib(self.free),
+            self.allocations,
+            self.blocks
+        )
+    }
+}
+
+/// Hands out parts of big memory blocks instead of allocating memory for every single resource.
+/// Every memory type gets its own blocks, for each strategy.
+pub(crate) struct MemoryAllocator {
+    device: ash::Device,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    buffer_image_granularity: vk::DeviceSize,
+    blocks: Vec<Option<Block>>, // freed ones leave a hole, so that allocations keep their index
+}
+
+struct Block {
+    memory: vk::DeviceMemory,
+    memory_type: u32,
+    mapped: *mut u8,
+    allocations: usize,
+    used: vk::DeviceSize,
+    space: Space,
+}
+
+enum Space {
+    FreeList(FreeList),
+    Linear(Linear),
+}
+
+impl Block {
+    fn size(&self) -> vk::DeviceSize {
+        match &self.space {
+            Space::FreeList(free_list) => free_list.size,
+            Space::Linear(linear) => linear.size,
+        }
+    }
+
+    fn strategy(&self) -> Strategy {
+        match &self.space {
+            Space::FreeList(_) => Strategy::FreeList,
+            Space::Linear(_) => Strategy::Linear,
+        }
+    }
+
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let offset = match &mut self.space {
+            Space::FreeList(free_list) => free_list.allocate(size, alignment),
+            Space::Linear(linear) => linear.allocate(size, alignment),
+        }?;
+        self.allocations += 1;
+        self.used += size;
+        Some(offset)
+    }
+
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        match &mut self.space {
+            Space::FreeList(free_list) => free_list.free(offset, size),
+            Space::Linear(linear) => linear.free(),
+        }
+        self.allocations -= 1;
+        self.used -= size;
+    }
+}
+
+impl MemoryAllocator {
+    pub(crate) fn new(
+        device: ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        buffer_image_granularity: vk::DeviceSize,
+    ) -> Self {
+        MemoryAllocator {
+            device,
+            memory_properties,
+            buffer_image_granularity,
+            blocks: vec![],
+        }
+    }
+
+    /// Linear resources are buffers and linearly tiled images, everything else mustn't share a
+    /// page of `bufferImageGranularity` with them.
+    pub(crate) fn allocate(
+        &mut self,
+        memory_type: u32,
+        requirements: vk::MemoryRequirements,
+        strategy: Strategy,
+        linear: bool,
+    ) -> VulkanResult<Allocation> {
+        let (size, alignment) = if linear {
+            (requirements.size, requirements.alignment)
+        } else {
+            let granularity = self.buffer_image_granularity;
+            (
+                align_up(requirements.size, granularity),
+                requirements.alignment.max(granularity),
+            )
+        };
+
+        for (index, slot) in self.blocks.iter_mut().enumerate() {
+            if let Some(block) = slot {
+                if block.memory_type != memory_type || block.strategy() != strategy {
+                    continue;
+                }
+                if let Some(offset) = block.allocate(size, alignment) {
+                    return Ok(Allocation {
+                        memory: block.memory,
+                        offset,
+                        size,
+                        block: index,
+                        mapped: mapped_at(block.mapped, offset),
+                    });
+                }
+            }
+        }
+
+        let mut block = self.create_block(memory_type, size, strategy)?;
+        let offset = block
+            .allocate(size, alignment)
+            .expect("Fresh memory block too small!");
+        let allocation = Allocation {
+            memory: block.memory,
+            offset,
+            size,
+            block: 0,
+            mapped: mapped_at(block.mapped, offset),
+        };
+        let index = match self.blocks.iter().position(|slot| slot.is_none()) {
+            Some(index) => {
+                self.blocks[index] = Some(block);
+                index
+            }
+            None => {
+                self.blocks.push(Some(block));
+                self.blocks.len() - 1
+            }
+        };
+        Ok(Allocation {
+            block: index,
+            ..allocation
+        })
+    }
+
+    /// Blocks that end up empty go back to the driver, except for linear ones of the usual size,
+    /// staging buffers keep coming.
+    pub(crate) fn free(&mut self, allocation: Allocation) {
+        if allocation.memory == vk::DeviceMemory::null() {
+            return;
+        }
+        let block = self.blocks[allocation.block]
+            .as_mut()
+            .expect("Memory freed twice!");
+        block.free(allocation.offset, allocation.size);
+        let keep = block.strategy() == Strategy::Linear && block.size() <= BLOCK_SIZE;
+        if block.allocations == 0 && !keep {
+            let block = self.blocks[allocation.block].take().unwrap();
+            self.destroy_block(&block);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> Vec<MemoryHeapStats> {
+        let heaps = &self.memory_properties.memory_heaps
+            [..self.memory_properties.memory_heap_count as usize];
+        let mut stats: Vec<MemoryHeapStats> = heaps
+            .iter()
+            .enumerate()
+            .map(|(heap_index, heap)| MemoryHeapStats {
+                heap_index: heap_index as u32,
+                device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                size: heap.size,
+                blocks: 0,
+                allocations: 0,
+                used: 0,
+                free: 0,
+            })
+            .collect();
+        for block in self.blocks.iter().flatten() {
+            let heap_index =
+                self.memory_properties.memory_types[block.memory_type as usize].heap_index as usize;
+            let heap_stats = &mut stats[heap_index];
+            heap_stats.blocks += 1;
+            heap_stats.allocations += block.allocations;
+            heap_stats.used += block.used;
+            heap_stats.free += block.size() - block.used;
+        }
+        stats
+    }
+
+    fn create_block(
+        &self,
+        memory_type: u32,
+        min_size: vk::DeviceSize,
+        strategy: Strategy,
+    ) -> VulkanResult<Block> {
+        let memory_type_properties = self.memory_properties.memory_types[memory_type as usize];
+        let heap_size =
+            self.memory_properties.memory_heaps[memory_type_properties.heap_index as usize].size;
+        let size = (heap_size / MAX_BLOCKS_PER_HEAP_SIZE)
+            .min(BLOCK_SIZE)
+            .max(min_size);
+
+        let allocate_info = vk::MemoryAllocateInfo {
+            allocation_size: size,
+            memory_type_index: memory_type,
+            ..Default::default()
+        };
+        let memory = unsafe {
+            self.device
+                .allocate_memory(&allocate_info, None)
+                .context("Failed to allocate memory block")?
+        };
+        let mapped = if memory_type_properties
+            .property_flags
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        {
+            let mapped = unsafe {
+                self.device
+                    .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+                    .context("Failed to Map Memory")
+            };
+            match mapped {
+                Ok(mapped) => mapped as *mut u8,
+                Err(error) => {
+                    unsafe { self.device.free_memory(memory, None) };
+                    return Err(error);
+                }
+            }
+        } else {
+            ptr::null_mut()
+        };
+
+        Ok(Block {
+            memory,
+            memory_type,
+            mapped,
+            allocations: 0,
+            used: 0,
+            space: match strategy {
+                Strategy::FreeList => Space::FreeList(FreeList::new(size)),
+                Strategy::Linear => Space::Linear(Linear::new(size)),
+            },
+        })
+    }
+
+    fn destroy_block(&self, block: &Block) {
+        unsafe {
+            // unmapped implicitly
+            self.device.free_memory(block.memory, None);
+        }
+    }
+
+    /// All of them, whatever is still allocated in there. The device has to be idle.
+    pub(crate) fn drop(&mut self) {
+        for block in std::mem::take(&mut self.blocks).iter().flatten() {
+            self.destroy_block(block);
+        }
+    }
+}
+
+fn mapped_at(mapped: *mut u8, offset: vk::DeviceSize) -> *mut u8 {
+    if mapped.is_null() {
+        mapped
+    } else {
+        unsafe { mapped.add(offset as usize) }
+    }
+}
+
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    value.div_ceil(alignment.max(1)) * alignment.max(1)
+}
+
+/// Free ranges sorted by offset, first fit. Neighbouring ones get merged back when freed.
+struct FreeList {
+    size: vk::DeviceSize,
+    free: Vec<(vk::DeviceSize, vk::DeviceSize)>, // offset and size
+}
+
+impl FreeList {
+    fn new(size: vk::DeviceSize) -> Self {
+        FreeList {
+            size,
+            free: vec![(0, size)],
+        }
+    }
+
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let (index, offset) = self
+            .free
+            .iter()
+            .enumerate()
+            .find_map(|(i, &(offset, free))| {
+                let aligned = align_up(offset, alignment);
+                (aligned - offset + size <= free).then_some((i, aligned))
+            })?;
+
+        let (free_offset, free_size) = self.free[index];
+        let mut remaining = vec![];
+        if offset > free_offset {
+            remaining.push((free_offset, offset - free_offset)); // lost to alignment, until merged
+        }
+        let end = offset + size;
+        if end < free_offset + free_size {
+            remaining.push((end, free_offset + free_size - end));
+        }
+        self.free.splice(index..=index, remaining);
+        Some(offset)
+    }
+
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let index = self
+            .free
+            .partition_point(|&(free_offset, _)| free_offset < offset);
+        self.free.insert(index, (offset, size));
+        if index + 1 < self.free.len() && offset + size == self.free[index + 1].0 {
+            self.free[index].1 += self.free[index + 1].1;
+            self.free.remove(index + 1);
+        }
+        if index > 0 && self.free[index - 1].0 + self.free[index - 1].1 == offset {
+            self.free[index - 1].1 += self.free[index].1;
+            self.free.remove(index);
+        }
+    }
+}
+
+/// Everything after `top` is free, all of it again once nothing is allocated any more.
+struct Linear {
+    size: vk::DeviceSize,
+    top: vk::DeviceSize,
+    allocations: usize,
+}
+
+impl Linear {
+    fn new(size: vk::DeviceSize) -> Self {
+        Linear {
+            size,
+            top: 0,
+            allocations: 0,
+        }
+    }
+
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let offset = align_up(self.top, alignment);
+        if offset + size > self.size {
+            return None;
+        }
+        self.top = offset + size;
+        self.allocations += 1;
+        Some(offset)
+    }
+
+    fn free(&mut self) {
+        self.allocations -= 1;
+        if self.allocations == 0 {
+            self.top = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use crate::vulkan::memory::{align_up, FreeList, Linear};
+
+    #[rstest(
+        value,
+        alignment,
+        expected,
+        case(0, 256, 0),
+        case(1, 256, 256),
+        case(256, 256, 256),
+        case(257, 256, 512),
+        case(13, 1, 13),
+        case(13, 0, 13)
+    )]
+    fn aligns_up(value: u64, alignment: u64, expected: u64) {
+        assert_eq!(align_up(value, alignment), expected);
+    }
+
+    #[test]
+    fn free_list_allocates_aligned_first_fit() {
+        let mut free_list = FreeList::new(1024);
+        assert_eq!(free_list.allocate(100, 1), Some(0));
+        assert_eq!(free_list.allocate(100, 256), Some(256));
+        assert_eq!(free_list.allocate(100, 1), Some(100)); // fits in front of the aligned one
+        assert_eq!(free_list.free, vec![(200, 56), (356, 668)]);
+    }
+
+    #[test]
+    fn free_list_runs_out_of_space() {
+        let mut free_list = FreeList::new(1024);
+        assert_eq!(free_list.allocate(1000, 1), Some(0));
+        assert_eq!(free_list.allocate(100, 1), None);
+        assert_eq!(free_list.allocate(24, 1), Some(1000));
+        assert_eq!(free_list.free, vec![]);
+    }
+
+    #[test]
+    fn free_list_merges_freed_neighbours() {
+        let mut free_list = FreeList::new(300);
+        let offsets: Vec<u64> = (0..3)
+            .map(|_| free_list.allocate(100, 1).unwrap())
+            .collect();
+        free_list.free(offsets[0], 100);
+        free_list.free(offsets[2], 100);
+        assert_eq!(free_list.free, vec![(0, 100), (200, 100)]);
+        free_list.free(offsets[1], 100);
+        assert_eq!(free_list.free, vec![(0, 300)]);
+        assert_eq!(free_list.allocate(300, 1), Some(0));
+    }
+
+    #[test]
+    fn linear_reuses_block_once_all_freed() {
+        let mut linear = Linear::new(1024);
+        assert_eq!(linear.allocate(100, 1), Some(0));
+        assert_eq!(linear.allocate(100, 64), Some(128));
+        linear.free();
+        assert_eq!(linear.allocate(1000, 1), None); // freed space isn't reused yet
+        linear.free();
+        assert_eq!(linear.allocate(1000, 1), Some(0));
+    }
+}
@@ -1,22 +1,47 @@
 use ash::{khr, vk};
+use image::RgbaImage;
 use memoffset::offset_of;
 
+use crate::bulb_mesh::{BulbInstanceData, BulbMesh};
 use crate::color_mesh::{ColorMesh, InstanceData};
+use crate::pbr_mesh::PbrMesh;
 use crate::scene::camera::Camera;
+use crate::scene::fog::Fog;
 use crate::scene::lights::Lights;
 use crate::scene::snow::{Snowflake, MAX_SNOWFLAKES};
+use crate::skybox::Skybox;
 use crate::textured_mesh::TexturedMesh;
+use crate::vulkan::cleanup::Cleanup;
 use crate::vulkan::compute_execution::VulkanComputeExecution;
 use crate::vulkan::compute_setup::VulkanComputeSetup;
 use crate::vulkan::core::VulkanCore;
-use crate::vulkan::graphics_execution::VulkanGraphicsExecution;
+use crate::vulkan::error::{Context, VulkanResult};
+use crate::vulkan::gpu::{Gpu, GpuSelection};
+use crate::vulkan::graphics_execution::{VulkanGraphicsExecution, MAX_FRAMES_IN_FLIGHT};
 use crate::vulkan::graphics_setup::VulkanGraphicsSetup;
+#[cfg(feature = "hot-reload")]
+use crate::vulkan::hot_reload::ShaderWatcher;
+use crate::vulkan::memory::MemoryHeapStats;
+use crate::vulkan::present_mode::PresentMode;
 
+mod cleanup;
 mod compute_execution;
 mod compute_setup;
 mod core;
+pub mod debug;
+pub mod error;
+pub mod gpu;
 mod graphics_execution;
 mod graphics_setup;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
+pub mod memory;
+mod msaa;
+mod pipeline_cache;
+pub mod present_mode;
+mod rendering;
+mod shaders;
+mod uploader;
 
 #[derive(Clone)]
 pub struct QueueFamilyIndices {
@@ -35,11 +60,11 @@ impl QueueFamilyIndices {
         }
     }
 
-    fn is_complete(&self) -> bool {
+    fn is_complete(&self, presenting: bool) -> bool {
         self.graphics_family.is_some()
             && self.compute_family.is_some()
             && self.transfer_family.is_some()
-            && self.present_family.is_some()
+            && (self.present_family.is_some() || !presenting)
     }
 }
 
@@ -90,58 +115,125 @@ pub struct Vulkan {
     compute_setup: VulkanComputeSetup,
     compute_execution: Option<VulkanComputeExecution>,
 
-    snow_calculated_semaphore: vk::Semaphore,
+    frustum: [[f32; 4]; 6], // snowflakes outside of it don't get drawn
+
+    #[cfg(feature = "hot-reload")]
+    shader_watcher: Option<ShaderWatcher>, // none if there's no compiler
 }
 
 impl Vulkan {
-    pub fn new(window: &winit::window::Window, application_name: &str) -> Self {
-        let (core, surface_composite) = VulkanCore::new(&window, application_name);
-        let graphics_setup = VulkanGraphicsSetup::new(core.clone(), surface_composite, &window);
-        let graphics_execution = VulkanGraphicsExecution::new(core.clone(), &graphics_setup);
-        let compute_setup = VulkanComputeSetup::new(core.clone());
+    pub fn new(
+        window: &winit::window::Window,
+        application_name: &str,
+        gpu_selection: Option<&GpuSelection>,
+    ) -> VulkanResult<Self> {
+        let (core, surface_composite) = VulkanCore::new(window, application_name, gpu_selection)?;
+        let window_size = window.inner_size();
+        // nothing left behind, so that another GPU can be tried with the same window
+        let graphics_setup = VulkanGraphicsSetup::new(
+            core.clone(),
+            surface_composite,
+            window_size.width,
+            window_size.height,
+        )
+        .inspect_err(|_| core.drop())?;
+        Vulkan::init(core, graphics_setup)
+    }
+
+    /// Renders without any window into an offscreen image, see `capture_next_frame`.
+    pub fn new_headless(
+        width: u32,
+        height: u32,
+        application_name: &str,
+        gpu_selection: Option<&GpuSelection>,
+    ) -> VulkanResult<Self> {
+        let core = VulkanCore::new_headless(application_name, gpu_selection)?;
+        let graphics_setup = VulkanGraphicsSetup::new_headless(core.clone(), width, height)
+            .inspect_err(|_| core.drop())?;
+        Vulkan::init(core, graphics_setup)
+    }
 
-        let snow_calculated_semaphore = core.create_semaphore();
+    /// All GPUs, in the order `GpuSelection::Index` refers to.
+    pub fn list_gpus(application_name: &str) -> VulkanResult<Vec<Gpu>> {
+        VulkanCore::list_gpus(application_name)
+    }
+
+    fn init(core: VulkanCore, graphics_setup: VulkanGraphicsSetup) -> VulkanResult<Self> {
+        let mut cleanup = Cleanup::new(&core);
+        cleanup.push(|core| core.drop());
+        cleanup.push(|_| graphics_setup.drop());
+        let mut graphics_execution = VulkanGraphicsExecution::new(core.clone(), &graphics_setup)?;
+        // snow for every frame in flight gets calculated on its own
+        let compute_setup = VulkanComputeSetup::new(core.clone(), MAX_FRAMES_IN_FLIGHT)
+            .inspect_err(|_| graphics_execution.drop())?;
+        cleanup.disarm();
 
-        Vulkan {
+        Ok(Vulkan {
             core,
             graphics_setup,
             graphics_execution,
             compute_setup,
             compute_execution: None,
 
-            snow_calculated_semaphore,
-        }
+            frustum: [[0.; 4]; 6],
+
+            #[cfg(feature = "hot-reload")]
+            shader_watcher: ShaderWatcher::new()
+                .map_err(|message| log::error!("{}, shaders won't get reloaded", message))
+                .ok(),
+        })
     }
 
     pub fn set_static_meshes(
         &mut self,
-        color_meshes: &Vec<ColorMesh>,
-        textured_meshes: &Vec<TexturedMesh>,
-    ) {
+        pbr_meshes: &[PbrMesh],
+        textured_meshes: &[TexturedMesh],
+        bulb_meshes: &[BulbMesh],
+    ) -> VulkanResult<()> {
         self.graphics_execution.set_static_meshes(
-            color_meshes,
+            pbr_meshes,
             textured_meshes,
+            bulb_meshes,
             &mut self.graphics_setup,
-        );
+        )
     }
 
-    pub fn set_snow_mesh(&mut self, snowflakes: &Vec<Snowflake>, meshes: &Vec<ColorMesh>) {
-        let (drawing_buffer, _buffer_memory) = self
-            .graphics_execution
-            .set_snow_mesh(meshes, &mut self.graphics_setup);
+    pub fn set_snow_mesh(
+        &mut self,
+        snowflakes: &[Snowflake],
+        meshes: &[ColorMesh],
+    ) -> VulkanResult<()> {
+        let snow_buffers = self.graphics_execution.set_snow_mesh(meshes)?;
 
         self.compute_execution = Some(VulkanComputeExecution::new(
             self.core.clone(),
             self.compute_setup.clone(),
             snowflakes,
-            drawing_buffer,
+            &snow_buffers,
             size_of::<InstanceData>() * MAX_SNOWFLAKES,
-        ));
+        )?);
+        Ok(())
+    }
+
+    pub fn set_fairy_lights_mesh(&mut self, mesh: &BulbMesh) -> VulkanResult<()> {
+        self.graphics_execution
+            .set_fairy_lights_mesh(mesh, &self.graphics_setup)
+    }
+
+    /// New brightness of the fairy lights, for the next drawn frame.
+    pub fn update_fairy_lights(&mut self, instances: &[BulbInstanceData]) {
+        self.graphics_execution.update_fairy_lights(instances);
     }
 
-    pub fn scene_complete(&mut self) {
+    /// Drawn where nothing else is, instead of the clear color.
+    pub fn set_skybox(&mut self, skybox: &Skybox) -> VulkanResult<()> {
         self.graphics_execution
-            .create_command_buffers(&self.graphics_setup);
+            .set_skybox(skybox, &self.graphics_setup)
+    }
+
+    pub fn scene_complete(&mut self) -> VulkanResult<()> {
+        self.graphics_execution
+            .create_command_buffers(&self.graphics_setup)
     }
 
     pub fn set_clear_value(&mut self, clear_value: [f32; 4]) {
@@ -149,22 +241,89 @@ impl Vulkan {
     }
 
     pub fn update_camera(&mut self, camera: &Camera) {
+        self.frustum = camera.frustum_planes().map(|plane| plane.into());
         self.graphics_execution
             .update_camera(camera, &self.graphics_setup);
     }
 
-    pub fn update_lights(&mut self, lights: &Lights) {
+    pub fn update_lights(&mut self, lights: &Lights) -> VulkanResult<()> {
         self.graphics_execution
-            .update_lights(lights, &self.graphics_setup);
+            .update_lights(lights, &self.graphics_setup)
     }
 
-    pub fn draw_frame(&mut self, last_frame_time_secs: f32) {
-        self.compute_execution
-            .as_mut()
-            .unwrap()
-            .do_calculations(self.snow_calculated_semaphore, last_frame_time_secs);
+    pub fn update_fog(&mut self, fog: &Fog) {
         self.graphics_execution
-            .draw_frame(&mut self.graphics_setup, self.snow_calculated_semaphore);
+            .update_fog(fog, &self.graphics_setup);
+    }
+
+    pub fn draw_frame(&mut self, last_frame_time_secs: f32) -> VulkanResult<()> {
+        let image_index = match self
+            .graphics_execution
+            .begin_frame(&mut self.graphics_setup)?
+        {
+            Some(image_index) => image_index,
+            None => return Ok(()),
+        };
+        // may overlap with drawing the previous frame, they write into different buffers
+        let (snow_calculated_semaphore, snow_calculated_value) =
+            self.compute_execution.as_mut().unwrap().do_calculations(
+                self.graphics_execution.current_frame(),
+                last_frame_time_secs,
+                self.frustum,
+            )?;
+        self.graphics_execution.draw_frame(
+            &mut self.graphics_setup,
+            image_index,
+            snow_calculated_semaphore,
+            snow_calculated_value,
+        )
+    }
+
+    /// Recompiles shaders edited since the last call and rebuilds pipelines using them. Shaders
+    /// that fail to compile or to build pipelines keep their last good version.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_changed_shaders(&mut self) -> VulkanResult<()> {
+        let changed = match self.shader_watcher.as_mut() {
+            Some(shader_watcher) => shader_watcher.recompile_changed(),
+            None => return Ok(()),
+        };
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let compute_changed = changed.iter().any(|(name, _)| name.ends_with(".comp"));
+        let graphics_changed = changed.iter().any(|(name, _)| !name.ends_with(".comp"));
+
+        // nothing gets replaced or torn down before all pipelines build with the new shaders
+        let mut trial_core = self.core.clone();
+        trial_core.shaders = self.core.shaders.with_replaced(&changed);
+        let mut tried = Ok(());
+        if graphics_changed {
+            tried = self.graphics_setup.try_pipelines(&trial_core);
+        }
+        if compute_changed && tried.is_ok() {
+            tried = self.compute_setup.try_pipeline(&trial_core);
+        }
+        if let Err(error) = tried {
+            log::error!("{}, keeping the previous shaders", error);
+            return Ok(());
+        }
+        for (name, spirv) in changed {
+            self.core.shaders.replace(&name, spirv);
+        }
+
+        // the same pipelines just got built, failing now isn't about the shaders
+        self.wait_device_idle()?;
+        if graphics_changed {
+            self.graphics_execution
+                .recreate_pipelines(&mut self.graphics_setup)?;
+        }
+        if compute_changed {
+            self.compute_setup.recreate_pipeline()?;
+            if let Some(compute_execution) = self.compute_execution.as_mut() {
+                compute_execution.set_compute_setup(self.compute_setup.clone());
+            }
+        }
+        Ok(())
     }
 
     fn cleanup_swapchain(&self) {
@@ -173,13 +332,77 @@ impl Vulkan {
         self.graphics_setup.cleanup_swapchain();
     }
 
-    pub fn wait_device_idle(&self) {
+    /// Anti-aliasing with at most that many samples per pixel, 1 turns it off. Returns how many
+    /// samples are actually used, that's up to the device.
+    pub fn set_msaa_samples(&mut self, max_samples: u32) -> VulkanResult<u32> {
+        let previous = self.graphics_setup.msaa_samples();
+        self.graphics_setup.set_msaa_samples(max_samples);
+        self.msaa_samples_changed(previous)
+    }
+
+    /// Twice as many samples per pixel, or anti-aliasing off after the highest supported count.
+    pub fn next_msaa_samples(&mut self) -> VulkanResult<u32> {
+        let previous = self.graphics_setup.msaa_samples();
+        self.graphics_setup.next_msaa_samples();
+        self.msaa_samples_changed(previous)
+    }
+
+    fn msaa_samples_changed(&mut self, previous: vk::SampleCountFlags) -> VulkanResult<u32> {
+        let current = self.graphics_setup.msaa_samples();
+        if current != previous {
+            self.wait_device_idle()?;
+            self.graphics_execution
+                .settings_changed(&mut self.graphics_setup)?;
+        }
+        Ok(current.as_raw())
+    }
+
+    /// Returns the one actually used, the closest one supported.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> VulkanResult<PresentMode> {
+        let previous = self.graphics_setup.present_mode();
+        self.graphics_setup.set_present_mode(present_mode)?;
+        self.present_mode_changed(previous)
+    }
+
+    /// Goes through all supported present modes.
+    pub fn next_present_mode(&mut self) -> VulkanResult<PresentMode> {
+        let previous = self.graphics_setup.present_mode();
+        self.graphics_setup.next_present_mode()?;
+        self.present_mode_changed(previous)
+    }
+
+    fn present_mode_changed(&mut self, previous: PresentMode) -> VulkanResult<PresentMode> {
+        let current = self.graphics_setup.present_mode();
+        if current != previous {
+            self.wait_device_idle()?;
+            self.graphics_execution
+                .settings_changed(&mut self.graphics_setup)?;
+        }
+        Ok(current)
+    }
+
+    /// Next drawn frame will be copied back, see `take_captured_frame`.
+    pub fn capture_next_frame(&mut self) -> VulkanResult<()> {
+        self.graphics_execution
+            .capture_next_frame(&self.graphics_setup)
+    }
+
+    pub fn take_captured_frame(&mut self) -> Option<RgbaImage> {
+        self.graphics_execution.take_captured_frame()
+    }
+
+    /// How much GPU memory is used, per heap.
+    pub fn memory_stats(&self) -> Vec<MemoryHeapStats> {
+        self.core.memory_stats()
+    }
+
+    pub fn wait_device_idle(&self) -> VulkanResult<()> {
         unsafe {
             self.core
                 .device
                 .device_wait_idle()
-                .expect("Failed to wait device idle!")
-        };
+                .context("Failed to wait device idle")
+        }
     }
 
     pub fn framebuffer_resized(&mut self, window_width: u32, window_height: u32) {
@@ -191,11 +414,8 @@ impl Vulkan {
 
 impl Drop for Vulkan {
     fn drop(&mut self) {
-        unsafe {
-            self.core
-                .device
-                .destroy_semaphore(self.snow_calculated_semaphore, None);
-        }
+        // whatever got compiled this time, including pipelines recreated with the swapchain
+        self.core.pipeline_cache.save(&self.core.device);
         if self.compute_execution.is_some() {
             self.compute_execution
                 .as_ref()
@@ -0,0 +1,86 @@
+use ash::vk;
+
+// more than that is barely noticeable, but costs a lot
+pub(crate) const DEFAULT_MAX_SAMPLES: u32 = 4;
+const MAX_SAMPLES: u32 = 8;
+
+/// The highest sample count the device supports, but not more than `max_samples`. One sample,
+/// meaning no MSAA at all, is always supported.
+pub(crate) fn highest_supported(
+    supported: vk::SampleCountFlags,
+    max_samples: u32,
+) -> vk::SampleCountFlags {
+    let max_samples = max_samples.clamp(1, MAX_SAMPLES);
+    let mut samples = max_samples.next_power_of_two();
+    if samples > max_samples {
+        samples /= 2;
+    }
+    while samples > 1 && !supported.contains(vk::SampleCountFlags::from_raw(samples)) {
+        samples /= 2;
+    }
+    vk::SampleCountFlags::from_raw(samples)
+}
+
+/// Twice as many samples, or back to no MSAA after the highest supported count.
+pub(crate) fn next_supported(
+    supported: vk::SampleCountFlags,
+    current: vk::SampleCountFlags,
+) -> vk::SampleCountFlags {
+    let next = current.as_raw() * 2;
+    if next <= MAX_SAMPLES && supported.contains(vk::SampleCountFlags::from_raw(next)) {
+        vk::SampleCountFlags::from_raw(next)
+    } else {
+        vk::SampleCountFlags::TYPE_1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use rstest::*;
+
+    use crate::vulkan::msaa::{highest_supported, next_supported};
+
+    const UP_TO_8: vk::SampleCountFlags = vk::SampleCountFlags::from_raw(0b1111);
+    const UP_TO_4: vk::SampleCountFlags = vk::SampleCountFlags::from_raw(0b0111);
+
+    #[rstest(
+        supported,
+        max_samples,
+        expected,
+        case(UP_TO_8, 4, 4),
+        case(UP_TO_8, 8, 8),
+        case(UP_TO_8, 16, 8),
+        case(UP_TO_8, 3, 2),
+        case(UP_TO_8, 1, 1),
+        case(UP_TO_8, 0, 1),
+        case(UP_TO_4, 8, 4),
+        case(vk::SampleCountFlags::TYPE_1, 4, 1)
+    )]
+    fn chooses_highest_supported_count(
+        supported: vk::SampleCountFlags,
+        max_samples: u32,
+        expected: u32,
+    ) {
+        assert_eq!(highest_supported(supported, max_samples).as_raw(), expected);
+    }
+
+    #[rstest(
+        supported,
+        current,
+        expected,
+        case(UP_TO_8, 1, 2),
+        case(UP_TO_8, 4, 8),
+        case(UP_TO_8, 8, 1),
+        case(UP_TO_4, 4, 1),
+        case(vk::SampleCountFlags::TYPE_1, 1, 1)
+    )]
+    fn cycles_through_supported_counts(
+        supported: vk::SampleCountFlags,
+        current: u32,
+        expected: u32,
+    ) {
+        let current = vk::SampleCountFlags::from_raw(current);
+        assert_eq!(next_supported(supported, current).as_raw(), expected);
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::PathBuf;
+
+use ash::vk;
+
+use crate::vulkan::error::{Context, VulkanResult};
+
+const CACHE_DIR: &str = "vulkan-christmas-tree";
+// VkPipelineCacheHeaderVersionOne, always little endian
+const HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+/// Pipelines compiled by the driver, kept on disk between runs. Caches from another device or
+/// driver version are useless, hence one file per both.
+#[derive(Clone)]
+pub(crate) struct PipelineCache {
+    pub cache: vk::PipelineCache,
+    path: Option<PathBuf>, // nowhere to save it
+}
+
+impl PipelineCache {
+    pub(crate) fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+    ) -> VulkanResult<Self> {
+        let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::default().push_next(&mut id_properties);
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut properties) };
+        let properties = properties.properties;
+
+        let path = cache_dir().map(|dir| {
+            dir.join(file_name(
+                &id_properties.device_uuid,
+                &id_properties.driver_uuid,
+            ))
+        });
+        // drivers should reject broken data themselves, not all of them do
+        let initial_data = path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .filter(|data| is_valid(data, &properties))
+            .unwrap_or_default();
+
+        let pipeline_cache_create_info = vk::PipelineCacheCreateInfo {
+            initial_data_size: initial_data.len(),
+            p_initial_data: initial_data.as_ptr() as *const std::ffi::c_void,
+            ..Default::default()
+        };
+        let cache = unsafe {
+            device
+                .create_pipeline_cache(&pipeline_cache_create_info, None)
+                .context("Failed to create Pipeline Cache")?
+        };
+
+        Ok(PipelineCache { cache, path })
+    }
+
+    /// Failing to save only makes the next start slower, hence no panic.
+    pub(crate) fn save(&self, device: &ash::Device) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let data = match unsafe { device.get_pipeline_cache_data(self.cache) } {
+            Ok(data) => data,
+            Err(error) => {
+                log::warn!("Failed to get pipeline cache data: {}", error);
+                return;
+            }
+        };
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(path, data));
+        if let Err(error) = written {
+            log::warn!(
+                "Failed to save pipeline cache to {}: {}",
+                path.display(),
+                error
+            );
+        }
+    }
+
+    pub(crate) fn drop(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline_cache(self.cache, None);
+        }
+    }
+}
+
+/// Per user, following each platform's conventions.
+fn cache_dir() -> Option<PathBuf> {
+    let env_dir = |name: &str| {
+        std::env::var_os(name)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = if cfg!(target_os = "windows") {
+        env_dir("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        env_dir("XDG_CACHE_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".cache")))
+    };
+    base.map(|dir| dir.join(CACHE_DIR))
+}
+
+fn file_name(device_uuid: &[u8; vk::UUID_SIZE], driver_uuid: &[u8; vk::UUID_SIZE]) -> String {
+    let hex = |uuid: &[u8; vk::UUID_SIZE]| {
+        uuid.iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+    };
+    format!("pipelines-{}-{}.bin", hex(device_uuid), hex(driver_uuid))
+}
+
+/// Whether the cache was created by the same device, and the same driver version of it.
+fn is_valid(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    read_u32(0) as usize >= HEADER_SIZE
+        && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && read_u32(8) == properties.vendor_id
+        && read_u32(12) == properties.device_id
+        && data[16..HEADER_SIZE] == properties.pipeline_cache_uuid
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use rstest::*;
+
+    use crate::vulkan::pipeline_cache::{file_name, is_valid};
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x2484,
+            pipeline_cache_uuid: [7; vk::UUID_SIZE],
+            ..Default::default()
+        }
+    }
+
+    fn header(version: u32, vendor_id: u32, device_id: u32, uuid: u8) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(32_u32.to_le_bytes());
+        data.extend(version.to_le_bytes());
+        data.extend(vendor_id.to_le_bytes());
+        data.extend(device_id.to_le_bytes());
+        data.extend([uuid; vk::UUID_SIZE]);
+        data.extend([1, 2, 3]); // whatever the driver keeps there
+        data
+    }
+
+    #[rstest(
+        data,
+        valid,
+        case(header(1, 0x10de, 0x2484, 7), true),
+        case(header(2, 0x10de, 0x2484, 7), false),
+        case(header(1, 0x1002, 0x2484, 7), false),
+        case(header(1, 0x10de, 0x1111, 7), false),
+        case(header(1, 0x10de, 0x2484, 8), false), // other driver version
+        case(header(1, 0x10de, 0x2484, 7)[..20].to_vec(), false),
+        case(vec![], false)
+    )]
+    fn accepts_only_caches_of_the_same_device_and_driver(data: Vec<u8>, valid: bool) {
+        assert_eq!(is_valid(&data, &properties()), valid);
+    }
+
+    #[test]
+    fn file_name_contains_both_uuids() {
+        let mut device_uuid = [0; vk::UUID_SIZE];
+        device_uuid[0] = 0xab;
+        let driver_uuid = [0x01; vk::UUID_SIZE];
+        assert_eq!(
+            file_name(&device_uuid, &driver_uuid),
+            "pipelines-ab000000000000000000000000000000-01010101010101010101010101010101.bin"
+        );
+    }
+}
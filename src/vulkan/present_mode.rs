@@ -0,0 +1,116 @@
+use ash::vk;
+
+/// How finished frames get to the screen, from vsync on to off.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Waits for the vertical blank, no tearing. Always supported.
+    #[default]
+    Fifo,
+    /// Like FIFO, but a late frame gets shown right away, tearing instead of waiting for the next
+    /// vertical blank.
+    FifoRelaxed,
+    /// No tearing, but frames rendered in the meantime replace the waiting one instead of
+    /// blocking, so the GPU never stops.
+    Mailbox,
+    /// Vsync off, frames get shown right away and tear.
+    Immediate,
+}
+
+impl PresentMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "on" => Some(PresentMode::Fifo),
+            "adaptive" => Some(PresentMode::FifoRelaxed),
+            "mailbox" => Some(PresentMode::Mailbox),
+            "off" => Some(PresentMode::Immediate),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PresentMode::Fifo => "on",
+            PresentMode::FifoRelaxed => "adaptive",
+            PresentMode::Mailbox => "mailbox",
+            PresentMode::Immediate => "off",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            PresentMode::Fifo => PresentMode::FifoRelaxed,
+            PresentMode::FifoRelaxed => PresentMode::Mailbox,
+            PresentMode::Mailbox => PresentMode::Immediate,
+            PresentMode::Immediate => PresentMode::Fifo,
+        }
+    }
+
+    /// This one if available, otherwise the closest one that is.
+    pub(crate) fn or_available(&self, available: &[vk::PresentModeKHR]) -> Self {
+        let fallbacks: &[PresentMode] = match self {
+            PresentMode::Fifo => &[],
+            PresentMode::FifoRelaxed => &[PresentMode::FifoRelaxed],
+            PresentMode::Mailbox => &[PresentMode::Mailbox, PresentMode::Immediate],
+            PresentMode::Immediate => &[PresentMode::Immediate, PresentMode::Mailbox],
+        };
+        fallbacks
+            .iter()
+            .find(|mode| available.contains(&mode.to_vk()))
+            .copied()
+            .unwrap_or(PresentMode::Fifo)
+    }
+
+    pub(crate) fn to_vk(self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use rstest::*;
+
+    use crate::vulkan::present_mode::PresentMode;
+
+    const ALL: [vk::PresentModeKHR; 4] = [
+        vk::PresentModeKHR::FIFO,
+        vk::PresentModeKHR::FIFO_RELAXED,
+        vk::PresentModeKHR::MAILBOX,
+        vk::PresentModeKHR::IMMEDIATE,
+    ];
+
+    #[rstest(
+        mode,
+        available,
+        expected,
+        case(PresentMode::Mailbox, &ALL, PresentMode::Mailbox),
+        case(PresentMode::Immediate, &ALL, PresentMode::Immediate),
+        case(PresentMode::Mailbox, &[vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE], PresentMode::Immediate),
+        case(PresentMode::Immediate, &[vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX], PresentMode::Mailbox),
+        case(PresentMode::FifoRelaxed, &[vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX], PresentMode::Fifo),
+        case(PresentMode::Immediate, &[vk::PresentModeKHR::FIFO], PresentMode::Fifo),
+        case(PresentMode::Fifo, &[], PresentMode::Fifo)
+    )]
+    fn falls_back_to_closest_available_mode(
+        mode: PresentMode,
+        available: &[vk::PresentModeKHR],
+        expected: PresentMode,
+    ) {
+        assert_eq!(mode.or_available(available), expected);
+    }
+
+    #[test]
+    fn names_get_parsed_back() {
+        let mut mode = PresentMode::default();
+        for _ in 0..4 {
+            assert_eq!(PresentMode::parse(mode.name()), Some(mode));
+            mode = mode.next();
+        }
+        assert_eq!(mode, PresentMode::default());
+    }
+}
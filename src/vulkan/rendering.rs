@@ -0,0 +1,300 @@
+use std::ffi::c_void;
+use std::ptr;
+
+use ash::{khr, vk};
+
+use crate::vulkan::core::VulkanCore;
+
+pub(crate) const DYNAMIC_RENDERING_EXTENSION: &str = "VK_KHR_dynamic_rendering";
+
+const ATTACHMENT_WRITES: vk::AccessFlags = vk::AccessFlags::from_raw(
+    vk::AccessFlags::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw(),
+);
+
+/// Rendering without any render pass or framebuffer objects, core since Vulkan 1.3.
+#[derive(Clone)]
+pub(crate) enum DynamicRendering {
+    Core,
+    Extension(khr::dynamic_rendering::Device),
+}
+
+impl DynamicRendering {
+    unsafe fn cmd_begin_rendering(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        rendering_info: &vk::RenderingInfo,
+    ) {
+        match self {
+            DynamicRendering::Core => device.cmd_begin_rendering(command_buffer, rendering_info),
+            DynamicRendering::Extension(loader) => {
+                loader.cmd_begin_rendering(command_buffer, rendering_info)
+            }
+        }
+    }
+
+    unsafe fn cmd_end_rendering(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        match self {
+            DynamicRendering::Core => device.cmd_end_rendering(command_buffer),
+            DynamicRendering::Extension(loader) => loader.cmd_end_rendering(command_buffer),
+        }
+    }
+}
+
+/// Everything a pass renders into. Render pass and framebuffer are null with dynamic rendering,
+/// the attachments are used instead.
+pub(crate) struct Pass {
+    pub render_pass: vk::RenderPass,
+    pub framebuffer: vk::Framebuffer,
+    pub extent: vk::Extent2D,
+    pub color: Option<Attachment>,
+    pub depth: Option<Attachment>,
+}
+
+pub(crate) struct Attachment {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub aspect_mask: vk::ImageAspectFlags,
+    pub layer: u32,
+    pub resolve: Option<(vk::Image, vk::ImageView)>, // multisampled color gets resolved into it
+    pub load_op: vk::AttachmentLoadOp,
+    pub store_op: vk::AttachmentStoreOp,
+    pub final_layout: vk::ImageLayout, // of the resolve image, if there's one
+}
+
+impl Attachment {
+    fn layout(&self) -> vk::ImageLayout {
+        if self.aspect_mask.contains(vk::ImageAspectFlags::COLOR) {
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        } else {
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        }
+    }
+
+    fn rendering_info(&self, clear_value: vk::ClearValue) -> vk::RenderingAttachmentInfo<'static> {
+        let (resolve_mode, resolve_image_view) = match self.resolve {
+            Some((_, view)) => (vk::ResolveModeFlags::AVERAGE, view),
+            None => (vk::ResolveModeFlags::NONE, vk::ImageView::null()),
+        };
+        vk::RenderingAttachmentInfo {
+            image_view: self.view,
+            image_layout: self.layout(),
+            resolve_mode,
+            resolve_image_view,
+            resolve_image_layout: self.layout(),
+            load_op: self.load_op,
+            store_op: self.store_op,
+            clear_value,
+            ..Default::default()
+        }
+    }
+
+    fn barrier(
+        &self,
+        image: vk::Image,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        dst_access_mask: vk::AccessFlags,
+    ) -> vk::ImageMemoryBarrier<'static> {
+        vk::ImageMemoryBarrier {
+            src_access_mask: ATTACHMENT_WRITES,
+            dst_access_mask,
+            old_layout,
+            new_layout,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: self.aspect_mask,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: self.layer,
+                layer_count: 1,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Clear values go in the same order as with render passes, color first.
+pub(crate) fn begin_pass(
+    core: &VulkanCore,
+    command_buffer: vk::CommandBuffer,
+    pass: &Pass,
+    clear_values: &[vk::ClearValue],
+) {
+    let device = &core.device;
+    let dynamic_rendering = match &core.dynamic_rendering {
+        Some(dynamic_rendering) => dynamic_rendering,
+        None => {
+            let render_pass_begin_info = vk::RenderPassBeginInfo {
+                render_pass: pass.render_pass,
+                framebuffer: pass.framebuffer,
+                render_area: vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent: pass.extent,
+                },
+                clear_value_count: clear_values.len() as u32,
+                p_clear_values: clear_values.as_ptr(),
+                ..Default::default()
+            };
+            unsafe {
+                device.cmd_begin_render_pass(
+                    command_buffer,
+                    &render_pass_begin_info,
+                    vk::SubpassContents::INLINE,
+                );
+            }
+            return;
+        }
+    };
+
+    // previous contents are never needed, whoever used the images before has to be done first
+    let attachment_access = vk::AccessFlags::COLOR_ATTACHMENT_READ
+        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+    let mut barriers = vec![];
+    for attachment in pass.color.iter().chain(pass.depth.iter()) {
+        barriers.push(attachment.barrier(
+            attachment.image,
+            vk::ImageLayout::UNDEFINED,
+            attachment.layout(),
+            attachment_access,
+        ));
+        if let Some((resolve_image, _)) = attachment.resolve {
+            barriers.push(attachment.barrier(
+                resolve_image,
+                vk::ImageLayout::UNDEFINED,
+                attachment.layout(),
+                attachment_access,
+            ));
+        }
+    }
+
+    let mut clear_values = clear_values.iter().copied();
+    let color_attachments: Vec<vk::RenderingAttachmentInfo> = pass
+        .color
+        .iter()
+        .map(|color| color.rendering_info(clear_values.next().unwrap_or_default()))
+        .collect();
+    let depth_attachment = pass
+        .depth
+        .as_ref()
+        .map(|depth| depth.rendering_info(clear_values.next().unwrap_or_default()));
+    let rendering_info = vk::RenderingInfo {
+        render_area: vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: pass.extent,
+        },
+        layer_count: 1,
+        color_attachment_count: color_attachments.len() as u32,
+        p_color_attachments: color_attachments.as_ptr(),
+        p_depth_attachment: depth_attachment
+            .as_ref()
+            .map_or(ptr::null(), |depth_attachment| depth_attachment),
+        ..Default::default()
+    };
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::FRAGMENT_SHADER
+                | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &barriers,
+        );
+        dynamic_rendering.cmd_begin_rendering(device, command_buffer, &rendering_info);
+    }
+}
+
+pub(crate) fn end_pass(core: &VulkanCore, command_buffer: vk::CommandBuffer, pass: &Pass) {
+    let device = &core.device;
+    let dynamic_rendering = match &core.dynamic_rendering {
+        Some(dynamic_rendering) => dynamic_rendering,
+        None => {
+            unsafe { device.cmd_end_render_pass(command_buffer) };
+            return;
+        }
+    };
+
+    // what render passes do on their own, getting images ready for whoever uses them next
+    let barriers: Vec<vk::ImageMemoryBarrier> = pass
+        .color
+        .iter()
+        .chain(pass.depth.iter())
+        .map(|attachment| {
+            let image = attachment
+                .resolve
+                .map_or(attachment.image, |(resolve_image, _)| resolve_image);
+            attachment.barrier(
+                image,
+                attachment.layout(),
+                attachment.final_layout,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ,
+            )
+        })
+        .filter(|barrier| barrier.old_layout != barrier.new_layout)
+        .collect();
+
+    unsafe {
+        dynamic_rendering.cmd_end_rendering(device, command_buffer);
+        if !barriers.is_empty() {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &barriers,
+            );
+        }
+    }
+}
+
+/// Attachment formats, what pipelines need to know when there's no render pass to tell them.
+pub(crate) fn pipeline_rendering_create_info(
+    color_formats: &[vk::Format],
+    depth_format: vk::Format,
+) -> vk::PipelineRenderingCreateInfo<'_> {
+    vk::PipelineRenderingCreateInfo {
+        color_attachment_count: color_formats.len() as u32,
+        p_color_attachment_formats: color_formats.as_ptr(),
+        depth_attachment_format: depth_format,
+        ..Default::default()
+    }
+}
+
+/// For chaining into a pipeline create info, with a render pass there's nothing to chain.
+pub(crate) fn pipeline_create_info_next(
+    render_pass: vk::RenderPass,
+    pipeline_rendering_create_info: &vk::PipelineRenderingCreateInfo,
+) -> *const c_void {
+    if render_pass == vk::RenderPass::null() {
+        pipeline_rendering_create_info as *const vk::PipelineRenderingCreateInfo as *const c_void
+    } else {
+        ptr::null()
+    }
+}
+
+/// Depth formats with stencil need both aspects in barriers.
+pub(crate) fn depth_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::DEPTH,
+    }
+}
@@ -0,0 +1,130 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// compiled by build.rs, named after their sources in src/shaders
+const EMBEDDED: [(&str, &[u8]); 16] = [
+    (
+        "color.vert",
+        include_bytes!("../../target/shaders/color.vert.spv"),
+    ),
+    (
+        "color.frag",
+        include_bytes!("../../target/shaders/color.frag.spv"),
+    ),
+    (
+        "pbr.vert",
+        include_bytes!("../../target/shaders/pbr.vert.spv"),
+    ),
+    (
+        "pbr.frag",
+        include_bytes!("../../target/shaders/pbr.frag.spv"),
+    ),
+    (
+        "textured.vert",
+        include_bytes!("../../target/shaders/textured.vert.spv"),
+    ),
+    (
+        "textured.frag",
+        include_bytes!("../../target/shaders/textured.frag.spv"),
+    ),
+    (
+        "shadow.vert",
+        include_bytes!("../../target/shaders/shadow.vert.spv"),
+    ),
+    (
+        "bulb.vert",
+        include_bytes!("../../target/shaders/bulb.vert.spv"),
+    ),
+    (
+        "bulb.frag",
+        include_bytes!("../../target/shaders/bulb.frag.spv"),
+    ),
+    (
+        "skybox.vert",
+        include_bytes!("../../target/shaders/skybox.vert.spv"),
+    ),
+    (
+        "skybox.frag",
+        include_bytes!("../../target/shaders/skybox.frag.spv"),
+    ),
+    (
+        "fullscreen.vert",
+        include_bytes!("../../target/shaders/fullscreen.vert.spv"),
+    ),
+    (
+        "bright_pass.frag",
+        include_bytes!("../../target/shaders/bright_pass.frag.spv"),
+    ),
+    (
+        "blur.frag",
+        include_bytes!("../../target/shaders/blur.frag.spv"),
+    ),
+    (
+        "tonemap.frag",
+        include_bytes!("../../target/shaders/tonemap.frag.spv"),
+    ),
+    (
+        "snow.comp",
+        include_bytes!("../../target/shaders/snow.comp.spv"),
+    ),
+];
+
+/// SPIR-V of all the shaders. All clones share the same code, so that shaders replaced at runtime
+/// get picked up by every pipeline built afterwards.
+#[derive(Clone)]
+pub(crate) struct Shaders {
+    spirv: Rc<RefCell<HashMap<&'static str, Vec<u8>>>>,
+}
+
+impl Shaders {
+    pub(crate) fn embedded() -> Self {
+        let spirv = EMBEDDED
+            .iter()
+            .map(|&(name, spirv)| (name, spirv.to_vec()))
+            .collect();
+        Shaders {
+            spirv: Rc::new(RefCell::new(spirv)),
+        }
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Vec<u8> {
+        match self.spirv.borrow().get(name) {
+            Some(spirv) => spirv.clone(),
+            None => panic!("Unknown shader {}", name),
+        }
+    }
+
+    #[cfg(feature = "hot-reload")]
+    pub(crate) fn replace(&self, name: &str, spirv: Vec<u8>) {
+        if let Some(code) = self.spirv.borrow_mut().get_mut(name) {
+            *code = spirv;
+        }
+    }
+
+    /// Copy not shared with any other, with some shaders replaced, e.g. to try them out.
+    #[cfg(feature = "hot-reload")]
+    pub(crate) fn with_replaced(&self, changed: &[(String, Vec<u8>)]) -> Self {
+        let copy = Shaders {
+            spirv: Rc::new(RefCell::new(self.spirv.borrow().clone())),
+        };
+        for (name, spirv) in changed {
+            copy.replace(name, spirv.clone());
+        }
+        copy
+    }
+}
+
+#[cfg(all(test, feature = "hot-reload"))]
+mod tests {
+    use crate::vulkan::shaders::Shaders;
+
+    #[test]
+    fn replaced_copy_leaves_original_alone() {
+        let shaders = Shaders::embedded();
+        let original = shaders.get("snow.comp");
+        let copy = shaders.with_replaced(&[("snow.comp".to_string(), vec![1, 2, 3])]);
+        assert_eq!(copy.get("snow.comp"), vec![1, 2, 3]);
+        assert_eq!(shaders.get("snow.comp"), original);
+    }
+}
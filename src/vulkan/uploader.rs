@@ -0,0 +1,698 @@
+use ash::vk;
+use image::RgbaImage;
+
+use crate::vulkan::cleanup::Cleanup;
+use crate::vulkan::core::VulkanCore;
+use crate::vulkan::error::{Context, VulkanResult};
+use crate::vulkan::memory::Allocation;
+
+// bigger batches get submitted right away, so that the first assets show up early
+const MAX_BATCH_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// Copies data into device local buffers and images on the transfer queue, without waiting for the
+/// copies to finish. They're collected into batches, `flush` submits the current one. Whatever got
+/// uploaded can be used once its batch is done, see `uploaded_batches`.
+pub(crate) struct Uploader {
+    core: VulkanCore,
+    transfer_command_pool: vk::CommandPool,
+    graphics_command_pool: vk::CommandPool, // takes the uploads over and finishes them
+    // batch n signals 2n - 1 once copied and 2n once the graphics queue is done with it too
+    timeline_semaphore: vk::Semaphore,
+    batch: Batch,
+    in_flight: Vec<Batch>,
+    flushed_batches: u64,
+}
+
+#[derive(Default)]
+struct Batch {
+    number: u64,
+    transfer_command_buffer: vk::CommandBuffer, // both null until something gets uploaded
+    graphics_command_buffer: vk::CommandBuffer,
+    staging_buffers: Vec<(vk::Buffer, Allocation)>,
+    size: vk::DeviceSize,
+}
+
+impl Uploader {
+    pub(crate) fn new(core: VulkanCore) -> VulkanResult<Self> {
+        let mut cleanup = Cleanup::new(&core);
+        let transfer_command_pool = core.create_command_pool(
+            core.queue_family.transfer_family.unwrap(),
+            vk::CommandPoolCreateFlags::TRANSIENT,
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_command_pool(transfer_command_pool, None)
+        });
+        let graphics_command_pool = core.create_command_pool(
+            core.queue_family.graphics_family.unwrap(),
+            vk::CommandPoolCreateFlags::TRANSIENT,
+        )?;
+        cleanup.push(move |core| unsafe {
+            core.device
+                .destroy_command_pool(graphics_command_pool, None)
+        });
+        let timeline_semaphore = core.create_timeline_semaphore()?;
+        cleanup.disarm();
+        Ok(Uploader {
+            core,
+            transfer_command_pool,
+            graphics_command_pool,
+            timeline_semaphore,
+            batch: Batch {
+                number: 1,
+                ..Default::default()
+            },
+            in_flight: vec![],
+            flushed_batches: 0,
+        })
+    }
+
+    /// The batch uploads go into right now.
+    pub(crate) fn current_batch(&self) -> u64 {
+        self.batch.number
+    }
+
+    /// How many batches are done, in order, so everything uploaded in them can be used.
+    pub(crate) fn uploaded_batches(&mut self) -> VulkanResult<u64> {
+        let value = unsafe {
+            self.core
+                .device
+                .get_semaphore_counter_value(self.timeline_semaphore)
+                .context("Failed to get timeline Semaphore value")?
+        };
+        let uploaded_batches = value / 2;
+
+        let (done, in_flight): (Vec<Batch>, Vec<Batch>) = self
+            .in_flight
+            .drain(..)
+            .partition(|batch| batch.number <= uploaded_batches);
+        self.in_flight = in_flight;
+        for batch in done {
+            self.free(batch);
+        }
+        Ok(uploaded_batches)
+    }
+
+    /// Blocks until all the batches flushed so far are done.
+    pub(crate) fn wait_for_flushed(&mut self) -> VulkanResult<()> {
+        let semaphores = [self.timeline_semaphore];
+        let values = [self.flushed_batches * 2];
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+        unsafe {
+            self.core
+                .device
+                .wait_semaphores(&wait_info, u64::MAX)
+                .context("Failed to wait for uploads")?;
+        }
+        self.uploaded_batches()?;
+        Ok(())
+    }
+
+    /// Device local buffer with the data in it, once the current batch is done.
+    pub(crate) fn upload_buffer<T>(
+        &mut self,
+        usage: vk::BufferUsageFlags,
+        data: &[T],
+        name: &str,
+    ) -> VulkanResult<(vk::Buffer, Allocation)> {
+        let size = size_of_val(data) as vk::DeviceSize;
+        let bytes =
+            unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, size as usize) };
+        let staging_buffer = self.create_staging_buffer(&[bytes])?;
+        let (buffer, buffer_memory) = self.core.create_buffer(
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            name,
+        )?;
+
+        let (src_queue_family_index, dst_queue_family_index) = self.ownership_transfer();
+        let barrier = vk::BufferMemoryBarrier {
+            src_queue_family_index,
+            dst_queue_family_index,
+            buffer,
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+            ..Default::default()
+        };
+        let device = &self.core.device;
+        unsafe {
+            device.cmd_copy_buffer(
+                self.batch.transfer_command_buffer,
+                staging_buffer,
+                buffer,
+                &[vk::BufferCopy {
+                    src_offset: 0,
+                    dst_offset: 0,
+                    size,
+                }],
+            );
+            if src_queue_family_index != dst_queue_family_index {
+                // released here, acquired with the same barrier on the graphics queue
+                device.cmd_pipeline_barrier(
+                    self.batch.transfer_command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[vk::BufferMemoryBarrier {
+                        src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                        ..barrier
+                    }],
+                    &[],
+                );
+            }
+            device.cmd_pipeline_barrier(
+                self.batch.graphics_command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[vk::BufferMemoryBarrier {
+                    dst_access_mask: vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+                    ..barrier
+                }],
+                &[],
+            );
+        }
+
+        Ok((buffer, buffer_memory))
+    }
+
+    /// Fills the image with tightly packed images, one per mip level starting from 0, or one per
+    /// array layer if layered. With just level 0 given, the others get blitted from it. Ends up in
+    /// SHADER_READ_ONLY_OPTIMAL, once the current batch is done.
+    pub(crate) fn upload_image(
+        &mut self,
+        image: vk::Image,
+        images: &[RgbaImage],
+        layered: bool,
+        mip_levels: u32,
+    ) -> VulkanResult<()> {
+        let chunks: Vec<&[u8]> = images
+            .iter()
+            .map(|image| image.as_raw().as_slice())
+            .collect();
+        let staging_buffer = self.create_staging_buffer(&chunks)?;
+        let layer_count = if layered { images.len() as u32 } else { 1 };
+        let blit_mips = !layered && (images.len() as u32) < mip_levels;
+        // blits need a graphics queue, the image stays a blit destination until it gets there
+        let uploaded_layout = if blit_mips {
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL
+        } else {
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        };
+
+        let (src_queue_family_index, dst_queue_family_index) = self.ownership_transfer();
+        let barrier = vk::ImageMemoryBarrier {
+            old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            new_layout: uploaded_layout,
+            src_queue_family_index,
+            dst_queue_family_index,
+            image,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: mip_levels,
+                base_array_layer: 0,
+                layer_count,
+            },
+            ..Default::default()
+        };
+        let device = &self.core.device;
+        unsafe {
+            device.cmd_pipeline_barrier(
+                self.batch.transfer_command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier {
+                    dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    ..barrier
+                }],
+            );
+            device.cmd_copy_buffer_to_image(
+                self.batch.transfer_command_buffer,
+                staging_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &copy_regions(images, layered),
+            );
+            if src_queue_family_index != dst_queue_family_index {
+                // released here, acquired with the same barrier on the graphics queue
+                device.cmd_pipeline_barrier(
+                    self.batch.transfer_command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[vk::ImageMemoryBarrier {
+                        src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                        ..barrier
+                    }],
+                );
+            }
+            device.cmd_pipeline_barrier(
+                self.batch.graphics_command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier {
+                    dst_access_mask: vk::AccessFlags::SHADER_READ
+                        | vk::AccessFlags::TRANSFER_READ
+                        | vk::AccessFlags::TRANSFER_WRITE,
+                    ..barrier
+                }],
+            );
+        }
+
+        if blit_mips {
+            record_mipmaps(
+                device,
+                self.batch.graphics_command_buffer,
+                image,
+                images[0].width(),
+                images[0].height(),
+                mip_levels,
+            );
+        }
+        Ok(())
+    }
+
+    /// Submits everything uploaded since the last flush and returns its batch.
+    pub(crate) fn flush(&mut self) -> VulkanResult<u64> {
+        if self.batch.transfer_command_buffer == vk::CommandBuffer::null() {
+            return Ok(self.flushed_batches); // nothing to wait for
+        }
+
+        let device = &self.core.device;
+        let number = self.batch.number;
+        unsafe {
+            device
+                .end_command_buffer(self.batch.transfer_command_buffer)
+                .context("Failed to end Command Buffer")?;
+            device
+                .end_command_buffer(self.batch.graphics_command_buffer)
+                .context("Failed to end Command Buffer")?;
+        }
+
+        let copied_values = [number * 2 - 1];
+        let transfer_command_buffers = [self.batch.transfer_command_buffer];
+        let signal_semaphores = [self.timeline_semaphore];
+        let mut copied_submit_info =
+            vk::TimelineSemaphoreSubmitInfo::default().signal_semaphore_values(&copied_values);
+        let transfer_submit_info = vk::SubmitInfo::default()
+            .command_buffers(&transfer_command_buffers)
+            .signal_semaphores(&signal_semaphores)
+            .push_next(&mut copied_submit_info);
+
+        let done_values = [number * 2];
+        let graphics_command_buffers = [self.batch.graphics_command_buffer];
+        let wait_stages = [vk::PipelineStageFlags::ALL_COMMANDS];
+        let mut done_submit_info = vk::TimelineSemaphoreSubmitInfo::default()
+            .wait_semaphore_values(&copied_values)
+            .signal_semaphore_values(&done_values);
+        let graphics_submit_info = vk::SubmitInfo::default()
+            .wait_semaphores(&signal_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(&graphics_command_buffers)
+            .signal_semaphores(&signal_semaphores)
+            .push_next(&mut done_submit_info);
+
+        unsafe {
+            device
+                .queue_submit(
+                    self.core.transfer_queue,
+                    &[transfer_submit_info],
+                    vk::Fence::null(),
+                )
+                .context("Failed to submit uploads")?;
+            device
+                .queue_submit(
+                    self.core.graphics_queue,
+                    &[graphics_submit_info],
+                    vk::Fence::null(),
+                )
+                .context("Failed to submit uploads")?;
+        }
+
+        let next_batch = Batch {
+            number: number + 1,
+            ..Default::default()
+        };
+        self.in_flight
+            .push(std::mem::replace(&mut self.batch, next_batch));
+        self.flushed_batches = number;
+        Ok(number)
+    }
+
+    /// Source and destination queue family of the barriers handing uploads over to the graphics
+    /// queue.
+    fn ownership_transfer(&self) -> (u32, u32) {
+        ownership_transfer(
+            self.core.queue_family.transfer_family.unwrap(),
+            self.core.queue_family.graphics_family.unwrap(),
+        )
+    }
+
+    /// Host-visible buffer with all the chunks tightly packed one after another, freed once the
+    /// current batch is done. Starts recording the batch if it's the first upload in it.
+    fn create_staging_buffer(&mut self, chunks: &[&[u8]]) -> VulkanResult<vk::Buffer> {
+        let buffer_size: usize = chunks.iter().map(|chunk| chunk.len()).sum();
+        if self.batch.size > 0 && self.batch.size + buffer_size as vk::DeviceSize > MAX_BATCH_SIZE {
+            self.flush()?;
+        }
+        if self.batch.transfer_command_buffer == vk::CommandBuffer::null() {
+            self.batch.transfer_command_buffer =
+                self.begin_command_buffer(self.transfer_command_pool, "upload transfer commands")?;
+            self.batch.graphics_command_buffer =
+                self.begin_command_buffer(self.graphics_command_pool, "upload graphics commands")?;
+        }
+
+        let (staging_buffer, staging_buffer_memory) = self
+            .core
+            .create_staging_buffer(buffer_size as vk::DeviceSize)?;
+
+        unsafe {
+            let data_ptr = staging_buffer_memory.mapped_ptr::<u8>();
+            let mut offset = 0;
+            for chunk in chunks.iter() {
+                data_ptr
+                    .add(offset)
+                    .copy_from_nonoverlapping(chunk.as_ptr(), chunk.len());
+                offset += chunk.len();
+            }
+        }
+
+        self.batch
+            .staging_buffers
+            .push((staging_buffer, staging_buffer_memory));
+        self.batch.size += buffer_size as vk::DeviceSize;
+        Ok(staging_buffer)
+    }
+
+    fn begin_command_buffer(
+        &self,
+        command_pool: vk::CommandPool,
+        name: &str,
+    ) -> VulkanResult<vk::CommandBuffer> {
+        let allocate_info = vk::CommandBufferAllocateInfo {
+            command_buffer_count: 1,
+            command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+            ..Default::default()
+        };
+        let begin_info = vk::CommandBufferBeginInfo {
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            ..Default::default()
+        };
+        unsafe {
+            let command_buffer = self
+                .core
+                .device
+                .allocate_command_buffers(&allocate_info)
+                .context("Failed to allocate Command Buffer")?[0];
+            self.core.debug_utils.name(command_buffer, name);
+            self.core
+                .device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .context("Failed to begin Command Buffer")?;
+            Ok(command_buffer)
+        }
+    }
+
+    fn free(&self, batch: Batch) {
+        let device = &self.core.device;
+        unsafe {
+            if batch.transfer_command_buffer != vk::CommandBuffer::null() {
+                device.free_command_buffers(
+                    self.transfer_command_pool,
+                    &[batch.transfer_command_buffer],
+                );
+                device.free_command_buffers(
+                    self.graphics_command_pool,
+                    &[batch.graphics_command_buffer],
+                );
+            }
+            for (buffer, buffer_memory) in batch.staging_buffers {
+                device.destroy_buffer(buffer, None);
+                self.core.free_memory(buffer_memory);
+            }
+        }
+    }
+
+    /// The device has to be idle.
+    pub(crate) fn drop(&mut self) {
+        // never flushed ones are only recorded, they can be freed too
+        let current = std::mem::take(&mut self.batch);
+        self.free(current);
+        for batch in std::mem::take(&mut self.in_flight) {
+            self.free(batch);
+        }
+        unsafe {
+            let device = &self.core.device;
+            device.destroy_semaphore(self.timeline_semaphore, None);
+            device.destroy_command_pool(self.graphics_command_pool, None);
+            device.destroy_command_pool(self.transfer_command_pool, None);
+        }
+    }
+}
+
+/// Queue family indices for barriers between the two families, ignored if they're the same one and
+/// there's no ownership to transfer.
+fn ownership_transfer(src_queue_family: u32, dst_queue_family: u32) -> (u32, u32) {
+    if src_queue_family == dst_queue_family {
+        (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+    } else {
+        (src_queue_family, dst_queue_family)
+    }
+}
+
+/// One region per image, going into consecutive mip levels or array layers.
+fn copy_regions(images: &[RgbaImage], layered: bool) -> Vec<vk::BufferImageCopy> {
+    let mut buffer_offset = 0;
+    images
+        .iter()
+        .enumerate()
+        .map(|(i, data)| {
+            let (mip_level, base_array_layer) = if layered { (0, i) } else { (i, 0) };
+            let region = vk::BufferImageCopy {
+                buffer_offset,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: mip_level as u32,
+                    base_array_layer: base_array_layer as u32,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: vk::Extent3D {
+                    width: data.width(),
+                    height: data.height(),
+                    depth: 1,
+                },
+            };
+            buffer_offset += data.len() as vk::DeviceSize;
+            region
+        })
+        .collect()
+}
+
+/// Expects all levels in TRANSFER_DST_OPTIMAL with level 0 filled in, leaves all of them in
+/// SHADER_READ_ONLY_OPTIMAL.
+fn record_mipmaps(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    width: u32,
+    height: u32,
+    mip_levels: u32,
+) {
+    let mut barrier = vk::ImageMemoryBarrier {
+        image,
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        },
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        ..Default::default()
+    };
+
+    let mut mip_width = width as i32;
+    let mut mip_height = height as i32;
+    for level in 1..mip_levels {
+        // previous level has just been written, now it's the blit source
+        barrier.subresource_range.base_mip_level = level - 1;
+        barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+        barrier.new_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+        barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
+        barrier.dst_access_mask = vk::AccessFlags::TRANSFER_READ;
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            );
+        }
+
+        let next_width = (mip_width / 2).max(1);
+        let next_height = (mip_height / 2).max(1);
+        let blit = vk::ImageBlit {
+            src_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: level - 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            src_offsets: [
+                vk::Offset3D { x: 0, y: 0, z: 0 },
+                vk::Offset3D {
+                    x: mip_width,
+                    y: mip_height,
+                    z: 1,
+                },
+            ],
+            dst_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: level,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            dst_offsets: [
+                vk::Offset3D { x: 0, y: 0, z: 0 },
+                vk::Offset3D {
+                    x: next_width,
+                    y: next_height,
+                    z: 1,
+                },
+            ],
+        };
+        unsafe {
+            device.cmd_blit_image(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::LINEAR,
+            );
+        }
+
+        // done with the previous level
+        barrier.old_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+        barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        barrier.src_access_mask = vk::AccessFlags::TRANSFER_READ;
+        barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            );
+        }
+
+        mip_width = next_width;
+        mip_height = next_height;
+    }
+
+    // the last level is never a blit source
+    barrier.subresource_range.base_mip_level = mip_levels - 1;
+    barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+    barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+    barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
+    barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use image::RgbaImage;
+    use rstest::*;
+
+    use crate::vulkan::uploader::{copy_regions, ownership_transfer};
+
+    #[rstest(
+        src,
+        dst,
+        expected,
+        case(0, 0, (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)),
+        case(2, 0, (2, 0)),
+        case(0, 1, (0, 1))
+    )]
+    fn transfers_ownership_between_different_families_only(
+        src: u32,
+        dst: u32,
+        expected: (u32, u32),
+    ) {
+        assert_eq!(ownership_transfer(src, dst), expected);
+    }
+
+    #[test]
+    fn mip_levels_follow_each_other_in_the_buffer() {
+        let mips = [
+            RgbaImage::new(4, 2),
+            RgbaImage::new(2, 1),
+            RgbaImage::new(1, 1),
+        ];
+        let regions = copy_regions(&mips, false);
+        let offsets: Vec<vk::DeviceSize> = regions.iter().map(|r| r.buffer_offset).collect();
+        let levels: Vec<u32> = regions
+            .iter()
+            .map(|r| r.image_subresource.mip_level)
+            .collect();
+        assert_eq!(offsets, vec![0, 32, 40]);
+        assert_eq!(levels, vec![0, 1, 2]);
+        assert!(regions
+            .iter()
+            .all(|r| r.image_subresource.base_array_layer == 0));
+    }
+
+    #[test]
+    fn layers_follow_each_other_in_the_buffer() {
+        let faces = vec![RgbaImage::new(2, 2); 6];
+        let regions = copy_regions(&faces, true);
+        let layers: Vec<u32> = regions
+            .iter()
+            .map(|r| r.image_subresource.base_array_layer)
+            .collect();
+        assert_eq!(layers, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(regions[5].buffer_offset, 5 * 16);
+        assert!(regions.iter().all(|r| r.image_subresource.mip_level == 0));
+    }
+}